@@ -1,14 +1,75 @@
 use super::util::FunctionLikeHosted;
-use crate::builtins::util::AsDefFunction;
+use crate::{
+  builtins::{util::AsDefFunction, IO_DENIED_MSG},
+  net::net_to_hvmc::net_to_hvmc,
+  term::{
+    term_to_net::{term_to_compat_net, Labels},
+    Term,
+  },
+};
 use hvmc::{
+  ast,
   host::Host,
-  run::{Def, LabSet, Mode, Net, Port, Tag, Wire},
-  stdlib::HostedDef,
+  run::{Def, LabSet, Mode, Net, Port, Tag, Trg, Wire},
+  stdlib::{ArcDef, AsArcDef, HostedDef},
 };
 use parking_lot::Mutex;
 use std::sync::Arc;
 
-pub(crate) fn add_exit_def(host: Arc<Mutex<Host>>) {
+/// The status code that `HVM.exit` was called with, shared between the host and whoever runs it.
+///
+/// Calling `HVM.exit` doesn't terminate the process, it stops the reduction of the program once its status
+/// code is known, so nothing else is performed after it. It's up to the caller to decide what to do with the
+/// status code. Only the thread that called it can be stopped, so programs that use it are run on a single
+/// core, see [`uses_exit`].
+#[derive(Clone, Debug, Default)]
+pub struct ExitStatus(Arc<Mutex<Option<i32>>>);
+
+impl ExitStatus {
+  /// The status code of the first call to `HVM.exit`, if there was one.
+  pub fn get(&self) -> Option<i32> {
+    *self.0.lock()
+  }
+
+  /// Takes the status code out, so that the host can be run again.
+  pub fn take(&self) -> Option<i32> {
+    self.0.lock().take()
+  }
+
+  /// Whether `HVM.exit` was called, and the reduction must stop.
+  pub fn has_exited(&self) -> bool {
+    self.0.lock().is_some()
+  }
+
+  /// Stores the status code and drops the pending redexes of the net, aborting its reduction.
+  fn exit<M: Mode>(&self, net: &mut Net<M>, port: &Port) {
+    let code = match port.tag() {
+      Tag::Num => port.num().try_into().unwrap_or(-1),
+      _ => -1,
+    };
+    self.0.lock().get_or_insert(code);
+    drop(std::mem::take(&mut net.redexes));
+  }
+}
+
+/// Checks if any net of an hvm-core book refers to `HVM.exit`.
+pub fn uses_exit(book: &ast::Book) -> bool {
+  book.values().any(|net| {
+    let mut visit = vec![&net.root];
+    visit.extend(net.redexes.iter().flat_map(|(l, r)| [l, r]));
+    while let Some(tree) = visit.pop() {
+      if let ast::Tree::Ref { nam } = tree
+        && nam == "HVM.exit"
+      {
+        return true;
+      }
+      visit.extend(tree.children());
+    }
+    false
+  })
+}
+
+pub(crate) fn add_exit_def(host: Arc<Mutex<Host>>, labels: &Labels, no_exit: bool, status: ExitStatus) {
   /// `HVM.exit`.
   /// Implements the following reduction rule
   ///
@@ -17,7 +78,9 @@ pub(crate) fn add_exit_def(host: Arc<Mutex<Host>>) {
   /// ---
   /// ExitDefGetStatus ~ a & <dangling> ~ b
   /// ```
-  struct ExitDef;
+  ///
+  /// Once the status code is reduced, the rest of the reduction is aborted.
+  struct ExitDef(ExitStatus);
 
   impl AsDefFunction for ExitDef {
     fn call<M: Mode>(&self, net: &mut Net<M>, input: Wire, output: Wire) {
@@ -26,27 +89,47 @@ pub(crate) fn add_exit_def(host: Arc<Mutex<Host>>) {
 
       if M::LAZY {
         net.normal_from(input.clone());
-        exit(net, input.load_target());
+        self.0.exit(net, &input.load_target());
       } else {
-        // The manual deref converts the type of (exit, exit) into Dynamic
-        #[allow(clippy::explicit_auto_deref)]
-        net.link_wire_port(input, Port::new_ref(&*Def::new(LabSet::ALL, (exit, exit))));
+        net.link_wire_port(input, ArcDef::new_arc_port(LabSet::ALL, ExitDefGetStatus(self.0.clone())));
       }
+    }
+  }
 
-      fn exit<M: Mode>(_: &mut Net<M>, port: Port) {
-        match port.tag() {
-          Tag::Num => {
-            std::process::exit(port.num().try_into().unwrap_or(-1));
-          }
-          _ => {
-            std::process::exit(-1);
-          }
-        }
-      }
+  /// Stores the status code once it's reduced to a number.
+  struct ExitDefGetStatus(ExitStatus);
+
+  impl AsArcDef for ExitDefGetStatus {
+    fn call<M: Mode>(slf: Arc<Def<Self>>, net: &mut Net<M>, port: Port) {
+      slf.data.0.exit(net, &port);
     }
   }
 
-  host
-    .lock()
-    .insert_def("HVM.exit", unsafe { HostedDef::new_hosted(LabSet::ALL, FunctionLikeHosted(ExitDef)) });
+  /// `HVM.exit`, when the IO policy denies exiting.
+  /// Erases the status code and returns an error instead.
+  struct DeniedExitDef {
+    host: Arc<Mutex<Host>>,
+    err: ast::Net,
+  }
+
+  impl AsDefFunction for DeniedExitDef {
+    fn call<M: Mode>(&self, net: &mut Net<M>, input: Wire, output: Wire) {
+      net.link_wire_port(input, Port::ERA);
+      self.host.lock().encode_net(net, Trg::wire(output), &self.err);
+    }
+  }
+
+  if no_exit {
+    let err = Term::encode_err(Term::encode_str(&format!("{IO_DENIED_MSG}exiting the program")));
+    // A term without free or scopeless variables is always a valid net.
+    let err = net_to_hvmc(&term_to_compat_net(&err, &mut labels.clone())).unwrap();
+    let def = DeniedExitDef { host: host.clone(), err };
+    host
+      .lock()
+      .insert_def("HVM.exit", unsafe { HostedDef::new_hosted(LabSet::ALL, FunctionLikeHosted(def)) });
+  } else {
+    host.lock().insert_def("HVM.exit", unsafe {
+      HostedDef::new_hosted(LabSet::ALL, FunctionLikeHosted(ExitDef(status)))
+    });
+  }
 }
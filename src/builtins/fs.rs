@@ -1,59 +1,158 @@
 use crate::{
-  builtins::util::{AsDefFunction, FunctionLike, FunctionLikeHosted},
-  net::net_to_hvmc::net_to_hvmc,
-  readback_hvmc,
-  term::{
-    term_to_net::{term_to_compat_net, Labels},
-    AdtEncoding, Book, Term,
+  builtins::{
+    util::{AsDefFunction, FunctionLike, FunctionLikeHosted, ReadbackData},
+    IoPolicy, IO_DENIED_MSG,
   },
+  term::{term_to_net::Labels, AdtEncoding, Book, Term},
 };
 use hvmc::{
-  ast, dispatch_dyn_net,
+  dispatch_dyn_net,
   host::Host,
-  run::{LabSet, Port, Trg, Wire},
+  run::{LabSet, Trg, Wire},
   stdlib::{ArcDef, HostedDef},
 };
 use parking_lot::Mutex;
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
-#[derive(Clone)]
-struct ReadbackData {
-  book: Arc<Book>,
-  host: Arc<Mutex<Host>>,
-  labels: Arc<Labels>,
-  adt_encoding: AdtEncoding,
-}
-
-const VICIOUS_CIRCLE_MSG: &str = "Found vicious circle";
 const FILENAME_NOT_VALID_MSG: &str = "Filename is not valid string.";
 const CONTENTS_NOT_VALID_MSG: &str = "Content is not valid string.";
+const BYTES_NOT_VALID_MSG: &str = "Content is not valid list of bytes.";
 const FS_ERROR_MSG: &str = "Filesystem error: ";
 const INVALID_UTF8_MSG: &str = "UTF-8 error: ";
 
-/// Adds the filesystem definitions (`HVM.store` and `HVM.load`) to the book
+/// The filesystem operations, each one implemented by a builtin definition.
+#[derive(Clone, Copy)]
+enum FsOp {
+  Load,
+  LoadBytes,
+  Store,
+  StoreBytes,
+  Append,
+  Exists,
+  ListDir,
+  Remove,
+  Mkdir,
+}
+
+impl FsOp {
+  /// Whether the operation takes the contents to write after the filename.
+  fn takes_contents(self) -> bool {
+    matches!(self, FsOp::Store | FsOp::StoreBytes | FsOp::Append)
+  }
+
+  /// Whether the operation changes the filesystem.
+  fn writes(self) -> bool {
+    matches!(self, FsOp::Store | FsOp::StoreBytes | FsOp::Append | FsOp::Remove | FsOp::Mkdir)
+  }
+
+  /// Fails if the IO policy denies the operation on the file.
+  fn check_policy(self, io_policy: &IoPolicy, filename: &str) -> Result<(), String> {
+    if self.writes() && !io_policy.allows_write(Path::new(filename)) {
+      Err(format!("{IO_DENIED_MSG}writing '{filename}'"))
+    } else if !self.writes() && !io_policy.allows_read(Path::new(filename)) {
+      Err(format!("{IO_DENIED_MSG}reading '{filename}'"))
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Runs an operation that doesn't take any contents.
+  fn run(self, filename: &str) -> Result<Term, String> {
+    let fs_err = |e: std::io::Error| format!("{FS_ERROR_MSG}{e}");
+    match self {
+      FsOp::Load => {
+        let bytes = std::fs::read(filename).map_err(fs_err)?;
+        let contents =
+          String::from_utf8(bytes).map_err(|e| format!("{INVALID_UTF8_MSG}{}", e.utf8_error()))?;
+        Ok(Term::encode_str(&contents))
+      }
+      FsOp::LoadBytes => {
+        let bytes = std::fs::read(filename).map_err(fs_err)?;
+        Ok(Term::encode_list(bytes.into_iter().map(|byte| Term::num(byte as u64)).collect()))
+      }
+      FsOp::Exists => {
+        let exists = std::path::Path::new(filename).try_exists().map_err(fs_err)?;
+        Ok(Term::num(exists as u64))
+      }
+      FsOp::ListDir => {
+        let mut names = vec![];
+        for entry in std::fs::read_dir(filename).map_err(fs_err)? {
+          let name = entry.map_err(fs_err)?.file_name();
+          let name = name.into_string().map_err(|name| format!("{INVALID_UTF8_MSG}{name:?}"))?;
+          names.push(name);
+        }
+        // The order of the entries depends on the platform.
+        names.sort();
+        Ok(Term::encode_list(names.iter().map(|name| Term::encode_str(name)).collect()))
+      }
+      FsOp::Remove => {
+        if std::path::Path::new(filename).is_dir() {
+          std::fs::remove_dir(filename).map_err(fs_err)?;
+        } else {
+          std::fs::remove_file(filename).map_err(fs_err)?;
+        }
+        Ok(Term::era())
+      }
+      FsOp::Mkdir => {
+        std::fs::create_dir_all(filename).map_err(fs_err)?;
+        Ok(Term::era())
+      }
+      FsOp::Store | FsOp::StoreBytes | FsOp::Append => unreachable!(),
+    }
+  }
+
+  /// Runs an operation that writes the read back `contents` to a file.
+  fn run_with_contents(self, filename: &str, contents: Term) -> Result<Term, String> {
+    let fs_err = |e: std::io::Error| format!("{FS_ERROR_MSG}{e}");
+    let contents = match (self, &contents) {
+      (FsOp::Store | FsOp::Append, Term::Str { val, .. }) => val.to_string().into_bytes(),
+      (FsOp::Store | FsOp::Append, _) => return Err(CONTENTS_NOT_VALID_MSG.to_string()),
+      (FsOp::StoreBytes, Term::Lst { els, .. }) => {
+        let to_byte = |el: &Term| match el {
+          Term::Num { val, .. } => u8::try_from(*val).ok(),
+          _ => None,
+        };
+        els.iter().map(to_byte).collect::<Option<Vec<_>>>().ok_or(BYTES_NOT_VALID_MSG.to_string())?
+      }
+      (FsOp::StoreBytes, _) => return Err(BYTES_NOT_VALID_MSG.to_string()),
+      _ => unreachable!(),
+    };
+    if let FsOp::Append = self {
+      use std::io::Write;
+      let mut file = std::fs::OpenOptions::new().append(true).create(true).open(filename).map_err(fs_err)?;
+      file.write_all(&contents).map_err(fs_err)?;
+    } else {
+      std::fs::write(filename, contents).map_err(fs_err)?;
+    }
+    Ok(Term::era())
+  }
+}
+
+/// Adds the filesystem definitions (`HVM.store`, `HVM.load`, `HVM.store_bytes`, `HVM.load_bytes`,
+/// `HVM.append`, `HVM.exists`, `HVM.list_dir`, `HVM.remove` and `HVM.mkdir`) to the book
 pub(crate) fn add_fs_defs(
   book: Arc<Book>,
   host: Arc<Mutex<Host>>,
   labels: Arc<Labels>,
   adt_encoding: AdtEncoding,
+  io_policy: Arc<IoPolicy>,
 ) {
   #[derive(Clone)]
   struct Fs0 {
     readback_data: ReadbackData,
-    save: bool,
+    op: FsOp,
   }
   impl AsDefFunction for Fs0 {
     fn call<M: hvmc::run::Mode>(&self, net: &mut hvmc::run::Net<M>, input: Wire, output: Wire) {
       let slf = self.clone();
       hvmc::stdlib::readback(net, slf.readback_data.host.clone(), Trg::wire(input), move |net, tree| {
         dispatch_dyn_net!(net => {
-          let (term, _errs) = readback_hvmc(&ast::Net { root: tree,redexes: vec![]} , &slf.readback_data.book, &slf.readback_data.labels, false, slf.readback_data.adt_encoding);
-          let filename = if let Term::Str { ref val } = term {
+          let filename = if let Term::Str { ref val, .. } = slf.readback_data.readback(tree) {
             Some(val.to_string())
           } else {
             None
           };
-          net.link_wire_port(output, ArcDef::new_arc_port(LabSet::ALL, FunctionLike(Fs1 { readback_data: slf.readback_data, save: slf.save, filename  })));
+          net.link_wire_port(output, ArcDef::new_arc_port(LabSet::ALL, FunctionLike(Fs1 { readback_data: slf.readback_data, op: slf.op, filename  })));
         })
       });
     }
@@ -62,98 +161,68 @@ pub(crate) fn add_fs_defs(
   #[derive(Clone)]
   struct Fs1 {
     readback_data: ReadbackData,
-    save: bool,
+    op: FsOp,
     filename: Option<String>,
   }
   impl AsDefFunction for Fs1 {
     fn call<M: hvmc::run::Mode>(&self, net: &mut hvmc::run::Net<M>, input: Wire, output: Wire) {
-      if self.save {
+      if self.op.takes_contents() {
         let slf = self.clone();
-        let mut labels = (*self.readback_data.labels).clone();
-        let host = self.readback_data.host.clone();
         hvmc::stdlib::readback(net, self.readback_data.host.clone(), Trg::wire(input), move |net, tree| {
           dispatch_dyn_net!(net => {
-            let (term, _errs) = readback_hvmc(&ast::Net { root: tree,redexes: vec![]} , &slf.readback_data.book, &slf.readback_data.labels, false, slf.readback_data.adt_encoding);
-            let contents = if let Term::Str { ref val } = term {
-              Some(val.to_string())
-            } else {
-              None
+            let contents = slf.readback_data.readback(tree);
+            // Write file
+            let result = match slf.filename {
+              None => Err(FILENAME_NOT_VALID_MSG.to_string()),
+              Some(filename) => slf
+                .op
+                .check_policy(&slf.readback_data.io_policy, &filename)
+                .and_then(|_| slf.op.run_with_contents(&filename, contents)),
             };
-            // Save file
-            let result = match (slf.filename, contents) {
-              (None, _) => {
-                Term::encode_err(Term::encode_str(FILENAME_NOT_VALID_MSG))
-              },
-              (_, None) => {
-                Term::encode_err(Term::encode_str(CONTENTS_NOT_VALID_MSG))
-              },
-              (Some(filename), Some(contents)) => {
-                match std::fs::write(filename, contents) {
-                  Ok(_) => Term::encode_ok(Term::Era),
-                  Err(e) => Term::encode_err(Term::encode_str(&format!("{FS_ERROR_MSG}{e}"))),
-                }
-              },
+            let result = match result {
+              Ok(val) => Term::encode_ok(val),
+              Err(e) => Term::encode_err(Term::encode_str(&e)),
             };
-            let result = term_to_compat_net(&result, &mut labels);
-            match net_to_hvmc(&result) {
-                Ok(result) => {
-                  // Return λx (x result)
-                  let app = net.create_node(hvmc::run::Tag::Ctr, 0);
-                  let lam = net.create_node(hvmc::run::Tag::Ctr, 0);
-                  host.lock().encode_net(net, Trg::port(app.p1), &result);
-                  net.link_port_port(app.p0, lam.p1);
-                  net.link_port_port(app.p2, lam.p2);
-
-                  net.link_wire_port(output, lam.p0);
-                },
-                Err(_) => {
-                  // If this happens, we can't even report an error to
-                  // the hvm program, so simply print an error, and plug in an ERA
-                  // The other option would be panicking.
-                  eprintln!("{VICIOUS_CIRCLE_MSG}");
-                  net.link_wire_port(output, Port::ERA);
-                },
-            }
+            // Return λx (x result)
+            let app = net.create_node(hvmc::run::Tag::Ctr, 0);
+            let lam = net.create_node(hvmc::run::Tag::Ctr, 0);
+            slf.readback_data.encode_term(net, &result, Trg::port(app.p1));
+            net.link_port_port(app.p0, lam.p1);
+            net.link_port_port(app.p2, lam.p2);
+            net.link_wire_port(output, lam.p0);
           })
         });
       } else {
         let app = net.create_node(hvmc::run::Tag::Ctr, 0);
-        let result = self
-          .filename
-          .as_ref()
-          .ok_or(FILENAME_NOT_VALID_MSG.to_owned())
-          .and_then(|filename| std::fs::read(filename).map_err(|e| format!("{FS_ERROR_MSG}{e}")))
-          .and_then(|x| {
-            std::str::from_utf8(&x).map(|x| x.to_string()).map_err(|e| format!("{INVALID_UTF8_MSG}{e}"))
-          });
+        let result = self.filename.as_ref().ok_or(FILENAME_NOT_VALID_MSG.to_owned()).and_then(|x| {
+          self.op.check_policy(&self.readback_data.io_policy, x)?;
+          self.op.run(x)
+        });
         let result = match result {
-          Ok(s) => Term::encode_ok(Term::encode_str(&s)),
+          Ok(val) => Term::encode_ok(val),
           Err(s) => Term::encode_err(Term::encode_str(&s)),
         };
-        let mut labels = (*self.readback_data.labels).clone();
-        let result = term_to_compat_net(&result, &mut labels);
-        if let Ok(result) = net_to_hvmc(&result) {
-          self.readback_data.host.lock().encode_net(net, Trg::port(app.p1), &result);
-        } else {
-          eprintln!("{VICIOUS_CIRCLE_MSG}");
-          net.link_port_port(Port::ERA, app.p1);
-        }
+        self.readback_data.encode_term(net, &result, Trg::port(app.p1));
         net.link_wire_port(output, app.p2);
         net.link_wire_port(input, app.p0);
       }
     }
   }
-  let readback_data = ReadbackData { book, host: host.clone(), labels, adt_encoding };
-  host.lock().insert_def("HVM.store", unsafe {
-    HostedDef::new_hosted(
-      LabSet::ALL,
-      FunctionLikeHosted(Fs0 { readback_data: readback_data.clone(), save: true }),
-    )
-  });
-  host.lock().insert_def("HVM.load", unsafe {
-    HostedDef::new_hosted(
-      LabSet::ALL,
-      FunctionLikeHosted(Fs0 { readback_data: readback_data.clone(), save: false }),
-    )
-  });
+  let readback_data = ReadbackData { book, host: host.clone(), labels, adt_encoding, io_policy };
+  let defs = [
+    ("HVM.store", FsOp::Store),
+    ("HVM.load", FsOp::Load),
+    ("HVM.store_bytes", FsOp::StoreBytes),
+    ("HVM.load_bytes", FsOp::LoadBytes),
+    ("HVM.append", FsOp::Append),
+    ("HVM.exists", FsOp::Exists),
+    ("HVM.list_dir", FsOp::ListDir),
+    ("HVM.remove", FsOp::Remove),
+    ("HVM.mkdir", FsOp::Mkdir),
+  ];
+  for (name, op) in defs {
+    host.lock().insert_def(name, unsafe {
+      HostedDef::new_hosted(LabSet::ALL, FunctionLikeHosted(Fs0 { readback_data: readback_data.clone(), op }))
+    });
+  }
 }
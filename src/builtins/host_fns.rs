@@ -0,0 +1,147 @@
+use crate::{
+  builtins::{
+    util::{AsDefFunction, FunctionLike, FunctionLikeHosted, ReadbackData},
+    IoPolicy, CORE_BUILTINS,
+  },
+  term::{
+    builtins::{LCONS, LNIL, RESULT_ERR, RESULT_OK, SCONS, SNIL},
+    term_to_net::Labels,
+    AdtEncoding, Book, Name, Term,
+  },
+};
+use hvmc::{
+  dispatch_dyn_net,
+  host::Host,
+  run::{LabSet, Mode, Net, Trg, Wire},
+  stdlib::{ArcDef, HostedDef},
+};
+use indexmap::IndexMap;
+use parking_lot::Mutex;
+use std::{fmt, sync::Arc};
+
+/// Definitions that the results of every host function can use,
+/// since they're what the strings, lists and results read back into.
+const DEFAULT_USES: [&str; 6] = [SCONS, SNIL, LCONS, LNIL, RESULT_OK, RESULT_ERR];
+
+/// Rust functions that programs can call like builtin definitions.
+///
+/// The arguments of a call are read back into terms, and the term returned by the function is
+/// encoded back into the net, so strings, lists and numbers can be used directly.
+///
+/// ```ignore
+/// let mut host_fns = HostFns::default();
+/// host_fns.register("DB.lookup", 1, move |args| match &args[0] {
+///   Term::Str { val } => match db.get(val.as_ref()) {
+///     Some(row) => Term::encode_ok(Term::from(row.clone())),
+///     None => Term::encode_err(Term::from("Not found")),
+///   },
+///   _ => Term::encode_err(Term::from("Expected a string key")),
+/// })?;
+/// let run_opts = RunOpts { host_fns, ..RunOpts::default() };
+/// let (res, info) = run_book(book, None, run_opts, compile_opts, diagnostics_cfg, None)?;
+/// ```
+#[derive(Clone, Default)]
+pub struct HostFns(IndexMap<Name, HostFn>);
+
+#[derive(Clone)]
+pub struct HostFn {
+  arity: usize,
+  uses: Vec<Name>,
+  fun: Arc<dyn Fn(Vec<Term>) -> Term + Send + Sync>,
+}
+
+impl HostFns {
+  /// Adds a function that is called once it's applied to `arity` arguments.
+  /// Registering a name again replaces the previous function.
+  /// Fails if the function doesn't take any arguments.
+  pub fn register(
+    &mut self,
+    name: &str,
+    arity: usize,
+    fun: impl Fn(Vec<Term>) -> Term + Send + Sync + 'static,
+  ) -> Result<&mut HostFn, String> {
+    if arity == 0 {
+      return Err(format!(
+        "Can't register the host function '{name}', host functions must take at least one argument."
+      ));
+    }
+    let uses = DEFAULT_USES.iter().map(|nam| Name::new(*nam)).collect();
+    let host_fn = HostFn { arity, uses, fun: Arc::new(fun) };
+    let (idx, _) = self.0.insert_full(Name::new(name), host_fn);
+    Ok(&mut self.0[idx])
+  }
+
+  /// Adds the names of the functions to the host builtins of the book, so that they can be referenced.
+  pub fn declare(&self, book: &mut Book) -> Result<(), String> {
+    for (name, host_fn) in &self.0 {
+      if book.defs.contains_key(name) || CORE_BUILTINS.contains(&&**name) {
+        return Err(format!(
+          "Can't register the host function '{name}', there's already a definition with that name."
+        ));
+      }
+      book.host_builtins.insert(name.clone(), host_fn.uses.clone());
+    }
+    Ok(())
+  }
+}
+
+impl HostFn {
+  /// Adds definitions that the results of the function can contain, like the constructors of an ADT,
+  /// so that they are not pruned.
+  pub fn uses(&mut self, defs: &[&str]) -> &mut Self {
+    self.uses.extend(defs.iter().map(|nam| Name::new(*nam)));
+    self
+  }
+}
+
+impl fmt::Debug for HostFns {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_set().entries(self.0.keys()).finish()
+  }
+}
+
+/// Adds the registered host functions to the host.
+pub(crate) fn add_host_fns(
+  host_fns: &HostFns,
+  book: Arc<Book>,
+  host: Arc<Mutex<Host>>,
+  labels: Arc<Labels>,
+  adt_encoding: AdtEncoding,
+  io_policy: Arc<IoPolicy>,
+) {
+  /// A call to a host function, with the arguments read back so far.
+  #[derive(Clone)]
+  struct HostCall {
+    data: ReadbackData,
+    host_fn: HostFn,
+    args: Vec<Term>,
+  }
+
+  impl AsDefFunction for HostCall {
+    fn call<M: Mode>(&self, net: &mut Net<M>, input: Wire, output: Wire) {
+      let slf = self.clone();
+      hvmc::stdlib::readback(net, self.data.host.clone(), Trg::wire(input), move |net, tree| {
+        dispatch_dyn_net!(net => {
+          let HostCall { data, host_fn, mut args } = slf;
+          args.push(data.readback(tree));
+          if args.len() < host_fn.arity {
+            // Waits for the next argument.
+            let call = HostCall { data, host_fn, args };
+            net.link_wire_port(output, ArcDef::new_arc_port(LabSet::ALL, FunctionLike(call)));
+          } else {
+            let mut result = (host_fn.fun)(args);
+            result.encode_builtins();
+            data.encode_term(net, &result, Trg::wire(output));
+          }
+        })
+      });
+    }
+  }
+
+  let data = ReadbackData { book, host: host.clone(), labels, adt_encoding, io_policy };
+  let mut host = host.lock();
+  for (name, host_fn) in &host_fns.0 {
+    let call = HostCall { data: data.clone(), host_fn: host_fn.clone(), args: vec![] };
+    host.insert_def(name, unsafe { HostedDef::new_hosted(LabSet::ALL, FunctionLikeHosted(call)) });
+  }
+}
@@ -1,34 +1,165 @@
-use self::query::make_query_def;
+use self::{
+  exit::ExitStatus,
+  host_fns::{add_host_fns, HostFns},
+  query::{make_query_def, StdinOp},
+};
 use crate::{
   readback_hvmc,
   term::{
-    builtins::{RESULT_ERR, RESULT_OK, SCONS, SNIL},
+    builtins::{LCONS, LNIL, RESULT_ERR, RESULT_OK, SCONS, SNIL},
     term_to_net::Labels,
     AdtEncoding, Book, Term,
   },
 };
 use hvmc::{ast, host::Host, stdlib::LogDef};
 use parking_lot::Mutex;
-use std::{str::FromStr, sync::Arc};
+use std::{
+  io::Write,
+  path::{Path, PathBuf},
+  str::FromStr,
+  sync::Arc,
+};
 
 pub mod exit;
 pub mod fs;
+pub mod host_fns;
 pub mod query;
+pub mod sys;
 pub mod util;
 
 /// These are the names of builtin defs that are not in the hvm-lang book, but
 /// are present in the hvm-core book. They are implemented using Rust code by
 /// [`create_host`] and they can not be rewritten as hvm-lang functions.
-pub const CORE_BUILTINS: [&str; 7] =
-  ["HVM.log", "HVM.black_box", "HVM.print", "HVM.query", "HVM.store", "HVM.load", "HVM.exit"];
+pub const CORE_BUILTINS: [&str; 22] = [
+  "HVM.log",
+  "HVM.black_box",
+  "HVM.print",
+  "HVM.query",
+  "HVM.store",
+  "HVM.load",
+  "HVM.exit",
+  "HVM.getenv",
+  "HVM.time",
+  "HVM.sleep",
+  "HVM.random",
+  "HVM.store_bytes",
+  "HVM.load_bytes",
+  "HVM.append",
+  "HVM.exists",
+  "HVM.list_dir",
+  "HVM.remove",
+  "HVM.mkdir",
+  "HVM.write",
+  "HVM.eprint",
+  "HVM.read_char",
+  "HVM.read_all",
+];
 /// List of definition names used by the core builtins
-pub const CORE_BUILTINS_USES: [&[&str]; 7] =
-  [&[], &[], &[], &[SCONS, SNIL], &[RESULT_OK, RESULT_ERR], &[RESULT_OK, RESULT_ERR], &[]];
+pub const CORE_BUILTINS_USES: [&[&str]; 22] = [
+  &[],
+  &[],
+  &[],
+  &[SCONS, SNIL],
+  &[RESULT_OK, RESULT_ERR],
+  &[RESULT_OK, RESULT_ERR],
+  &[],
+  &[SCONS, SNIL, RESULT_OK, RESULT_ERR],
+  &[],
+  &[],
+  &[],
+  &[RESULT_OK, RESULT_ERR],
+  &[LCONS, LNIL, RESULT_OK, RESULT_ERR],
+  &[RESULT_OK, RESULT_ERR],
+  &[RESULT_OK, RESULT_ERR],
+  &[SCONS, SNIL, LCONS, LNIL, RESULT_OK, RESULT_ERR],
+  &[RESULT_OK, RESULT_ERR],
+  &[RESULT_OK, RESULT_ERR],
+  &[],
+  &[],
+  &[SCONS, SNIL, RESULT_OK, RESULT_ERR],
+  &[SCONS, SNIL, RESULT_OK, RESULT_ERR],
+];
+
+/// The message of the errors returned by operations that the [`IoPolicy`] denies.
+const IO_DENIED_MSG: &str = "Denied by the IO policy: ";
+
+/// Printed when a term returned by a builtin can't be encoded into the net.
+const VICIOUS_CIRCLE_MSG: &str = "Found vicious circle";
+
+/// The side effects that the builtins of a running program are allowed to have.
+/// Denied operations return a `Result.err` to the program instead of acting.
+///
+/// The default policy allows everything.
+#[derive(Clone, Debug, Default)]
+pub struct IoPolicy {
+  /// The directories whose files can be read. `None` allows reading any file.
+  pub allow_read: Option<Vec<PathBuf>>,
+  /// The directories whose files can be written, created or removed. `None` allows writing any file.
+  pub allow_write: Option<Vec<PathBuf>>,
+  /// Denies `HVM.exit` from terminating the process.
+  pub no_exit: bool,
+  /// Denies `HVM.query` from reading the standard input.
+  pub no_stdin: bool,
+  /// Denies `HVM.getenv` from reading environment variables.
+  pub no_env: bool,
+}
+
+impl IoPolicy {
+  /// A policy that denies every side effect except printing.
+  pub fn sandboxed() -> Self {
+    Self { allow_read: Some(vec![]), allow_write: Some(vec![]), no_exit: true, no_stdin: true, no_env: true }
+  }
+
+  pub fn allows_read(&self, path: &Path) -> bool {
+    allows_path(self.allow_read.as_deref(), path)
+  }
+
+  pub fn allows_write(&self, path: &Path) -> bool {
+    allows_path(self.allow_write.as_deref(), path)
+  }
+}
+
+/// Whether `path` is inside one of the allowed directories, once symbolic links and `..` are resolved.
+fn allows_path(allowed: Option<&[PathBuf]>, path: &Path) -> bool {
+  let Some(allowed) = allowed else { return true };
+  let Some(path) = resolve_path(path) else { return false };
+  allowed.iter().filter_map(|dir| dir.canonicalize().ok()).any(|dir| path.starts_with(dir))
+}
+
+/// The canonical form of a path that might not exist yet, like a file that is about to be created.
+/// The missing components are joined to the canonical form of the longest ancestor that exists,
+/// and can't be `..`.
+fn resolve_path(path: &Path) -> Option<PathBuf> {
+  let mut missing = vec![];
+  let mut existing = path;
+  let resolved = loop {
+    let dir = if existing.as_os_str().is_empty() { Path::new(".") } else { existing };
+    match dir.canonicalize() {
+      Ok(resolved) => break resolved,
+      Err(_) => {
+        missing.push(existing.file_name()?);
+        existing = existing.parent()?;
+      }
+    }
+  };
+  Some(missing.into_iter().rev().fold(resolved, |path, name| path.join(name)))
+}
 
 /// Creates a host with the hvm-core primitive definitions built-in.
 /// This needs the book as an Arc because the closure that logs
 /// data needs access to the book.
-pub fn create_host(book: Arc<Book>, labels: Arc<Labels>, adt_encoding: AdtEncoding) -> Arc<Mutex<Host>> {
+///
+/// Calls to `HVM.exit` store their status code in `exit_status`.
+/// The registered `host_fns` are added after the core builtins.
+pub fn create_host(
+  book: Arc<Book>,
+  labels: Arc<Labels>,
+  adt_encoding: AdtEncoding,
+  io_policy: IoPolicy,
+  host_fns: &HostFns,
+  exit_status: ExitStatus,
+) -> Arc<Mutex<Host>> {
+  let io_policy = Arc::new(io_policy);
   let host = Arc::new(Mutex::new(Host::default()));
   host.lock().insert_def("HVM.log", unsafe {
     LogDef::new(host.clone(), {
@@ -41,22 +172,40 @@ pub fn create_host(book: Arc<Book>, labels: Arc<Labels>, adt_encoding: AdtEncodi
       }
     })
   });
-  host.lock().insert_def("HVM.print", unsafe {
-    LogDef::new(host.clone(), {
-      let book = book.clone();
-      let labels = labels.clone();
-      move |tree| {
-        let net = hvmc::ast::Net { root: tree, redexes: vec![] };
-        let (term, _errs) = readback_hvmc(&net, &book, &labels, false, adt_encoding);
-        if let Term::Str { val } = &term {
-          println!("{val}");
+  // Definitions like `HVM.log` that only write strings, without quotes.
+  type StringWriter = fn(&str);
+  let string_writers: [(&str, StringWriter); 3] = [
+    ("HVM.print", |str| println!("{str}")),
+    ("HVM.write", |str| {
+      print!("{str}");
+      let _ = std::io::stdout().flush();
+    }),
+    ("HVM.eprint", |str| eprintln!("{str}")),
+  ];
+  for (name, write) in string_writers {
+    host.lock().insert_def(name, unsafe {
+      LogDef::new(host.clone(), {
+        let book = book.clone();
+        let labels = labels.clone();
+        move |tree| {
+          let net = hvmc::ast::Net { root: tree, redexes: vec![] };
+          let (term, _errs) = readback_hvmc(&net, &book, &labels, false, adt_encoding);
+          if let Term::Str { val, .. } = &term {
+            write(val);
+          }
         }
-      }
-    })
-  });
-  host.lock().insert_def("HVM.query", make_query_def(host.clone(), labels.clone()));
-  fs::add_fs_defs(book.clone(), host.clone(), labels.clone(), adt_encoding);
-  exit::add_exit_def(host.clone());
+      })
+    });
+  }
+  let stdin_defs =
+    [("HVM.query", StdinOp::Line), ("HVM.read_char", StdinOp::Char), ("HVM.read_all", StdinOp::All)];
+  for (name, op) in stdin_defs {
+    host.lock().insert_def(name, make_query_def(host.clone(), labels.clone(), io_policy.no_stdin, op));
+  }
+  fs::add_fs_defs(book.clone(), host.clone(), labels.clone(), adt_encoding, io_policy.clone());
+  exit::add_exit_def(host.clone(), &labels, io_policy.no_exit, exit_status);
+  sys::add_sys_defs(book.clone(), host.clone(), labels.clone(), adt_encoding, io_policy.clone());
+  add_host_fns(host_fns, book.clone(), host.clone(), labels.clone(), adt_encoding, io_policy);
   let book = ast::Book::from_str("@HVM.black_box = (x x)").unwrap();
   host.lock().insert_book(&book);
 
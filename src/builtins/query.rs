@@ -1,5 +1,8 @@
 use crate::{
-  builtins::util::{AsDefFunction, FunctionLikeHosted},
+  builtins::{
+    util::{AsDefFunction, FunctionLikeHosted},
+    IO_DENIED_MSG,
+  },
   term::{term_to_net::Labels, Term},
 };
 use hvmc::{
@@ -8,21 +11,95 @@ use hvmc::{
   stdlib::HostedDef,
 };
 use parking_lot::Mutex;
-use std::sync::Arc;
+use std::{
+  io::{BufRead, Read},
+  sync::Arc,
+};
+
+const EOF_MSG: &str = "End of file";
+const STDIN_ERROR_MSG: &str = "Standard input error: ";
+const INVALID_UTF8_MSG: &str = "UTF-8 error: ";
+
+/// The ways of reading the standard input, each one implemented by a builtin definition.
+#[derive(Clone, Copy)]
+pub(crate) enum StdinOp {
+  /// `HVM.query`, reads a line without its trailing newline.
+  Line,
+  /// `HVM.read_char`, reads a single character as a number.
+  Char,
+  /// `HVM.read_all`, reads everything until the end of the input.
+  All,
+}
+
+impl StdinOp {
+  /// Reads from the standard input, failing with [`EOF_MSG`] if the input ended before reading anything.
+  fn read(self) -> Result<Term, String> {
+    let mut stdin = std::io::stdin().lock();
+    let io_err = |e: std::io::Error| format!("{STDIN_ERROR_MSG}{e}");
+    match self {
+      StdinOp::Line => {
+        let mut buf = String::new();
+        if stdin.read_line(&mut buf).map_err(io_err)? == 0 {
+          return Err(EOF_MSG.to_string());
+        }
+        // strip trailing newline
+        let buf = buf.strip_suffix('\n').unwrap_or(&buf);
+        Ok(Term::encode_str(buf))
+      }
+      StdinOp::Char => {
+        let mut buf = [0; 4];
+        if stdin.read(&mut buf[.. 1]).map_err(io_err)? == 0 {
+          return Err(EOF_MSG.to_string());
+        }
+        let utf8_err = |e: std::str::Utf8Error| format!("{INVALID_UTF8_MSG}{e}");
+        // The length of an UTF-8 encoded character is given by its first byte.
+        let len = match buf[0] {
+          0x00 ..= 0x7F => 1,
+          0xC2 ..= 0xDF => 2,
+          0xE0 ..= 0xEF => 3,
+          0xF0 ..= 0xF4 => 4,
+          // A byte that can't start a character is an error by itself, so the next bytes are left unread.
+          _ => return Err(utf8_err(std::str::from_utf8(&buf[.. 1]).unwrap_err())),
+        };
+        stdin.read_exact(&mut buf[1 .. len]).map_err(io_err)?;
+        let str = std::str::from_utf8(&buf[.. len]).map_err(utf8_err)?;
+        let char = str.chars().next().unwrap();
+        Ok(Term::num(char as u64))
+      }
+      StdinOp::All => {
+        // Reaching the end of the input is expected here, so an empty input is not an error.
+        let mut buf = String::new();
+        stdin.read_to_string(&mut buf).map_err(io_err)?;
+        Ok(Term::encode_str(&buf))
+      }
+    }
+  }
+}
 
-pub(crate) fn make_query_def(host: Arc<Mutex<Host>>, labels: Arc<Labels>) -> DefRef {
+pub(crate) fn make_query_def(
+  host: Arc<Mutex<Host>>,
+  labels: Arc<Labels>,
+  no_stdin: bool,
+  op: StdinOp,
+) -> DefRef {
   struct Query0 {
     host: Arc<Mutex<Host>>,
     labels: Arc<Labels>,
+    no_stdin: bool,
+    op: StdinOp,
   }
   impl AsDefFunction for Query0 {
     fn call<M: hvmc::run::Mode>(&self, net: &mut hvmc::run::Net<M>, input: Wire, output: Wire) {
       let app_node = net.create_node(hvmc::run::Tag::Ctr, 0);
-      let mut buf = String::new();
-      let _ = std::io::stdin().read_line(&mut buf);
-      // strip trailing newline
-      let buf = buf.strip_suffix('\n').unwrap_or(&buf);
-      let text = Term::encode_ok(Term::encode_str(buf));
+      let result = if self.no_stdin {
+        Err(format!("{IO_DENIED_MSG}reading the standard input"))
+      } else {
+        self.op.read()
+      };
+      let text = match result {
+        Ok(val) => Term::encode_ok(val),
+        Err(e) => Term::encode_err(Term::encode_str(&e)),
+      };
       let mut labs = (*self.labels).clone();
       let text = crate::term::term_to_net::term_to_compat_net(&text, &mut labs);
       net.link_wire_port(output, app_node.p2);
@@ -35,5 +112,5 @@ pub(crate) fn make_query_def(host: Arc<Mutex<Host>>, labels: Arc<Labels>) -> Def
       net.link_wire_port(input, app_node.p0);
     }
   }
-  unsafe { HostedDef::new_hosted(LabSet::ALL, FunctionLikeHosted(Query0 { host, labels })) }
+  unsafe { HostedDef::new_hosted(LabSet::ALL, FunctionLikeHosted(Query0 { host, labels, no_stdin, op })) }
 }
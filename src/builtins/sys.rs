@@ -0,0 +1,146 @@
+use crate::{
+  builtins::{
+    util::{AsDefFunction, FunctionLikeHosted, ReadbackData},
+    IoPolicy, IO_DENIED_MSG,
+  },
+  diagnostics::Loc,
+  term::{term_to_net::Labels, AdtEncoding, Book, Term, NUM_SIGN_BIT, U60_MASK},
+};
+use hvmc::{
+  ast, dispatch_dyn_net,
+  host::Host,
+  run::{LabSet, Mode, Net, Tag, Trg, Wire},
+  stdlib::HostedDef,
+};
+use parking_lot::Mutex;
+use std::{
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+/// The random numbers and seeds are kept below the sign bit of the 60-bit numbers of hvm-core,
+/// so that they are positive in signed operations and are read back unchanged when passed as seeds.
+const RANDOM_MASK: u64 = NUM_SIGN_BIT - 1;
+
+const VAR_NAME_NOT_VALID_MSG: &str = "Variable name is not valid string.";
+const ENV_ERROR_MSG: &str = "Environment error: ";
+
+/// Adds the definitions that interact with the system
+/// (`HVM.getenv`, `HVM.time`, `HVM.sleep` and `HVM.random`) to the host.
+pub(crate) fn add_sys_defs(
+  book: Arc<Book>,
+  host: Arc<Mutex<Host>>,
+  labels: Arc<Labels>,
+  adt_encoding: AdtEncoding,
+  io_policy: Arc<IoPolicy>,
+) {
+  /// `HVM.getenv`.
+  /// Reads back the name of the variable, and returns `λk (k result)`.
+  struct GetEnv(ReadbackData);
+
+  impl AsDefFunction for GetEnv {
+    fn call<M: Mode>(&self, net: &mut Net<M>, input: Wire, output: Wire) {
+      let data = self.0.clone();
+      hvmc::stdlib::readback(net, self.0.host.clone(), Trg::wire(input), move |net, tree| {
+        dispatch_dyn_net!(net => {
+          let result = match data.readback(tree) {
+            Term::Str { .. } if data.io_policy.no_env => {
+              Term::encode_err(Term::encode_str(&format!("{IO_DENIED_MSG}reading environment variables")))
+            }
+            Term::Str { ref val, .. } => match std::env::var(val.to_string()) {
+              Ok(val) => Term::encode_ok(Term::encode_str(&val)),
+              Err(e) => Term::encode_err(Term::encode_str(&format!("{ENV_ERROR_MSG}{e}"))),
+            },
+            _ => Term::encode_err(Term::encode_str(VAR_NAME_NOT_VALID_MSG)),
+          };
+          let app = net.create_node(Tag::Ctr, 0);
+          let lam = net.create_node(Tag::Ctr, 0);
+          data.encode_term(net, &result, Trg::port(app.p1));
+          net.link_port_port(app.p0, lam.p1);
+          net.link_port_port(app.p2, lam.p2);
+          net.link_wire_port(output, lam.p0);
+        })
+      });
+    }
+  }
+
+  /// `HVM.time`.
+  /// Calls its argument with the nanoseconds elapsed since the host was created.
+  struct Time {
+    data: ReadbackData,
+    start: Instant,
+  }
+
+  impl AsDefFunction for Time {
+    fn call<M: Mode>(&self, net: &mut Net<M>, input: Wire, output: Wire) {
+      let nanos = self.start.elapsed().as_nanos() as u64 & U60_MASK;
+      let app = net.create_node(Tag::Ctr, 0);
+      self.data.encode_term(net, &Term::num(nanos), Trg::port(app.p1));
+      net.link_wire_port(output, app.p2);
+      net.link_wire_port(input, app.p0);
+    }
+  }
+
+  /// `HVM.sleep`.
+  /// Waits for the given amount of nanoseconds, then returns the identity function.
+  struct Sleep(ReadbackData);
+
+  impl AsDefFunction for Sleep {
+    fn call<M: Mode>(&self, net: &mut Net<M>, input: Wire, output: Wire) {
+      hvmc::stdlib::readback(net, self.0.host.clone(), Trg::wire(input), move |net, tree| {
+        dispatch_dyn_net!(net => {
+          if let ast::Tree::Num { val } = tree {
+            std::thread::sleep(Duration::from_nanos(val as u64 & U60_MASK));
+          }
+          let id = net.create_node(Tag::Ctr, 0);
+          net.link_port_port(id.p1, id.p2);
+          net.link_wire_port(output, id.p0);
+        })
+      });
+    }
+  }
+
+  /// `HVM.random`.
+  /// Given a seed, returns a pseudo-random number and the seed of the next one.
+  struct Random(ReadbackData);
+
+  impl AsDefFunction for Random {
+    fn call<M: Mode>(&self, net: &mut Net<M>, input: Wire, output: Wire) {
+      let data = self.0.clone();
+      hvmc::stdlib::readback(net, self.0.host.clone(), Trg::wire(input), move |net, tree| {
+        dispatch_dyn_net!(net => {
+          let seed = if let ast::Tree::Num { val } = tree { val as u64 & U60_MASK } else { 0 };
+          let (val, seed) = splitmix(seed);
+          let result = Term::Tup { els: vec![Term::num(val), Term::num(seed)], loc: Loc::default() };
+          data.encode_term(net, &result, Trg::wire(output));
+        })
+      });
+    }
+  }
+
+  let data = ReadbackData { book, host: host.clone(), labels, adt_encoding, io_policy };
+  let mut host = host.lock();
+  host.insert_def("HVM.getenv", unsafe {
+    HostedDef::new_hosted(LabSet::ALL, FunctionLikeHosted(GetEnv(data.clone())))
+  });
+  host.insert_def("HVM.time", unsafe {
+    HostedDef::new_hosted(LabSet::ALL, FunctionLikeHosted(Time { data: data.clone(), start: Instant::now() }))
+  });
+  host.insert_def("HVM.sleep", unsafe {
+    HostedDef::new_hosted(LabSet::ALL, FunctionLikeHosted(Sleep(data.clone())))
+  });
+  host.insert_def("HVM.random", unsafe {
+    HostedDef::new_hosted(LabSet::ALL, FunctionLikeHosted(Random(data)))
+  });
+}
+
+/// One step of the SplitMix64 generator, truncated to the positive numbers of hvm-core.
+/// Returns the generated number and the next state.
+fn splitmix(seed: u64) -> (u64, u64) {
+  let seed = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+  let mut z = seed;
+  z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+  z ^= z >> 31;
+  (z & RANDOM_MASK, seed & RANDOM_MASK)
+}
@@ -1,7 +1,53 @@
+use crate::{
+  builtins::{IoPolicy, VICIOUS_CIRCLE_MSG},
+  net::net_to_hvmc::net_to_hvmc,
+  readback_hvmc,
+  term::{
+    term_to_net::{term_to_compat_net, Labels},
+    AdtEncoding, Book, Term,
+  },
+};
 use hvmc::{
-  run::{Port, Wire},
+  ast,
+  host::Host,
+  run::{Mode, Net, Port, Trg, Wire},
   stdlib::{ArcDef, AsArcDef, AsHostedDef},
 };
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// What the host definitions need to read back their arguments and to encode their results.
+#[derive(Clone)]
+pub(crate) struct ReadbackData {
+  pub book: Arc<Book>,
+  pub host: Arc<Mutex<Host>>,
+  pub labels: Arc<Labels>,
+  pub adt_encoding: AdtEncoding,
+  pub io_policy: Arc<IoPolicy>,
+}
+
+impl ReadbackData {
+  /// Reads back the tree of an argument into a term.
+  pub fn readback(&self, tree: ast::Tree) -> Term {
+    let net = ast::Net { root: tree, redexes: vec![] };
+    let (term, _errs) = readback_hvmc(&net, &self.book, &self.labels, false, self.adt_encoding);
+    term
+  }
+
+  /// Encodes a term into the net, linking it to `trg`.
+  pub fn encode_term<M: Mode>(&self, net: &mut Net<M>, term: &Term, trg: Trg) {
+    let mut labels = (*self.labels).clone();
+    let term = term_to_compat_net(term, &mut labels);
+    match net_to_hvmc(&term) {
+      Ok(term) => self.host.lock().encode_net(net, trg, &term),
+      Err(_) => {
+        // We can't report this error to the hvm program, so we print it and plug in an ERA.
+        eprintln!("{VICIOUS_CIRCLE_MSG}");
+        net.link_trg_port(trg, Port::ERA);
+      }
+    }
+  }
+}
 
 /// This utility struct implements `AsArcDef`
 /// It is a wrapper around a type, and makes it act like a function.
@@ -1,7 +1,10 @@
 use crate::term::{display::DisplayFn, Name};
+use highlight_error::highlight_error;
 use std::{
   collections::BTreeMap,
   fmt::{Display, Formatter},
+  hash::{Hash, Hasher},
+  sync::Arc,
 };
 
 pub const ERR_INDENT_SIZE: usize = 2;
@@ -27,10 +30,32 @@ pub struct DiagnosticsConfig {
 
 #[derive(Debug, Clone)]
 pub struct Diagnostic {
-  message: String,
-  severity: Severity,
+  pub message: String,
+  pub severity: Severity,
+  pub span: Option<Span>,
 }
 
+/// A file that a book was parsed from.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct SourceFile {
+  pub path: String,
+  pub code: String,
+}
+
+/// A byte range of a source file, from `ini` (inclusive) to `end` (exclusive).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Span {
+  pub file: Arc<SourceFile>,
+  pub ini: usize,
+  pub end: usize,
+}
+
+/// Where a term or pattern was written, if it was parsed from a file.
+///
+/// Locations are ignored when comparing and hashing, so the same term written in two places is still equal.
+#[derive(Debug, Clone, Default)]
+pub struct Loc(pub Option<Span>);
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum DiagnosticOrigin {
   /// An error from the relationship between multiple top-level definitions.
@@ -75,9 +100,9 @@ impl Diagnostics {
     self.add_diagnostic(err, Severity::Error, DiagnosticOrigin::Book);
   }
 
-  pub fn add_rule_error(&mut self, err: impl ToStringVerbose, def_name: Name) {
+  pub fn add_rule_error(&mut self, err: impl ToStringVerbose, def_name: Name, span: Option<Span>) {
     self.err_counter += 1;
-    self.add_diagnostic(err, Severity::Error, DiagnosticOrigin::Rule(def_name));
+    self.add_located_diagnostic(err, Severity::Error, DiagnosticOrigin::Rule(def_name), span);
   }
 
   pub fn add_inet_error(&mut self, err: impl ToStringVerbose, def_name: String) {
@@ -85,12 +110,18 @@ impl Diagnostics {
     self.add_diagnostic(err, Severity::Error, DiagnosticOrigin::Inet(def_name));
   }
 
-  pub fn add_rule_warning(&mut self, warn: impl ToStringVerbose, warn_type: WarningType, def_name: Name) {
+  pub fn add_rule_warning(
+    &mut self,
+    warn: impl ToStringVerbose,
+    warn_type: WarningType,
+    def_name: Name,
+    span: Option<Span>,
+  ) {
     let severity = self.config.warning_severity(warn_type);
     if severity == Severity::Error {
       self.err_counter += 1;
     }
-    self.add_diagnostic(warn, severity, DiagnosticOrigin::Rule(def_name));
+    self.add_located_diagnostic(warn, severity, DiagnosticOrigin::Rule(def_name), span);
   }
 
   pub fn add_book_warning(&mut self, warn: impl ToStringVerbose, warn_type: WarningType) {
@@ -102,15 +133,31 @@ impl Diagnostics {
   }
 
   pub fn add_diagnostic(&mut self, msg: impl ToStringVerbose, severity: Severity, orig: DiagnosticOrigin) {
-    let diag = Diagnostic { message: msg.to_string_verbose(self.config.verbose), severity };
+    self.add_located_diagnostic(msg, severity, orig, None)
+  }
+
+  /// Adds a diagnostic that points to the region of the source code that caused it.
+  pub fn add_located_diagnostic(
+    &mut self,
+    msg: impl ToStringVerbose,
+    severity: Severity,
+    orig: DiagnosticOrigin,
+    span: Option<Span>,
+  ) {
+    let diag = Diagnostic { message: msg.to_string_verbose(self.config.verbose), severity, span };
     self.diagnostics.entry(orig).or_default().push(diag)
   }
 
-  pub fn take_rule_err<T, E: ToStringVerbose>(&mut self, result: Result<T, E>, def_name: Name) -> Option<T> {
+  pub fn take_rule_err<T, E: ToStringVerbose>(
+    &mut self,
+    result: Result<T, E>,
+    def_name: Name,
+    span: Option<Span>,
+  ) -> Option<T> {
     match result {
       Ok(t) => Some(t),
       Err(e) => {
-        self.add_rule_error(e, def_name);
+        self.add_rule_error(e, def_name, span);
         None
       }
     }
@@ -130,6 +177,14 @@ impl Diagnostics {
     }
   }
 
+  /// Adds the diagnostics of another stage, like the ones found when reading back the result of a run.
+  pub fn extend(&mut self, other: Diagnostics) {
+    self.err_counter += other.err_counter;
+    for (orig, diags) in other.diagnostics {
+      self.diagnostics.entry(orig).or_default().extend(diags);
+    }
+  }
+
   pub fn has_severity(&self, severity: Severity) -> bool {
     self.diagnostics.values().any(|errs| errs.iter().any(|e| e.severity == severity))
   }
@@ -218,6 +273,7 @@ impl From<String> for Diagnostics {
       diagnostics: BTreeMap::from_iter([(DiagnosticOrigin::Book, vec![Diagnostic {
         message: value,
         severity: Severity::Error,
+        span: None,
       }])]),
       ..Default::default()
     }
@@ -267,10 +323,72 @@ impl Default for DiagnosticsConfig {
 
 impl Display for Diagnostic {
   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-    write!(f, "{}", self.message)
+    write!(f, "{}", self.message)?;
+    if let Some(span) = &self.span {
+      write!(f, "\n{:ERR_INDENT_SIZE$}{span}", "")?;
+    }
+    Ok(())
+  }
+}
+
+impl Span {
+  /// Returns the 1-based line and column of the start of this span.
+  pub fn line_col(&self) -> (usize, usize) {
+    let before = &self.file.code[.. self.ini];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rsplit('\n').next().unwrap_or_default().chars().count() + 1;
+    (line, col)
+  }
+}
+
+impl Display for Span {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let (line, col) = self.line_col();
+    // Only show the first line, a whole definition is too much context.
+    let end = self.file.code[self.ini .. self.end].find('\n').map_or(self.end, |i| self.ini + i);
+    writeln!(f, "at {}:{line}:{col}", self.file.path)?;
+    if use_colors() {
+      write!(f, "{}", highlight_error(self.ini, end, &self.file.code))
+    } else {
+      // Without colors, the highlighted part is marked in the line below.
+      let text = self.file.code.lines().nth(line - 1).unwrap_or_default();
+      let marks = self.file.code[self.ini .. end].trim_end().chars().count().max(1);
+      let width = line.to_string().len() + ERR_INDENT_SIZE;
+      write!(f, "{line:>width$} | {text}\n{:width$} | {:pad$}{}", "", "", "^".repeat(marks), pad = col - 1)
+    }
+  }
+}
+
+/// Whether diagnostics should be highlighted with terminal colors.
+/// Follows the `NO_COLOR` convention to disable them.
+fn use_colors() -> bool {
+  std::env::var_os("NO_COLOR").is_none()
+}
+
+impl Loc {
+  pub fn span(&self) -> Option<Span> {
+    self.0.clone()
+  }
+}
+
+impl From<Option<Span>> for Loc {
+  fn from(span: Option<Span>) -> Self {
+    Self(span)
+  }
+}
+
+impl PartialEq for Loc {
+  fn eq(&self, _: &Self) -> bool {
+    true
   }
 }
 
+impl Eq for Loc {}
+
+impl Hash for Loc {
+  fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
 impl ToStringVerbose for &str {
   fn to_string_verbose(&self, _verbose: bool) -> String {
     self.to_string()
@@ -1,7 +1,12 @@
 #![feature(box_patterns)]
 #![feature(let_chains)]
 
-use builtins::{create_host, CORE_BUILTINS_USES};
+use builtins::{
+  create_host,
+  exit::{uses_exit, ExitStatus},
+  host_fns::HostFns,
+  IoPolicy, CORE_BUILTINS_USES,
+};
 use diagnostics::{DiagnosticOrigin, Diagnostics, DiagnosticsConfig, Severity, WarningType};
 use hvmc::{
   ast::Net,
@@ -15,14 +20,22 @@ use hvmc_net::{
 };
 use net::{hvmc_to_net::hvmc_to_net, net_to_hvmc::nets_to_hvmc};
 use parking_lot::Mutex;
-use std::{sync::Arc, time::Instant};
-use term::{book_to_nets, net_to_term::net_to_term, term_to_net::Labels, AdtEncoding, Book, Ctx, Name, Term};
+use std::{collections::HashMap, sync::Arc, time::Instant};
+use term::{
+  book_to_nets, check::type_check::Type, net_to_term::net_to_term, term_to_net::Labels, AdtEncoding, Book,
+  Ctx, Name, Term,
+};
 
 pub mod builtins;
 pub mod diagnostics;
 pub mod hvmc_net;
+#[cfg(feature = "lsp")]
+pub mod lsp;
 pub mod net;
+pub mod program;
+pub mod repl;
 pub mod term;
+pub mod test_runner;
 
 pub use term::load_book::load_file_to_book;
 
@@ -84,6 +97,7 @@ pub fn compile_book(
   if opts.prune {
     let mut prune_entrypoints = vec![book.hvmc_entrypoint().to_string()];
     let mut builtin_uses = CORE_BUILTINS_USES.concat().iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    builtin_uses.extend(book.host_builtins.values().flatten().map(|x| x.to_string()));
     prune_entrypoints.append(&mut builtin_uses);
     core_book.prune(&prune_entrypoints);
   }
@@ -99,24 +113,11 @@ pub fn desugar_book(
 ) -> Result<Diagnostics, Diagnostics> {
   let mut ctx = Ctx::new(book, diagnostics_cfg);
 
-  ctx.check_shared_names();
-
-  ctx.set_entrypoint();
-
-  ctx.book.encode_adts(opts.adt_encoding);
-
-  ctx.fix_match_defs()?;
-
-  ctx.apply_args(args)?;
-
-  ctx.book.encode_builtins();
-
-  ctx.resolve_refs()?;
-
-  ctx.fix_match_terms()?;
-  ctx.desugar_match_defs()?;
+  desugar_definitions(&mut ctx, &opts, args)?;
 
-  ctx.check_unbound_vars()?;
+  if opts.type_check {
+    ctx.check_types()?;
+  }
 
   // Auto match linearization
   match opts.linearize_matches {
@@ -154,21 +155,84 @@ pub fn desugar_book(
   if !ctx.info.has_errors() { Ok(ctx.info) } else { Err(ctx.info) }
 }
 
+/// Infers the type of each definition of a book, without compiling it.
+pub fn infer_book_types(
+  book: &mut Book,
+  opts: CompileOpts,
+  diagnostics_cfg: DiagnosticsConfig,
+) -> Result<(HashMap<Name, Type>, Diagnostics), Diagnostics> {
+  let mut ctx = Ctx::new(book, diagnostics_cfg);
+  desugar_definitions(&mut ctx, &opts, None)?;
+  let types = ctx.infer_types()?;
+  Ok((types, ctx.info))
+}
+
+/// Runs the passes that turn the pattern matching rules of each definition into a single term,
+/// checking the names they refer to.
+fn desugar_definitions(
+  ctx: &mut Ctx,
+  opts: &CompileOpts,
+  args: Option<Vec<Term>>,
+) -> Result<(), Diagnostics> {
+  ctx.check_shared_names();
+
+  ctx.set_entrypoint();
+
+  ctx.book.desugar_do_blocks();
+
+  ctx.book.encode_adts(opts.adt_encoding);
+
+  ctx.fix_match_defs()?;
+
+  ctx.apply_args(args)?;
+
+  // After applying the arguments, which can also have infix expressions.
+  ctx.book.desugar_infix();
+
+  ctx.book.encode_builtins();
+  ctx.book.encode_typed_ops();
+
+  ctx.resolve_refs()?;
+
+  ctx.fix_match_terms()?;
+  ctx.desugar_match_defs()?;
+
+  ctx.check_unbound_vars()
+}
+
 pub fn run_book(
+  book: Book,
+  max_memory: Option<usize>,
+  run_opts: RunOpts,
+  compile_opts: CompileOpts,
+  diagnostics_cfg: DiagnosticsConfig,
+  args: Option<Vec<Term>>,
+) -> Result<(Term, RunInfo), Diagnostics> {
+  // TODO: Printing should be taken care by the cli module, but we'd
+  // like to print any warnings before running so that the user can
+  // cancel the run if a problem is detected.
+  run_book_with_hook(book, max_memory, run_opts, compile_opts, diagnostics_cfg, args, |diagnostics| {
+    eprint!("{diagnostics}")
+  })
+}
+
+/// Like [`run_book`], but instead of printing the compilation diagnostics, passes them to `on_compiled`
+/// before running the program.
+pub fn run_book_with_hook(
   mut book: Book,
   max_memory: Option<usize>,
   run_opts: RunOpts,
   compile_opts: CompileOpts,
   diagnostics_cfg: DiagnosticsConfig,
   args: Option<Vec<Term>>,
+  on_compiled: impl FnOnce(Diagnostics),
 ) -> Result<(Term, RunInfo), Diagnostics> {
+  run_opts.check()?;
+  run_opts.host_fns.declare(&mut book)?;
   let CompileResult { core_book, labels, diagnostics } =
     compile_book(&mut book, compile_opts.clone(), diagnostics_cfg, args)?;
 
-  // TODO: Printing should be taken care by the cli module, but we'd
-  // like to print any warnings before running so that the user can
-  // cancel the run if a problem is detected.
-  eprint!("{diagnostics}");
+  on_compiled(diagnostics);
 
   // Turn the book into an Arc so that we can use it for logging, debugging, etc.
   // from anywhere else in the program
@@ -176,20 +240,44 @@ pub fn run_book(
   let book = Arc::new(book);
   let labels = Arc::new(labels);
 
+  // `HVM.exit` can't stop the other threads, so a program that exits is run on a single core.
+  let run_opts = RunOpts { single_core: run_opts.single_core || uses_exit(&core_book), ..run_opts };
   let debug_hook = run_opts.debug_hook(&book, &labels);
 
-  let host = create_host(book.clone(), labels.clone(), compile_opts.adt_encoding);
+  let exit_status = ExitStatus::default();
+  let io_policy = run_opts.io_policy.clone();
+  let host = create_host(
+    book.clone(),
+    labels.clone(),
+    compile_opts.adt_encoding,
+    io_policy,
+    &run_opts.host_fns,
+    exit_status.clone(),
+  );
   host.lock().insert_book(&core_book);
 
-  let (res_lnet, stats) = run_compiled(host, max_memory, run_opts, debug_hook, book.hvmc_entrypoint());
+  let (res_lnet, stats) =
+    run_compiled(host, max_memory, &run_opts, debug_hook, book.hvmc_entrypoint(), &exit_status);
 
-  let (res_term, diagnostics) =
+  let (mut res_term, mut diagnostics) =
     readback_hvmc(&res_lnet, &book, &labels, run_opts.linear, compile_opts.adt_encoding);
+  if run_opts.typed_nums {
+    res_term.resugar_typed_nums();
+  }
+  check_max_rewrites(&stats, &mut diagnostics);
 
-  let info = RunInfo { stats, diagnostics, net: res_lnet, book, labels };
+  let info = RunInfo { stats, diagnostics, net: res_lnet, book, labels, exit_code: exit_status.get() };
   Ok((res_term, info))
 }
 
+/// Warns when a run was stopped for reaching the maximum amount of rewrites,
+/// since its result is then only partially reduced.
+pub(crate) fn check_max_rewrites(stats: &RunStats, diagnostics: &mut Diagnostics) {
+  if stats.reached_max_rewrites {
+    diagnostics.add_diagnostic("Exceeded max rwts", Severity::Warning, DiagnosticOrigin::Book);
+  }
+}
+
 /// Utility function to count the amount of nodes in an hvm-core AST net
 pub fn count_nodes<'l>(net: &'l hvmc::ast::Net) -> usize {
   let mut visit: Vec<&'l hvmc::ast::Tree> = vec![&net.root];
@@ -210,12 +298,16 @@ pub fn count_nodes<'l>(net: &'l hvmc::ast::Net) -> usize {
   count
 }
 
+/// Runs the entrypoint of a host until it's fully reduced, the program exits or the rewrite limit is reached.
+///
+/// A run with a rewrite limit is always single-core. Panics if the options don't pass [`RunOpts::check`].
 pub fn run_compiled(
   host: Arc<Mutex<Host>>,
   mem_size: Option<usize>,
-  run_opts: RunOpts,
+  run_opts: &RunOpts,
   hook: Option<impl FnMut(&Net)>,
   entrypoint: &str,
+  exit_status: &ExitStatus,
 ) -> (Net, RunStats) {
   let heap = Heap::new(mem_size).expect("memory allocation failed");
   let mut root = DynNet::new(&heap, run_opts.lazy_mode);
@@ -225,27 +317,28 @@ pub fn run_compiled(
 
     let start_time = Instant::now();
 
+    let mut reached_max_rewrites = false;
     match (hook, run_opts.lazy_mode, max_rwts) {
-      (Some(hook), true, _) => normal_lazy_debug(hook, host.clone(), root),
-      (Some(hook), false, _) => normal_strict_debug(hook, host.clone(), root),
-      (_, lazy_mode, Some(max_rwts)) if !lazy_mode => {
-        if !run_opts.single_core {
-          panic!("Parallel mode does not yet support rewrite limit");
-        }
-        normal_strict_rwts(max_rwts, root);
+      (Some(hook), true, _) => normal_lazy_debug(hook, host.clone(), root, exit_status),
+      (Some(hook), false, _) => normal_strict_debug(hook, host.clone(), root, exit_status),
+      // The rewrites are only counted on a single core.
+      (_, false, Some(max_rwts)) => {
+        reached_max_rewrites = normal_strict_rwts(max_rwts, root, exit_status);
       },
       (_, true, Some(_)) => {
         panic!("Lazy mode does not yet support rewrite limit");
       },
       (_, false, None) if !run_opts.single_core => root.parallel_normal(),
-      _ => root.normal(),
+      (_, true, None) => normal_lazy(root, exit_status),
+      (_, false, None) => normal_strict(root, exit_status),
     }
 
     let elapsed = start_time.elapsed().as_secs_f64();
 
     let net = host.lock().readback(root);
 
-    let stats = RunStats { rewrites: root.rwts, used: count_nodes(&net), run_time: elapsed };
+    let stats =
+      RunStats { rewrites: root.rwts, used: count_nodes(&net), run_time: elapsed, reached_max_rewrites };
     (net, stats)
   })
 }
@@ -271,13 +364,38 @@ pub fn readback_hvmc(
   (term, diags)
 }
 
+/// Reduces the net to normal form, stopping if the program calls `HVM.exit`.
+fn normal_strict<M: hvmc::run::Mode>(root: &mut hvmc::run::Net<M>, exit_status: &ExitStatus) {
+  root.expand();
+  while !root.redexes.is_empty() && !exit_status.has_exited() {
+    root.reduce(usize::MAX);
+  }
+}
+
+/// Reduces the net to normal form from its root, stopping if the program calls `HVM.exit`.
+fn normal_lazy<M: hvmc::run::Mode>(root: &mut hvmc::run::Net<M>, exit_status: &ExitStatus) {
+  let mut visit = vec![hvmc::run::Port::new_var(root.root.addr())];
+  while let Some(prev) = visit.pop()
+    && !exit_status.has_exited()
+  {
+    let next = root.weak_normal(prev, root.root.clone());
+    if next.is_full_node() {
+      visit.push(hvmc::run::Port::new_var(next.addr()));
+      visit.push(hvmc::run::Port::new_var(next.addr().other_half()));
+    }
+  }
+}
+
 fn normal_lazy_debug<M: hvmc::run::Mode>(
   mut hook: impl FnMut(&Net),
   host: Arc<Mutex<Host>>,
   root: &mut hvmc::run::Net<M>,
+  exit_status: &ExitStatus,
 ) {
   let mut visit = vec![hvmc::run::Port::new_var(root.root.addr())];
-  while let Some(prev) = visit.pop() {
+  while let Some(prev) = visit.pop()
+    && !exit_status.has_exited()
+  {
     let next = root.weak_normal(prev, root.root.clone());
 
     let readback = host.lock().readback(root);
@@ -294,30 +412,36 @@ fn normal_strict_debug<M: hvmc::run::Mode>(
   mut hook: impl FnMut(&Net),
   host: Arc<Mutex<Host>>,
   root: &mut hvmc::run::Net<M>,
+  exit_status: &ExitStatus,
 ) {
-  while !root.redexes.is_empty() {
+  while !root.redexes.is_empty() && !exit_status.has_exited() {
     let readback = host.lock().readback(root);
     hook(&readback);
     root.reduce(1);
   }
 }
 
-fn normal_strict_rwts<M: hvmc::run::Mode>(mut max_rwts: usize, root: &mut hvmc::run::Net<M>) {
+/// Reduces the net like [`normal_strict`], but stops once it has done `max_rwts` rewrites.
+/// Returns whether it was stopped before reaching the normal form.
+fn normal_strict_rwts<M: hvmc::run::Mode>(
+  max_rwts: usize,
+  root: &mut hvmc::run::Net<M>,
+  exit_status: &ExitStatus,
+) -> bool {
+  let limit = root.rwts.total().saturating_add(max_rwts as u64);
   root.expand();
-  while !root.redexes.is_empty() {
-    let old_rwts = root.rwts.total();
-    root.reduce(max_rwts);
-    let delta_rwts = root.rwts.total() - old_rwts;
-    if (max_rwts as u64) < delta_rwts {
-      eprintln!("Warning: Exceeded max rwts");
-      break;
+  while !root.redexes.is_empty() && !exit_status.has_exited() {
+    let left = limit.saturating_sub(root.rwts.total());
+    if left == 0 {
+      return true;
     }
-    max_rwts -= delta_rwts as usize;
+    root.reduce(left as usize);
     root.expand();
   }
+  false
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct RunOpts {
   pub single_core: bool,
   pub debug: bool,
@@ -326,6 +450,11 @@ pub struct RunOpts {
   pub max_memory: Option<usize>,
   pub max_rewrites: Option<usize>,
   pub pretty: bool,
+  /// Reads back the numbers whose bits encode a negative or a float number as one.
+  pub typed_nums: bool,
+  pub io_policy: IoPolicy,
+  /// Rust functions that the program can call, besides the builtins.
+  pub host_fns: HostFns,
 }
 
 impl RunOpts {
@@ -333,6 +462,14 @@ impl RunOpts {
     Self { lazy_mode: true, single_core: true, ..Self::default() }
   }
 
+  /// Checks that the options can be used together.
+  pub fn check(&self) -> Result<(), Diagnostics> {
+    if self.lazy_mode && self.max_rewrites.is_some() {
+      return Err("Lazy mode does not support a maximum amount of rewrites.".to_string().into());
+    }
+    Ok(())
+  }
+
   fn debug_hook<'a>(&'a self, book: &'a Book, labels: &'a Labels) -> Option<impl FnMut(&Net) + 'a> {
     self.debug.then_some({
       |net: &_| {
@@ -399,6 +536,9 @@ pub struct CompileOpts {
   pub pre_reduce_rewrites: u64,
 
   pub pre_reduce_skip: Vec<Name>,
+
+  /// Enables [term::check::type_check].
+  pub type_check: bool,
 }
 
 impl CompileOpts {
@@ -424,6 +564,7 @@ impl CompileOpts {
       adt_encoding: self.adt_encoding,
       pre_reduce_memory: self.pre_reduce_memory,
       pre_reduce_rewrites: self.pre_reduce_rewrites,
+      type_check: self.type_check,
       ..Self::default()
     }
   }
@@ -466,6 +607,7 @@ impl Default for CompileOpts {
       pre_reduce_memory: None,
       pre_reduce_rewrites: MAX_REWRITES_DEFAULT,
       pre_reduce_skip: vec![],
+      type_check: false,
     }
   }
 }
@@ -482,12 +624,33 @@ pub struct RunInfo {
   pub net: Net,
   pub book: Arc<Book>,
   pub labels: Arc<Labels>,
+  /// The status code that `HVM.exit` was called with, if the program called it.
+  /// The result of a program that exited is not meaningful.
+  pub exit_code: Option<i32>,
 }
 
 pub struct RunStats {
   pub rewrites: Rewrites,
   pub used: usize,
   pub run_time: f64,
+  /// Whether the run was stopped at the maximum amount of rewrites, before reaching the normal form.
+  pub reached_max_rewrites: bool,
+}
+
+impl std::fmt::Display for RunStats {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let total_rewrites = self.rewrites.total();
+    let rps = total_rewrites as f64 / self.run_time / 1_000_000.0;
+    writeln!(f, "RWTS   : {}", total_rewrites)?;
+    writeln!(f, "- ANNI : {}", self.rewrites.anni)?;
+    writeln!(f, "- COMM : {}", self.rewrites.comm)?;
+    writeln!(f, "- ERAS : {}", self.rewrites.eras)?;
+    writeln!(f, "- DREF : {}", self.rewrites.dref)?;
+    writeln!(f, "- OPER : {}", self.rewrites.oper)?;
+    writeln!(f, "TIME   : {:.3} s", self.run_time)?;
+    writeln!(f, "RPS    : {:.3} m", rps)?;
+    write!(f, "SIZE   : {} nodes", self.used)
+  }
 }
 
 fn maybe_grow<R, F>(f: F) -> R
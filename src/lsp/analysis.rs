@@ -0,0 +1,285 @@
+use crate::{
+  desugar_book,
+  diagnostics::{DiagnosticOrigin, DiagnosticsConfig, Loc, Severity, SourceFile, Span},
+  maybe_grow,
+  term::{
+    load_book::do_parse_book,
+    parser::{NameRole, ParsedName, TermParser},
+    Book, Definition, Name, Rule, Term,
+  },
+  CompileOpts, ENTRY_POINT, HVM1_ENTRY_POINT,
+};
+use itertools::Itertools;
+use std::{path::Path, sync::Arc};
+
+/// What is known about a source file open in the editor.
+pub struct Analysis {
+  pub file: Arc<SourceFile>,
+  /// The parsed program, including the modules it imports.
+  pub book: Book,
+  pub diagnostics: Vec<FileDiagnostic>,
+  /// The definition, constructor and datatype names written in the file, in order.
+  pub occurrences: Vec<Occurrence>,
+}
+
+/// A diagnostic located in the analysed file.
+#[derive(Debug, Clone)]
+pub struct FileDiagnostic {
+  pub message: String,
+  pub severity: Severity,
+  pub ini: usize,
+  pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameKind {
+  Def,
+  Ctr,
+  Adt,
+}
+
+/// A place in the file where a top-level name is written.
+#[derive(Debug, Clone)]
+pub struct Occurrence {
+  pub name: Name,
+  pub kind: NameKind,
+  pub ini: usize,
+  pub end: usize,
+  /// Whether this is where the name is declared, instead of a reference to it.
+  pub is_declaration: bool,
+}
+
+impl Analysis {
+  /// Parses and checks the code of a file, as it would be by `hvml check`.
+  pub fn new(code: &str, path: &Path) -> Self {
+    let file = Arc::new(SourceFile { path: path.display().to_string(), code: code.to_string() });
+
+    // Parse the file by itself first, to know where syntax errors are and where its names are written.
+    let names = match TermParser::new_source_book_with_names(&file, Book::builtins()) {
+      Ok((_, names)) => names,
+      Err((msg, idx)) => {
+        let ini = idx.min(code.len());
+        let end = code[ini ..].chars().next().map_or(ini, |c| ini + c.len_utf8());
+        let diagnostic = FileDiagnostic { message: first_line(&msg), severity: Severity::Error, ini, end };
+        return Self { file, book: Book::default(), diagnostics: vec![diagnostic], occurrences: vec![] };
+      }
+    };
+
+    // Errors in imported modules are shown at the start of the file.
+    let book = match do_parse_book(code, path) {
+      Ok(book) => book,
+      Err(msg) => {
+        let message = strip_ansi(&msg).trim().to_string();
+        let diagnostic = FileDiagnostic { message, severity: Severity::Error, ini: 0, end: 0 };
+        return Self { file, book: Book::default(), diagnostics: vec![diagnostic], occurrences: vec![] };
+      }
+    };
+
+    let occurrences = index_names(&book, &file, names);
+    let mut analysis = Self { file, book, diagnostics: vec![], occurrences };
+    analysis.check();
+    analysis
+  }
+
+  /// Runs the desugaring passes over the book, collecting their diagnostics.
+  fn check(&mut self) {
+    let mut book = self.book.clone();
+    let mut diagnostics_cfg = DiagnosticsConfig::default_strict();
+
+    // Files that are only imported as modules have no main function.
+    let has_main =
+      [ENTRY_POINT, HVM1_ENTRY_POINT].iter().any(|main| book.defs.contains_key(&Name::new(*main)));
+    if book.entrypoint.is_none() && !has_main {
+      let rules = vec![Rule { pats: vec![], guard: None, body: Term::era(), loc: Loc::default() }];
+      let name = Name::new(ENTRY_POINT);
+      book.defs.insert(name.clone(), Definition { name, rules, builtin: false });
+      diagnostics_cfg.unused_definition = Severity::Allow;
+    }
+
+    let diagnostics = match desugar_book(&mut book, CompileOpts::default_strict(), diagnostics_cfg, None) {
+      Ok(diagnostics) | Err(diagnostics) => diagnostics,
+    };
+
+    for (origin, diags) in diagnostics.diagnostics {
+      for diag in diags {
+        if diag.severity == Severity::Allow {
+          continue;
+        }
+        let span = diag.span.or_else(|| match &origin {
+          DiagnosticOrigin::Rule(name) => self.book.defs.get(name).and_then(Definition::span),
+          _ => None,
+        });
+        let (ini, end, message) = match span {
+          Some(span) if span.file.path == self.file.path => {
+            // Only the first line of a rule is highlighted, like in the command line output.
+            let end = self.file.code[span.ini .. span.end].find('\n').map_or(span.end, |i| span.ini + i);
+            (span.ini, end, diag.message)
+          }
+          Some(span) => (0, 0, format!("In {} :\n{}", span.file.path, diag.message)),
+          None => (0, 0, diag.message),
+        };
+        self.diagnostics.push(FileDiagnostic { message, severity: diag.severity, ini, end });
+      }
+    }
+  }
+
+  /// The name written at the given byte offset of the file, if any.
+  pub fn occurrence_at(&self, offset: usize) -> Option<&Occurrence> {
+    self.occurrences.iter().find(|occ| occ.ini <= offset && offset <= occ.end)
+  }
+
+  /// The occurrences of a name in the file.
+  pub fn references<'a>(
+    &'a self,
+    name: &'a Name,
+    kind: NameKind,
+    include_declaration: bool,
+  ) -> impl Iterator<Item = &'a Occurrence> {
+    self.occurrences.iter().filter(move |occ| {
+      occ.name == *name && occ.kind == kind && (include_declaration || !occ.is_declaration)
+    })
+  }
+
+  /// Where a name is declared, in this file or in one of its modules.
+  pub fn declaration(&self, name: &Name, kind: NameKind) -> Option<Span> {
+    let (span, role) = match kind {
+      NameKind::Def => (self.book.defs.get(name)?.span()?, NameRole::Def),
+      NameKind::Ctr => (self.book.adts.get(self.book.ctrs.get(name)?)?.span.clone()?, NameRole::Ctr),
+      NameKind::Adt => (self.book.adts.get(name)?.span.clone()?, NameRole::Adt),
+    };
+    // Names are written without their module prefix inside their module.
+    let local_name = name.rsplit('.').next().unwrap();
+    let (_, names) = TermParser::new_source_book_with_names(&span.file, Book::default()).ok()?;
+    let decl = names.into_iter().find(|parsed| {
+      parsed.role == role
+        && span.ini <= parsed.range.start
+        && parsed.range.end <= span.end
+        && (parsed.name == *name || parsed.name == local_name)
+    })?;
+    Some(Span { file: span.file.clone(), ini: decl.range.start, end: decl.range.end })
+  }
+
+  /// A description of a name, in markdown.
+  pub fn hover(&self, name: &Name, kind: NameKind) -> Option<String> {
+    match kind {
+      NameKind::Ctr => {
+        let adt_name = self.book.ctrs.get(name)?;
+        let ctr = ctr_signature(name, &self.book.adts.get(adt_name)?.ctrs[name]);
+        Some(format!("```\n{ctr}\n```\nConstructor of the datatype `{adt_name}`."))
+      }
+      NameKind::Adt => {
+        let adt = self.book.adts.get(name)?;
+        let mut ctrs = adt.ctrs.iter().map(|(ctr, fields)| ctr_signature(ctr, fields));
+        Some(format!("```\ndata {name} = {}\n```", ctrs.join(" | ")))
+      }
+      NameKind::Def => {
+        let def = self.book.defs.get(name)?;
+        let arity = def.rules.first()?.arity();
+        Some(format!("```\n{name}\n```\nDefinition with {arity} argument(s)."))
+      }
+    }
+  }
+
+  /// The names that can be referred to in the file.
+  pub fn completions(&self) -> impl Iterator<Item = (&Name, NameKind)> {
+    let defs = self.book.defs.values().filter(|def| !def.builtin).map(|def| (&def.name, NameKind::Def));
+    let ctrs = self.book.ctrs.keys().map(|ctr| (ctr, NameKind::Ctr));
+    defs.chain(ctrs)
+  }
+}
+
+/// Finds where the top-level names are written in a file.
+///
+/// Declarations and the names in patterns come from the parser, and the references in terms from their locations.
+fn index_names(book: &Book, file: &SourceFile, names: Vec<ParsedName>) -> Vec<Occurrence> {
+  let mut occurrences = vec![];
+
+  for ParsedName { name, role, range } in names {
+    let (kind, is_declaration) = match role {
+      NameRole::Def => (NameKind::Def, true),
+      NameRole::Adt => (NameKind::Adt, true),
+      NameRole::Ctr => (NameKind::Ctr, true),
+      NameRole::Pattern if book.ctrs.contains_key(&name) => (NameKind::Ctr, false),
+      NameRole::Pattern => continue,
+    };
+    occurrences.push(Occurrence { name, kind, ini: range.start, end: range.end, is_declaration });
+  }
+
+  for def in book.defs.values() {
+    for rule in &def.rules {
+      if !rule.loc.0.as_ref().is_some_and(|span| span.file.path == file.path) {
+        continue;
+      }
+      let mut scope = rule.pats.iter().flat_map(|pat| pat.binds().flatten().cloned()).collect::<Vec<_>>();
+      for term in rule.terms() {
+        index_references(term, book, file, &mut scope, &mut occurrences);
+      }
+    }
+  }
+
+  occurrences.sort_by_key(|occ| occ.ini);
+  occurrences
+}
+
+/// Finds the references to definitions and constructors in a term, skipping the variables that shadow them.
+fn index_references(
+  term: &Term,
+  book: &Book,
+  file: &SourceFile,
+  scope: &mut Vec<Name>,
+  occurrences: &mut Vec<Occurrence>,
+) {
+  maybe_grow(|| {
+    if let Term::Var { nam, loc } = term
+      && let Some(span) = loc.span()
+      && span.file.path == file.path
+      && !scope.contains(nam)
+    {
+      let kind = if book.ctrs.contains_key(nam) {
+        Some(NameKind::Ctr)
+      } else if book.defs.contains_key(nam) {
+        Some(NameKind::Def)
+      } else {
+        None
+      };
+      if let Some(kind) = kind {
+        occurrences.push(Occurrence {
+          name: nam.clone(),
+          kind,
+          ini: span.ini,
+          end: span.end,
+          is_declaration: false,
+        });
+      }
+    }
+
+    for (child, binds) in term.children_with_binds() {
+      let scope_len = scope.len();
+      scope.extend(binds.flatten().cloned());
+      index_references(child, book, file, scope, occurrences);
+      scope.truncate(scope_len);
+    }
+  })
+}
+
+fn ctr_signature(ctr: &Name, fields: &[Name]) -> String {
+  if fields.is_empty() { ctr.to_string() } else { format!("({ctr} {})", fields.iter().join(" ")) }
+}
+
+/// The first line of a message, without terminal colors.
+fn first_line(msg: &str) -> String {
+  strip_ansi(msg).trim().lines().next().unwrap_or_default().to_string()
+}
+
+fn strip_ansi(msg: &str) -> String {
+  let mut out = String::new();
+  let mut chars = msg.chars();
+  while let Some(c) = chars.next() {
+    if c == '\x1b' {
+      chars.by_ref().find(|&c| c == 'm');
+    } else {
+      out.push(c);
+    }
+  }
+  out
+}
@@ -0,0 +1,241 @@
+//! A language server for hvm-lang programs, speaking the Language Server Protocol over stdio.
+
+use crate::diagnostics::{Severity, Span};
+use analysis::{Analysis, NameKind, Occurrence};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+  notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument, Notification as _,
+    PublishDiagnostics,
+  },
+  request::{Completion, GotoDefinition, HoverRequest, References, Request as _},
+  CompletionItem, CompletionItemKind, CompletionOptions, CompletionResponse, Diagnostic, DiagnosticSeverity,
+  GotoDefinitionResponse, Hover, HoverContents, HoverProviderCapability, Location, MarkupContent, MarkupKind,
+  OneOf, Position, PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentPositionParams,
+  TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use std::{collections::HashMap, error::Error, path::PathBuf};
+
+pub mod analysis;
+
+type LspResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Runs the language server until the client asks it to exit.
+pub fn run() -> LspResult<()> {
+  let (connection, io_threads) = Connection::stdio();
+
+  let capabilities = ServerCapabilities {
+    text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+    definition_provider: Some(OneOf::Left(true)),
+    references_provider: Some(OneOf::Left(true)),
+    hover_provider: Some(HoverProviderCapability::Simple(true)),
+    completion_provider: Some(CompletionOptions::default()),
+    ..Default::default()
+  };
+  connection.initialize(serde_json::to_value(capabilities)?)?;
+
+  let mut server = Server { connection, documents: HashMap::new() };
+  server.main_loop()?;
+  drop(server);
+
+  io_threads.join()?;
+  Ok(())
+}
+
+struct Server {
+  connection: Connection,
+  /// The analysis of each open file, by its uri.
+  documents: HashMap<Url, Analysis>,
+}
+
+impl Server {
+  fn main_loop(&mut self) -> LspResult<()> {
+    while let Ok(msg) = self.connection.receiver.recv() {
+      match msg {
+        Message::Request(req) => {
+          if self.connection.handle_shutdown(&req)? {
+            return Ok(());
+          }
+          let response = self.handle_request(req);
+          self.connection.sender.send(Message::Response(response))?;
+        }
+        Message::Notification(not) => self.handle_notification(not)?,
+        Message::Response(_) => {}
+      }
+    }
+    Ok(())
+  }
+
+  fn handle_request(&self, req: Request) -> Response {
+    match req.method.as_str() {
+      GotoDefinition::METHOD => self.respond::<GotoDefinition>(req, Self::goto_definition),
+      References::METHOD => self.respond::<References>(req, Self::references),
+      HoverRequest::METHOD => self.respond::<HoverRequest>(req, Self::hover),
+      Completion::METHOD => self.respond::<Completion>(req, Self::completion),
+      _ => Response::new_err(
+        req.id,
+        ErrorCode::MethodNotFound as i32,
+        format!("Unknown method '{}'", req.method),
+      ),
+    }
+  }
+
+  fn respond<R: lsp_types::request::Request>(
+    &self,
+    req: Request,
+    handler: impl FnOnce(&Self, R::Params) -> R::Result,
+  ) -> Response {
+    let id = req.id.clone();
+    match req.extract::<R::Params>(R::METHOD) {
+      Ok((id, params)) => Response::new_ok(id, handler(self, params)),
+      Err(err) => Response::new_err(id, ErrorCode::InvalidParams as i32, err.to_string()),
+    }
+  }
+
+  fn handle_notification(&mut self, not: Notification) -> LspResult<()> {
+    match not.method.as_str() {
+      DidOpenTextDocument::METHOD => {
+        let params = not.extract::<lsp_types::DidOpenTextDocumentParams>(DidOpenTextDocument::METHOD)?;
+        self.update(params.text_document.uri, &params.text_document.text)?;
+      }
+      DidChangeTextDocument::METHOD => {
+        let params = not.extract::<lsp_types::DidChangeTextDocumentParams>(DidChangeTextDocument::METHOD)?;
+        // With full synchronization, the last change has the whole text of the document.
+        if let Some(change) = params.content_changes.into_iter().last() {
+          self.update(params.text_document.uri, &change.text)?;
+        }
+      }
+      DidSaveTextDocument::METHOD => {
+        // The modules imported by the open files may have changed.
+        let documents = self.documents.iter().map(|(uri, doc)| (uri.clone(), doc.file.code.clone()));
+        for (uri, code) in documents.collect::<Vec<_>>() {
+          self.update(uri, &code)?;
+        }
+      }
+      DidCloseTextDocument::METHOD => {
+        let params = not.extract::<lsp_types::DidCloseTextDocumentParams>(DidCloseTextDocument::METHOD)?;
+        self.documents.remove(&params.text_document.uri);
+        self.publish_diagnostics(params.text_document.uri, vec![])?;
+      }
+      _ => {}
+    }
+    Ok(())
+  }
+
+  /// Analyses the new text of a document and publishes its diagnostics.
+  fn update(&mut self, uri: Url, code: &str) -> LspResult<()> {
+    let path = uri.to_file_path().unwrap_or_else(|_| PathBuf::from(uri.path()));
+    let analysis = Analysis::new(code, &path);
+
+    let diagnostics = analysis
+      .diagnostics
+      .iter()
+      .map(|diag| Diagnostic {
+        range: range(code, diag.ini, diag.end),
+        severity: Some(match diag.severity {
+          Severity::Error => DiagnosticSeverity::ERROR,
+          Severity::Warning | Severity::Allow => DiagnosticSeverity::WARNING,
+        }),
+        source: Some("hvml".to_string()),
+        message: diag.message.clone(),
+        ..Default::default()
+      })
+      .collect();
+
+    self.documents.insert(uri.clone(), analysis);
+    self.publish_diagnostics(uri, diagnostics)
+  }
+
+  fn publish_diagnostics(&self, uri: Url, diagnostics: Vec<Diagnostic>) -> LspResult<()> {
+    let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+    let not = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+    self.connection.sender.send(Message::Notification(not))?;
+    Ok(())
+  }
+
+  /// The document and the name under the cursor.
+  fn occurrence_at(&self, params: &TextDocumentPositionParams) -> Option<(&Analysis, &Occurrence)> {
+    let analysis = self.documents.get(&params.text_document.uri)?;
+    let offset = offset(&analysis.file.code, params.position);
+    Some((analysis, analysis.occurrence_at(offset)?))
+  }
+
+  fn goto_definition(&self, params: lsp_types::GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+    let params = params.text_document_position_params;
+    let (analysis, occ) = self.occurrence_at(&params)?;
+    let span = analysis.declaration(&occ.name, occ.kind)?;
+    Some(GotoDefinitionResponse::Scalar(span_location(&params.text_document.uri, analysis, &span)?))
+  }
+
+  fn references(&self, params: lsp_types::ReferenceParams) -> Option<Vec<Location>> {
+    let include_declaration = params.context.include_declaration;
+    let params = params.text_document_position;
+    let (analysis, occ) = self.occurrence_at(&params)?;
+    let code = &analysis.file.code;
+    let references = analysis.references(&occ.name, occ.kind, include_declaration);
+    Some(
+      references
+        .map(|r| Location::new(params.text_document.uri.clone(), range(code, r.ini, r.end)))
+        .collect(),
+    )
+  }
+
+  fn hover(&self, params: lsp_types::HoverParams) -> Option<Hover> {
+    let (analysis, occ) = self.occurrence_at(&params.text_document_position_params)?;
+    let value = analysis.hover(&occ.name, occ.kind)?;
+    Some(Hover {
+      contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value }),
+      range: Some(range(&analysis.file.code, occ.ini, occ.end)),
+    })
+  }
+
+  fn completion(&self, params: lsp_types::CompletionParams) -> Option<CompletionResponse> {
+    let analysis = self.documents.get(&params.text_document_position.text_document.uri)?;
+    let items = analysis.completions().map(|(name, kind)| CompletionItem {
+      label: name.to_string(),
+      kind: Some(match kind {
+        NameKind::Def => CompletionItemKind::FUNCTION,
+        NameKind::Ctr => CompletionItemKind::CONSTRUCTOR,
+        NameKind::Adt => CompletionItemKind::STRUCT,
+      }),
+      ..Default::default()
+    });
+    Some(CompletionResponse::Array(items.collect()))
+  }
+}
+
+/// The location of a span, which may be in the document itself or in one of the modules it imports.
+fn span_location(uri: &Url, analysis: &Analysis, span: &Span) -> Option<Location> {
+  let uri = if span.file.path == analysis.file.path {
+    uri.clone()
+  } else {
+    Url::from_file_path(&span.file.path).ok()?
+  };
+  Some(Location::new(uri, range(&span.file.code, span.ini, span.end)))
+}
+
+fn range(code: &str, ini: usize, end: usize) -> Range {
+  Range::new(position(code, ini), position(code, end))
+}
+
+/// Converts a byte offset into a line and an utf-16 column, as used by the protocol.
+fn position(code: &str, offset: usize) -> Position {
+  let before = &code[.. offset];
+  let line = before.matches('\n').count();
+  let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+  let character = before[line_start ..].encode_utf16().count();
+  Position::new(line as u32, character as u32)
+}
+
+/// Converts a protocol position into a byte offset.
+fn offset(code: &str, position: Position) -> usize {
+  let line_start = code.split_inclusive('\n').take(position.line as usize).map(str::len).sum::<usize>();
+  let mut character = 0;
+  for (i, c) in code[line_start ..].char_indices() {
+    if character >= position.character as usize || c == '\n' {
+      return line_start + i;
+    }
+    character += c.len_utf16();
+  }
+  code.len()
+}
@@ -1,12 +1,17 @@
 use clap::{Args, CommandFactory, Parser, Subcommand};
 use hvml::{
+  builtins::IoPolicy,
   check_book, compile_book, desugar_book,
-  diagnostics::{Diagnostics, DiagnosticsConfig, Severity},
+  diagnostics::{DiagnosticOrigin, Diagnostics, DiagnosticsConfig, Loc, Severity},
   hvmc_net::pre_reduce::MAX_REWRITES_DEFAULT,
-  load_file_to_book, run_book,
-  term::{AdtEncoding, Book, Name},
-  CompileOpts, OptLevel, RunInfo, RunOpts,
+  load_file_to_book,
+  repl::Repl,
+  run_book, run_book_with_hook,
+  term::{format::format_code, parser::TermParser, AdtEncoding, Book, Name, Term},
+  test_runner::{run_tests, TestOutcome, TestResult, TEST_MAX_REWRITES_DEFAULT},
+  CompileOpts, OptLevel, RunInfo, RunOpts, RunStats, ENTRY_POINT, HVM1_ENTRY_POINT,
 };
+use serde_json::{json, Value};
 use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug)]
@@ -20,6 +25,24 @@ struct Cli {
 
   #[arg(short = 'e', long, global = true, help = "Use other entrypoint rather than main or Main")]
   pub entrypoint: Option<String>,
+
+  #[arg(
+    long = "output-format",
+    global = true,
+    value_enum,
+    default_value_t = OutputFormat::Text,
+    help = "How to print diagnostics and results"
+  )]
+  pub output_format: OutputFormat,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+  /// Human readable text.
+  #[default]
+  Text,
+  /// One JSON object on stdout, for tools and editors.
+  Json,
 }
 
 #[derive(Subcommand, Clone, Debug)]
@@ -44,6 +67,9 @@ enum Mode {
     #[command(flatten)]
     warn_opts: CliWarnOpts,
 
+    #[arg(long = "types", help = "Infer and check the types of the program")]
+    types: bool,
+
     #[arg(help = "Path to the input file")]
     path: PathBuf,
   },
@@ -99,9 +125,89 @@ enum Mode {
     #[arg(help = "Path to the input file")]
     path: PathBuf,
 
-    #[arg(value_parser = |arg: &str| hvml::term::parser::TermParser::new_term(arg))]
-    arguments: Option<Vec<hvml::term::Term>>,
+    #[arg(value_parser = |arg: &str| TermParser::new_term(arg))]
+    arguments: Option<Vec<Term>>,
+
+    #[arg(
+      long = "arg-file",
+      help = "Path to a file with a term to pass as an argument, after the ones given in the command line"
+    )]
+    arg_files: Vec<PathBuf>,
+
+    #[arg(
+      last = true,
+      help = "Arguments passed to main as a list of strings, if it takes one more argument than the terms given"
+    )]
+    argv: Option<Vec<String>>,
   },
+  /// Starts an interactive session to evaluate expressions.
+  Repl {
+    #[arg(short = 'L', help = "Lazy mode")]
+    lazy_mode: bool,
+
+    #[arg(short = 'p', help = "Debug and normalization pretty printing")]
+    pretty: bool,
+
+    #[command(flatten)]
+    run_opts: RunArgs,
+
+    #[arg(
+      short = 'O',
+      value_delimiter = ' ',
+      action = clap::ArgAction::Append,
+      long_help = r#"Enables or disables the given optimizations
+      float_combinators is enabled by default on strict mode."#,
+    )]
+    comp_opts: Vec<OptArgs>,
+
+    #[command(flatten)]
+    transform_opts: TransformOpts,
+
+    #[command(flatten)]
+    warn_opts: CliWarnOpts,
+
+    #[arg(help = "Path to a file with the definitions to load")]
+    path: Option<PathBuf>,
+  },
+  /// Runs the tests of a file: the definitions whose name starts with `test_`.
+  ///
+  /// There is no lazy mode, since a lazy run can't be stopped at the rewrite limit of each test.
+  Test {
+    #[command(flatten)]
+    run_opts: RunArgs,
+
+    #[arg(
+      short = 'O',
+      value_delimiter = ' ',
+      action = clap::ArgAction::Append,
+      long_help = r#"Enables or disables the given optimizations
+      float_combinators is enabled by default on strict mode."#,
+    )]
+    comp_opts: Vec<OptArgs>,
+
+    #[command(flatten)]
+    transform_opts: TransformOpts,
+
+    #[command(flatten)]
+    warn_opts: CliWarnOpts,
+
+    #[arg(long = "filter", help = "Only runs the tests whose name contains this text")]
+    filter: Option<String>,
+
+    #[arg(help = "Path to the input file")]
+    path: PathBuf,
+  },
+  /// Formats source files in the canonical layout.
+  Fmt {
+    #[arg(long, help = "Only checks if the files are formatted, exiting with an error if they are not")]
+    check: bool,
+
+    #[arg(help = "Paths to the files to format", required = true)]
+    paths: Vec<PathBuf>,
+  },
+  /// Starts a language server for editors, communicating over stdio.
+  #[cfg(feature = "lsp")]
+  Lsp,
   /// Runs the lambda-term level desugaring passes.
   Desugar {
     #[arg(
@@ -158,7 +264,7 @@ struct RunArgs {
   #[arg(short = 'm', long = "mem", help = "How much memory to allocate for the runtime", value_parser = parse_abbrev_number::<usize>)]
   max_memory: Option<usize>,
 
-  #[arg(short = 'r', long = "rwts", help = "Maximum amount of rewrites", value_parser = parse_abbrev_number::<usize>)]
+  #[arg(short = 'r', long = "rwts", help = "Maximum amount of rewrites (counted on a single core, not supported in lazy mode)", value_parser = parse_abbrev_number::<usize>)]
   max_rewrites: Option<usize>,
 
   #[arg(short = 'd', help = "Debug mode (print each reduction step)")]
@@ -172,6 +278,54 @@ struct RunArgs {
 
   #[arg(short = 's', long = "stats", help = "Shows runtime stats and rewrite counts")]
   arg_stats: bool,
+
+  #[arg(long = "typed-nums", help = "Read back numbers as negative or float when their bits encode one")]
+  typed_nums: bool,
+
+  #[command(flatten)]
+  io_opts: IoArgs,
+}
+
+#[derive(Args, Clone, Debug)]
+struct IoArgs {
+  #[arg(
+    long = "allow-read",
+    value_name = "DIR",
+    help = "Only allow reading files inside the given directories"
+  )]
+  allow_read: Option<Vec<PathBuf>>,
+
+  #[arg(
+    long = "allow-write",
+    value_name = "DIR",
+    help = "Only allow writing files inside the given directories"
+  )]
+  allow_write: Option<Vec<PathBuf>>,
+
+  #[arg(long = "no-exit", help = "Make HVM.exit return an error instead of terminating the program")]
+  no_exit: bool,
+
+  #[arg(long = "no-stdin", help = "Deny reading the standard input")]
+  no_stdin: bool,
+
+  #[arg(long = "no-env", help = "Deny reading environment variables")]
+  no_env: bool,
+
+  #[arg(long = "sandbox", help = "Deny all IO that is not allowed with --allow-read and --allow-write")]
+  sandbox: bool,
+}
+
+impl IoArgs {
+  fn io_policy(self) -> IoPolicy {
+    let base = if self.sandbox { IoPolicy::sandboxed() } else { IoPolicy::default() };
+    IoPolicy {
+      allow_read: self.allow_read.or(base.allow_read),
+      allow_write: self.allow_write.or(base.allow_write),
+      no_exit: self.no_exit || base.no_exit,
+      no_stdin: self.no_stdin || base.no_stdin,
+      no_env: self.no_env || base.no_env,
+    }
+  }
 }
 
 #[derive(Args, Debug, Clone)]
@@ -226,6 +380,7 @@ pub enum OptArgs {
   NoInline,
   AdtScott,
   AdtTaggedScott,
+  AdtNumTagged,
 }
 
 fn compile_opts_from_cli(args: &Vec<OptArgs>, transform_opts: TransformOpts, lazy_mode: bool) -> CompileOpts {
@@ -251,6 +406,7 @@ fn compile_opts_from_cli(args: &Vec<OptArgs>, transform_opts: TransformOpts, laz
 
       AdtScott => opts.adt_encoding = AdtEncoding::Scott,
       AdtTaggedScott => opts.adt_encoding = AdtEncoding::TaggedScott,
+      AdtNumTagged => opts.adt_encoding = AdtEncoding::NumTagged,
 
       LinearizeMatches => opts.linearize_matches = OptLevel::Enabled,
       LinearizeMatchesExtra => opts.linearize_matches = OptLevel::Extra,
@@ -282,15 +438,21 @@ fn main() {
   compile_error!("The 'cli' feature is needed for the hvm-lang cli");
 
   let cli = Cli::parse();
+  let output_format = cli.output_format;
 
   if let Err(diagnostics) = execute_cli_mode(cli) {
-    eprint!("{diagnostics}")
+    match output_format {
+      OutputFormat::Text => eprint!("{diagnostics}"),
+      OutputFormat::Json => println!("{}", json!({ "diagnostics": diagnostics_json(&diagnostics) })),
+    }
+    std::process::exit(1);
   }
 }
 
 fn execute_cli_mode(mut cli: Cli) -> Result<(), Diagnostics> {
   let arg_verbose = cli.verbose;
   let entrypoint = cli.entrypoint.take();
+  let json_output = cli.output_format == OutputFormat::Json;
 
   let load_book = |path: &Path| -> Result<Book, Diagnostics> {
     let mut book = load_file_to_book(path)?;
@@ -304,16 +466,22 @@ fn execute_cli_mode(mut cli: Cli) -> Result<(), Diagnostics> {
   };
 
   match cli.mode {
-    Mode::Check { comp_opts, transform_opts, lazy_mode, warn_opts, path } => {
+    Mode::Check { comp_opts, transform_opts, lazy_mode, warn_opts, types, path } => {
       let diagnostics_cfg = set_warning_cfg_from_cli(
         if lazy_mode { DiagnosticsConfig::default_lazy() } else { DiagnosticsConfig::default_strict() },
         lazy_mode,
         warn_opts,
       );
-      let compile_opts = compile_opts_from_cli(&comp_opts, transform_opts, lazy_mode);
+      let mut compile_opts = compile_opts_from_cli(&comp_opts, transform_opts, lazy_mode);
+      compile_opts.type_check = types;
 
       let mut book = load_book(&path)?;
-      check_book(&mut book, diagnostics_cfg, compile_opts)?;
+      if json_output {
+        let compile_res = compile_book(&mut book, compile_opts, diagnostics_cfg, None)?;
+        println!("{}", json!({ "diagnostics": diagnostics_json(&compile_res.diagnostics) }));
+      } else {
+        check_book(&mut book, diagnostics_cfg, compile_opts)?;
+      }
     }
 
     Mode::Compile { path, comp_opts, warn_opts, lazy_mode, transform_opts } => {
@@ -327,8 +495,13 @@ fn execute_cli_mode(mut cli: Cli) -> Result<(), Diagnostics> {
       let mut book = load_book(&path)?;
       let compile_res = compile_book(&mut book, opts, diagnostics_cfg, None)?;
 
-      eprint!("{}", compile_res.diagnostics);
-      println!("{}", compile_res.core_book);
+      if json_output {
+        let diagnostics = diagnostics_json(&compile_res.diagnostics);
+        println!("{}", json!({ "diagnostics": diagnostics, "result": compile_res.core_book.to_string() }));
+      } else {
+        eprint!("{}", compile_res.diagnostics);
+        println!("{}", compile_res.core_book);
+      }
     }
 
     Mode::Desugar { path, comp_opts, warn_opts, pretty, lazy_mode, transform_opts } => {
@@ -343,16 +516,163 @@ fn execute_cli_mode(mut cli: Cli) -> Result<(), Diagnostics> {
       let mut book = load_book(&path)?;
       let diagnostics = desugar_book(&mut book, opts, diagnostics_cfg, None)?;
 
-      eprint!("{diagnostics}");
-      if pretty {
-        println!("{}", book.display_pretty())
+      let result = if pretty { book.display_pretty().to_string() } else { book.to_string() };
+      if json_output {
+        println!("{}", json!({ "diagnostics": diagnostics_json(&diagnostics), "result": result }));
       } else {
-        println!("{book}");
+        eprint!("{diagnostics}");
+        println!("{result}");
+      }
+    }
+
+    Mode::Repl { lazy_mode, pretty, run_opts, comp_opts, transform_opts, warn_opts, path } => {
+      let RunArgs { max_memory, max_rewrites, debug, single_core, linear, arg_stats: _, typed_nums, io_opts } =
+        run_opts;
+
+      let diagnostics_cfg =
+        set_warning_cfg_from_cli(DiagnosticsConfig::new(Severity::Allow, arg_verbose), lazy_mode, warn_opts);
+      let compile_opts = compile_opts_from_cli(&comp_opts, transform_opts, lazy_mode);
+      let single_core = single_core || lazy_mode;
+      let io_policy = io_opts.io_policy();
+      let run_opts = RunOpts {
+        single_core,
+        debug,
+        linear,
+        lazy_mode,
+        max_memory,
+        max_rewrites,
+        pretty,
+        typed_nums,
+        io_policy,
+        ..RunOpts::default()
+      };
+
+      let book = match path {
+        Some(path) => load_book(&path)?,
+        None => Book::builtins(),
+      };
+      let mut repl = Repl::new(book, compile_opts, diagnostics_cfg, run_opts);
+      if let Err(e) = repl.run(std::io::stdin().lock(), &mut std::io::stdout()) {
+        eprintln!("{e}");
+      }
+    }
+
+    Mode::Test { run_opts, comp_opts, transform_opts, warn_opts, filter, path } => {
+      let RunArgs {
+        max_memory,
+        max_rewrites,
+        debug,
+        single_core: _,
+        linear,
+        arg_stats: _,
+        typed_nums,
+        io_opts,
+      } = run_opts;
+
+      let diagnostics_cfg =
+        set_warning_cfg_from_cli(DiagnosticsConfig::new(Severity::Allow, arg_verbose), false, warn_opts);
+      let compile_opts = compile_opts_from_cli(&comp_opts, transform_opts, false);
+      compile_opts.check_for_strict();
+
+      // The rewrite limit is only supported in strict mode.
+      let max_rewrites = Some(max_rewrites.unwrap_or(TEST_MAX_REWRITES_DEFAULT));
+      let io_policy = io_opts.io_policy();
+      let run_opts = RunOpts {
+        single_core: true,
+        debug,
+        linear,
+        lazy_mode: false,
+        max_memory,
+        max_rewrites,
+        pretty: false,
+        typed_nums,
+        io_policy,
+        ..RunOpts::default()
+      };
+
+      let book = load_book(&path)?;
+      let results = run_tests(&book, filter.as_deref(), run_opts, compile_opts, diagnostics_cfg);
+
+      let passed = results.iter().filter(|result| result.passed()).count();
+      if json_output {
+        let tests = results.iter().map(|TestResult { name, outcome, stats }| {
+          json!({
+            "name": name.to_string(),
+            "outcome": outcome.to_string(),
+            "passed": matches!(outcome, TestOutcome::Passed),
+            "stats": stats.as_ref().map(stats_json),
+          })
+        });
+        let tests = tests.collect::<Vec<_>>();
+        println!("{}", json!({ "failed": results.len() - passed, "passed": passed, "tests": tests }));
+        if passed != results.len() {
+          std::process::exit(1);
+        }
+        return Ok(());
+      }
+      for TestResult { name, outcome, stats } in &results {
+        let outcome = outcome.to_string();
+        let (status, details) = outcome.split_once('\n').unwrap_or((&outcome, ""));
+        print!("test {name} ... {status}");
+        if let Some(stats) = stats {
+          print!(" ({} rewrites, {:.3} s)", stats.rewrites.total(), stats.run_time);
+        }
+        println!();
+        if !details.is_empty() {
+          println!("{}", details.trim_end());
+        }
+      }
+      println!("\n{} tests: {passed} passed, {} failed", results.len(), results.len() - passed);
+
+      if passed != results.len() {
+        std::process::exit(1);
       }
     }
 
-    Mode::Run { lazy_mode, run_opts, pretty, comp_opts, transform_opts, warn_opts, arguments, path } => {
-      let RunArgs { max_memory, max_rewrites, debug, mut single_core, linear, arg_stats } = run_opts;
+    Mode::Fmt { check, paths } => {
+      let mut unformatted = vec![];
+      for path in paths {
+        let code = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        let formatted = format_code(&code).map_err(|e| format!("In {} :\n{e}", path.display()))?;
+        if formatted != code {
+          if check {
+            unformatted.push(path);
+          } else {
+            std::fs::write(&path, formatted).map_err(|e| e.to_string())?;
+          }
+        }
+      }
+      if !unformatted.is_empty() {
+        let paths = unformatted.iter().map(|path| format!("  {}", path.display())).collect::<Vec<_>>();
+        return Err(format!("The following files are not formatted:\n{}", paths.join("\n")).into());
+      }
+    }
+
+    #[cfg(feature = "lsp")]
+    Mode::Lsp => hvml::lsp::run().map_err(|e| e.to_string())?,
+
+    Mode::Run {
+      lazy_mode,
+      run_opts,
+      pretty,
+      comp_opts,
+      transform_opts,
+      warn_opts,
+      arguments,
+      arg_files,
+      argv,
+      path,
+    } => {
+      let RunArgs {
+        max_memory,
+        max_rewrites,
+        debug,
+        mut single_core,
+        linear,
+        arg_stats,
+        typed_nums,
+        io_opts,
+      } = run_opts;
 
       let diagnostics_cfg =
         set_warning_cfg_from_cli(DiagnosticsConfig::new(Severity::Allow, arg_verbose), lazy_mode, warn_opts);
@@ -370,15 +690,77 @@ fn execute_cli_mode(mut cli: Cli) -> Result<(), Diagnostics> {
         compile_opts.check_for_strict();
       }
 
-      let run_opts = RunOpts { single_core, debug, linear, lazy_mode, max_memory, max_rewrites, pretty };
+      let io_policy = io_opts.io_policy();
+      let run_opts = RunOpts {
+        single_core,
+        debug,
+        linear,
+        lazy_mode,
+        max_memory,
+        max_rewrites,
+        pretty,
+        typed_nums,
+        io_policy,
+        ..RunOpts::default()
+      };
 
       let book = load_book(&path)?;
-      let (res_term, RunInfo { stats, diagnostics, net, book: _, labels: _ }) =
+
+      let mut arguments = arguments.unwrap_or_default();
+      for arg_file in arg_files {
+        let code = std::fs::read_to_string(&arg_file).map_err(|e| e.to_string())?;
+        let term = TermParser::new_term(&code).map_err(|e| format!("In {} :\n{e}", arg_file.display()))?;
+        arguments.push(term);
+      }
+      // Programs that take one more argument receive the ones after `--` as a list of strings.
+      // Without `--`, the entrypoint is applied only to the given terms, even if it takes more.
+      if let Some(argv) = argv {
+        if entrypoint_arity(&book) <= arguments.len() {
+          return Err(
+            "The arguments after '--' are only passed to a main function that takes them.".to_string().into(),
+          );
+        }
+        arguments
+          .push(Term::Lst { els: argv.iter().map(|arg| Term::str(arg)).collect(), loc: Loc::default() });
+      }
+      let arguments = Some(arguments);
+
+      if json_output {
+        let mut compile_diagnostics = None;
+        let (res_term, RunInfo { stats, diagnostics, exit_code, .. }) = run_book_with_hook(
+          book,
+          max_memory,
+          run_opts,
+          compile_opts,
+          diagnostics_cfg,
+          arguments,
+          |diagnostics| compile_diagnostics = Some(diagnostics),
+        )?;
+        let mut diagnostics_out = compile_diagnostics.as_ref().map(diagnostics_json).unwrap_or_default();
+        diagnostics_out.extend(diagnostics_json(&diagnostics));
+        let result = if pretty { res_term.display_pretty(0).to_string() } else { res_term.to_string() };
+        println!(
+          "{}",
+          json!({
+            "diagnostics": diagnostics_out,
+            "exit_code": exit_code,
+            "result": result,
+            "stats": stats_json(&stats)
+          })
+        );
+        if let Some(code) = exit_code {
+          std::process::exit(code);
+        }
+        return Ok(());
+      }
+
+      let (res_term, RunInfo { stats, diagnostics, net, book: _, labels: _, exit_code }) =
         run_book(book, max_memory, run_opts, compile_opts, diagnostics_cfg, arguments)?;
 
-      let total_rewrites = stats.rewrites.total() as f64;
-      let rps = total_rewrites / stats.run_time / 1_000_000.0;
-      let size = stats.used;
+      // The result of a program that exited is not meaningful.
+      if let Some(code) = exit_code {
+        std::process::exit(code);
+      }
 
       if cli.verbose {
         println!("{net}");
@@ -392,21 +774,71 @@ fn execute_cli_mode(mut cli: Cli) -> Result<(), Diagnostics> {
       }
 
       if arg_stats {
-        println!("\nRWTS   : {}", total_rewrites);
-        println!("- ANNI : {}", stats.rewrites.anni);
-        println!("- COMM : {}", stats.rewrites.comm);
-        println!("- ERAS : {}", stats.rewrites.eras);
-        println!("- DREF : {}", stats.rewrites.dref);
-        println!("- OPER : {}", stats.rewrites.oper);
-        println!("TIME   : {:.3} s", stats.run_time);
-        println!("RPS    : {:.3} m", rps);
-        println!("SIZE   : {} nodes", size);
+        println!("\n{stats}");
       }
     }
   };
   Ok(())
 }
 
+/// The number of arguments the entrypoint of a program is declared to take.
+fn entrypoint_arity(book: &Book) -> usize {
+  let mut names =
+    book.entrypoint.iter().cloned().chain([Name::new(ENTRY_POINT), Name::new(HVM1_ENTRY_POINT)]);
+  match names.find_map(|name| book.defs.get(&name)) {
+    Some(def) => def.arity(),
+    None => 0,
+  }
+}
+
+/// The diagnostics that are shown to the user, as JSON records.
+/// Keys are written in alphabetical order, so that the output is the same whichever way the map is ordered.
+fn diagnostics_json(diagnostics: &Diagnostics) -> Vec<Value> {
+  let mut records = vec![];
+  for (origin, diags) in &diagnostics.diagnostics {
+    let origin = match origin {
+      DiagnosticOrigin::Book => json!({ "kind": "book" }),
+      DiagnosticOrigin::Rule(name) => json!({ "kind": "rule", "name": name.to_string() }),
+      DiagnosticOrigin::Inet(name) => json!({ "kind": "inet", "name": name }),
+      DiagnosticOrigin::Readback => json!({ "kind": "readback" }),
+    };
+    for diag in diags {
+      let severity = match diag.severity {
+        Severity::Allow => continue,
+        Severity::Warning => "warning",
+        Severity::Error => "error",
+      };
+      let span = diag.span.as_ref().map(|span| {
+        let (line, column) = span.line_col();
+        json!({ "column": column, "end": span.end, "file": span.file.path, "line": line, "start": span.ini })
+      });
+      records.push(json!({
+        "message": diag.message,
+        "origin": origin,
+        "severity": severity,
+        "span": span,
+      }));
+    }
+  }
+  records
+}
+
+fn stats_json(stats: &RunStats) -> Value {
+  let rewrites = &stats.rewrites;
+  json!({
+    "rewrites": {
+      "anni": rewrites.anni,
+      "comm": rewrites.comm,
+      "dref": rewrites.dref,
+      "eras": rewrites.eras,
+      "oper": rewrites.oper,
+      "total": rewrites.total(),
+    },
+    "run_time": stats.run_time,
+    "used": stats.used,
+  })
+}
+
 /// Turn a string representation of a number, such as '1G' or '400K', into a
 /// number.
 ///
@@ -0,0 +1,305 @@
+use crate::{
+  builtins::{
+    create_host,
+    exit::{uses_exit, ExitStatus},
+  },
+  check_max_rewrites, desugar_book,
+  diagnostics::{Diagnostics, DiagnosticsConfig, Loc, Severity},
+  hvmc_net::mutual_recursion,
+  net::net_to_hvmc::nets_to_hvmc,
+  readback_hvmc, run_compiled,
+  term::{
+    term_to_net::{book_to_nets_with_labels, Labels},
+    Book, Definition, Name, Rule, Term,
+  },
+  CompileOpts, RunInfo, RunOpts, ENTRY_POINT, HVM1_ENTRY_POINT,
+};
+use hvmc::{ast::Net, host::Host};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// The name of the net that calls a definition with its arguments.
+const CALL_ENTRY: &str = "Program$call";
+
+/// A program compiled once, whose definitions can then be called any number of times.
+///
+/// Only the call itself is compiled when calling a definition, and it runs in the same host as the previous
+/// calls. Nothing is printed, diagnostics are returned to the caller instead.
+///
+/// ```ignore
+/// let book = hvml::load_file_to_book(Path::new("sum.hvm"))?;
+/// let mut program = Program::new(book, CompileOpts::default_strict(), diagnostics_cfg, RunOpts::default())?;
+/// let sum: u64 = program.call_as("sum", vec![Term::from(vec![1u64, 2, 3])])?;
+/// ```
+pub struct Program {
+  /// The desugared definitions.
+  book: Arc<Book>,
+  /// The labels used by the definitions, that the calls keep generating from.
+  labels: Labels,
+  host: Arc<Mutex<Host>>,
+  /// Where the host stores the status code of `HVM.exit`, taken out after each call.
+  exit_status: ExitStatus,
+  /// Whether the definitions refer to `HVM.exit`, which can't stop the other threads of a parallel run.
+  exits: bool,
+  compile_opts: CompileOpts,
+  diagnostics_cfg: DiagnosticsConfig,
+  run_opts: RunOpts,
+  /// The warnings found when compiling the program.
+  diagnostics: Diagnostics,
+}
+
+impl Program {
+  /// Compiles the definitions of a book.
+  ///
+  /// Every definition can be called, so the optimizations that remove or rename definitions are disabled.
+  /// The book doesn't need an entrypoint.
+  pub fn new(
+    mut book: Book,
+    compile_opts: CompileOpts,
+    diagnostics_cfg: DiagnosticsConfig,
+    run_opts: RunOpts,
+  ) -> Result<Self, Diagnostics> {
+    run_opts.check()?;
+    let compile_opts =
+      CompileOpts { prune: false, merge: false, pre_reduce: false, inline: false, ..compile_opts };
+    let diagnostics_cfg = DiagnosticsConfig { unused_definition: Severity::Allow, ..diagnostics_cfg };
+
+    let has_main =
+      [ENTRY_POINT, HVM1_ENTRY_POINT].iter().any(|main| book.defs.contains_key(&Name::new(*main)));
+    if book.entrypoint.is_none() && !has_main {
+      // The compiler needs an entrypoint, even if it's never called.
+      let main = Name::new(ENTRY_POINT);
+      let rule = Rule { pats: vec![], guard: None, body: Term::era(), loc: Loc::default() };
+      book.defs.insert(main.clone(), Definition { name: main, rules: vec![rule], builtin: false });
+    }
+
+    run_opts.host_fns.declare(&mut book)?;
+    let mut diagnostics = desugar_book(&mut book, compile_opts.clone(), diagnostics_cfg, None)?;
+
+    let mut labels = Labels::default();
+    let nets = book_to_nets_with_labels(&book, &mut labels);
+    let mut core_book = nets_to_hvmc(nets, &mut diagnostics)?;
+    if compile_opts.eta {
+      core_book.values_mut().for_each(Net::eta_reduce);
+    }
+    mutual_recursion::check_cycles(&core_book, &mut diagnostics)?;
+
+    if !has_main {
+      book.defs.shift_remove(&Name::new(ENTRY_POINT));
+    }
+
+    let book = Arc::new(book);
+    let io_policy = run_opts.io_policy.clone();
+    let exit_status = ExitStatus::default();
+    let host = create_host(
+      book.clone(),
+      Arc::new(labels.clone()),
+      compile_opts.adt_encoding,
+      io_policy,
+      &run_opts.host_fns,
+      exit_status.clone(),
+    );
+    host.lock().insert_book(&core_book);
+    let exits = uses_exit(&core_book);
+
+    Ok(Self { book, labels, host, exit_status, exits, compile_opts, diagnostics_cfg, run_opts, diagnostics })
+  }
+
+  /// The warnings found when compiling the program.
+  pub fn diagnostics(&self) -> &Diagnostics {
+    &self.diagnostics
+  }
+
+  /// The desugared definitions of the program.
+  pub fn book(&self) -> &Book {
+    &self.book
+  }
+
+  pub fn run_opts(&self) -> &RunOpts {
+    &self.run_opts
+  }
+
+  /// Sets the options of the next calls, failing if they can't be used together.
+  /// Debug mode is not supported, since it prints each step.
+  /// The IO policy and the host functions are the ones given when creating the program, and can't be changed.
+  pub fn set_run_opts(&mut self, run_opts: RunOpts) -> Result<(), Diagnostics> {
+    run_opts.check()?;
+    self.run_opts = run_opts;
+    Ok(())
+  }
+
+  /// Calls a definition with the given arguments, returning its result and the information of the run.
+  ///
+  /// The arguments are compiled like the body of a definition, so they can use `match` terms and `do`
+  /// blocks. They can't refer to variables bound outside of them, but can use the constructors of the
+  /// program and its definitions, except for the entrypoint.
+  pub fn call(&mut self, def: &str, args: Vec<Term>) -> Result<(Term, RunInfo), Diagnostics> {
+    let def = Name::new(def);
+    if !self.book.defs.contains_key(&def) {
+      return Err(format!("There is no definition named '{def}' in the program.").into());
+    }
+    let fun = if self.book.entrypoint.as_ref() == Some(&def) {
+      Term::r#ref(self.book.hvmc_entrypoint())
+    } else {
+      Term::Ref { nam: def, loc: Loc::default() }
+    };
+
+    let (call_book, labels, mut compile_diagnostics) = self.compile_call(Term::call(fun, args))?;
+    // Replaces the nets of the previous call, which is no longer running.
+    self.host.lock().insert_book(&call_book);
+
+    // Calls that can exit are run on a single core, so that the exit stops all of the reduction.
+    let single_core = self.run_opts.single_core || self.exits || uses_exit(&call_book);
+    let run_opts = RunOpts { debug: false, single_core, ..self.run_opts.clone() };
+    let (res_lnet, stats) = run_compiled(
+      self.host.clone(),
+      run_opts.max_memory,
+      &run_opts,
+      None::<fn(&Net)>,
+      CALL_ENTRY,
+      &self.exit_status,
+    );
+
+    let labels = Arc::new(labels);
+    let (mut res_term, diagnostics) =
+      readback_hvmc(&res_lnet, &self.book, &labels, run_opts.linear, self.compile_opts.adt_encoding);
+    if run_opts.typed_nums {
+      res_term.resugar_typed_nums();
+    }
+    compile_diagnostics.extend(diagnostics);
+    check_max_rewrites(&stats, &mut compile_diagnostics);
+
+    let exit_code = self.exit_status.take();
+    let info = RunInfo {
+      stats,
+      diagnostics: compile_diagnostics,
+      net: res_lnet,
+      book: self.book.clone(),
+      labels,
+      exit_code,
+    };
+    Ok((res_term, info))
+  }
+
+  /// Compiles a call as the body of a new definition, with the definitions of the program replaced by
+  /// placeholders. Returns the nets of the call and of the definitions generated from it.
+  fn compile_call(&self, term: Term) -> Result<(hvmc::ast::Book, Labels, Diagnostics), Diagnostics> {
+    let call = Name::new(CALL_ENTRY);
+    let mut book = Book {
+      adts: self.book.adts.clone(),
+      ctrs: self.book.ctrs.clone(),
+      host_builtins: self.book.host_builtins.clone(),
+      entrypoint: Some(call.clone()),
+      ..Book::default()
+    };
+    for (nam, def) in self.book.defs.iter() {
+      // The constructors are encoded again, and the call must be the only entrypoint.
+      let is_program_entrypoint = self.book.entrypoint.as_ref() == Some(nam) || is_entrypoint(nam);
+      if !self.book.ctrs.contains_key(nam) && !is_program_entrypoint {
+        book.defs.insert(nam.clone(), placeholder(nam, def.builtin));
+      }
+    }
+    let rule = Rule { pats: vec![], guard: None, body: term, loc: Loc::default() };
+    book.defs.insert(call.clone(), Definition { name: call, rules: vec![rule], builtin: false });
+
+    let mut diagnostics = desugar_book(&mut book, self.compile_opts.clone(), self.diagnostics_cfg, None)?;
+    book.defs.retain(|nam, _| is_generated_by(nam, CALL_ENTRY));
+
+    let mut labels = self.labels.clone();
+    let nets = book_to_nets_with_labels(&book, &mut labels);
+    let mut call_book = nets_to_hvmc(nets, &mut diagnostics)?;
+    if self.compile_opts.eta {
+      call_book.values_mut().for_each(Net::eta_reduce);
+    }
+    Ok((call_book, labels, diagnostics))
+  }
+
+  /// Calls a definition, converting its result into a Rust value.
+  /// Results with readback errors, or of calls that exited, are not converted.
+  pub fn call_as<T: TryFrom<Term, Error = String>>(
+    &mut self,
+    def: &str,
+    args: Vec<Term>,
+  ) -> Result<T, Diagnostics> {
+    let (term, info) = self.call(def, args)?;
+    if info.diagnostics.has_errors() {
+      return Err(info.diagnostics);
+    }
+    if let Some(code) = info.exit_code {
+      return Err(format!("The call exited with status code {code}.").into());
+    }
+    Ok(T::try_from(term)?)
+  }
+}
+
+pub(crate) fn is_entrypoint(nam: &Name) -> bool {
+  nam.as_ref() == ENTRY_POINT || nam.as_ref() == HVM1_ENTRY_POINT
+}
+
+/// Whether a definition is `def` or was generated from it.
+pub(crate) fn is_generated_by(nam: &str, def: &str) -> bool {
+  nam == def || nam.strip_prefix(def).is_some_and(|rest| rest.starts_with('$'))
+}
+
+/// A definition that stands for one that was already compiled.
+pub(crate) fn placeholder(nam: &Name, builtin: bool) -> Definition {
+  let rule = Rule { pats: vec![], guard: None, body: Term::era(), loc: Loc::default() };
+  Definition { name: nam.clone(), rules: vec![rule], builtin }
+}
+
+impl From<u64> for Term {
+  fn from(val: u64) -> Self {
+    Term::num(val)
+  }
+}
+
+impl From<&str> for Term {
+  fn from(val: &str) -> Self {
+    Term::str(val)
+  }
+}
+
+impl From<String> for Term {
+  fn from(val: String) -> Self {
+    Term::str(&val)
+  }
+}
+
+impl<T: Into<Term>> From<Vec<T>> for Term {
+  fn from(els: Vec<T>) -> Self {
+    Term::Lst { els: els.into_iter().map(Into::into).collect(), loc: Loc::default() }
+  }
+}
+
+impl TryFrom<Term> for u64 {
+  type Error = String;
+
+  fn try_from(term: Term) -> Result<Self, Self::Error> {
+    match term {
+      Term::Num { val, .. } => Ok(val),
+      _ => Err(format!("Expected a number, found '{term}'.")),
+    }
+  }
+}
+
+impl TryFrom<Term> for String {
+  type Error = String;
+
+  fn try_from(term: Term) -> Result<Self, Self::Error> {
+    match &term {
+      Term::Str { val, .. } => Ok(String::from(&**val)),
+      _ => Err(format!("Expected a string, found '{term}'.")),
+    }
+  }
+}
+
+impl<T: TryFrom<Term, Error = String>> TryFrom<Term> for Vec<T> {
+  type Error = String;
+
+  fn try_from(mut term: Term) -> Result<Self, Self::Error> {
+    match &mut term {
+      Term::Lst { els, .. } => std::mem::take(els).into_iter().map(T::try_from).collect(),
+      _ => Err(format!("Expected a list, found '{term}'.")),
+    }
+  }
+}
@@ -0,0 +1,452 @@
+use crate::{
+  builtins::{
+    create_host,
+    exit::{uses_exit, ExitStatus},
+    host_fns::HostFns,
+  },
+  desugar_book,
+  diagnostics::{Diagnostics, DiagnosticsConfig, Loc, Severity, SourceFile},
+  hvmc_net::mutual_recursion,
+  infer_book_types,
+  net::net_to_hvmc::nets_to_hvmc,
+  program::{is_entrypoint, is_generated_by, placeholder},
+  readback_hvmc, run_compiled,
+  term::{
+    parser::TermParser,
+    term_to_net::{book_to_nets_with_labels, Labels},
+    Book, Definition, Name, Rule, Term,
+  },
+  CompileOpts, RunOpts, RunStats, ENTRY_POINT,
+};
+use hvmc::ast::Net;
+use indexmap::IndexMap;
+use std::{
+  io::{self, BufRead, Write},
+  sync::Arc,
+};
+
+const HELP: &str = "\
+Enter a definition to add it to the session, replacing any previous one with the same name.
+The rules of a definition must be entered together, in the same line.
+Enter an expression to evaluate it.
+
+Commands:
+  :type <expr>     Shows the inferred type of an expression
+  :desugar <expr>  Shows the desugared definitions of an expression
+  :compile <expr>  Shows the hvm-core nets of an expression
+  :stats           Shows the stats of the last evaluated expression
+  :lazy            Switches between lazy and strict mode
+  :help            Shows this message
+  :quit            Exits the repl
+";
+
+/// An interactive session that evaluates expressions using the definitions of a program.
+///
+/// The definitions are compiled once and reused by every expression, so that only the expression
+/// itself needs to be compiled. New definitions are compiled the same way, on top of the previous ones.
+pub struct Repl {
+  /// The definitions of the session, as written by the user.
+  book: Book,
+  compile_opts: CompileOpts,
+  diagnostics_cfg: DiagnosticsConfig,
+  run_opts: RunOpts,
+  /// The compiled definitions, if they're up to date.
+  program: Option<CompiledProgram>,
+  /// The stats of the last evaluated expression.
+  stats: Option<RunStats>,
+}
+
+struct CompiledProgram {
+  /// The desugared definitions.
+  book: Book,
+  core_book: hvmc::ast::Book,
+  /// The labels used so far, that the expressions keep generating from.
+  labels: Labels,
+}
+
+/// Definitions compiled on top of the already compiled ones.
+struct CompiledDefs {
+  /// The desugared definitions generated from the new ones.
+  defs: Book,
+  /// The hvm-core nets generated from the new definitions.
+  nets: hvmc::ast::Book,
+  labels: Labels,
+  diagnostics: Diagnostics,
+}
+
+/// An expression compiled together with the definitions of the session.
+struct CompiledExpr {
+  /// The desugared definitions generated from the expression.
+  defs: Book,
+  /// The hvm-core nets generated from the expression.
+  nets: hvmc::ast::Book,
+  /// The definitions of the session with the ones of the expression.
+  book: Book,
+  core_book: hvmc::ast::Book,
+  labels: Labels,
+  diagnostics: Diagnostics,
+}
+
+impl Repl {
+  pub fn new(
+    mut book: Book,
+    compile_opts: CompileOpts,
+    diagnostics_cfg: DiagnosticsConfig,
+    run_opts: RunOpts,
+  ) -> Self {
+    book.entrypoint = None;
+    // The whole program optimizations would have to be redone for every expression.
+    let compile_opts =
+      CompileOpts { prune: false, merge: false, pre_reduce: false, inline: false, ..compile_opts };
+    // Most definitions are not used by the expression being evaluated.
+    let diagnostics_cfg = DiagnosticsConfig { unused_definition: Severity::Allow, ..diagnostics_cfg };
+    Self { book, compile_opts, diagnostics_cfg, run_opts, program: None, stats: None }
+  }
+
+  /// Reads and evaluates entries until the end of the input or a `:quit` command.
+  pub fn run(&mut self, input: impl BufRead, output: &mut impl Write) -> io::Result<()> {
+    writeln!(output, "Enter an expression to evaluate it, or ':help' to see the available commands.")?;
+    match compile_program(&self.book, &self.compile_opts, self.diagnostics_cfg, &self.run_opts.host_fns) {
+      Ok((program, diagnostics)) => {
+        write!(output, "{diagnostics}")?;
+        self.program = Some(program);
+      }
+      Err(diagnostics) => write!(output, "{diagnostics}")?,
+    }
+
+    let mut lines = input.lines();
+    while let Some(entry) = read_entry(&mut lines, output)? {
+      if matches!(entry.trim(), ":q" | ":quit") {
+        return Ok(());
+      }
+      match self.eval(&entry) {
+        Ok(out) => write!(output, "{out}")?,
+        Err(diagnostics) => write!(output, "{diagnostics}")?,
+      }
+    }
+    writeln!(output)
+  }
+
+  /// Evaluates a command, expression or definitions, returning what should be shown to the user.
+  pub fn eval(&mut self, input: &str) -> Result<String, Diagnostics> {
+    let input = input.trim();
+
+    if let Some(cmd) = input.strip_prefix(':') {
+      let (cmd, arg) = cmd.split_once(char::is_whitespace).unwrap_or((cmd, ""));
+      let arg = arg.trim();
+      return match cmd {
+        "type" | "t" => self.show_type(TermParser::new_full_term(arg)?),
+        "desugar" => Ok(format!("{}\n", self.compile_expr(TermParser::new_full_term(arg)?)?.defs)),
+        "compile" => Ok(self.compile_expr(TermParser::new_full_term(arg)?)?.nets.to_string()),
+        "stats" => Ok(self.show_stats()),
+        "lazy" => Ok(self.toggle_lazy()),
+        "help" | "h" => Ok(HELP.to_string()),
+        _ => Err(format!("Unknown command ':{cmd}', enter ':help' to see the available commands.").into()),
+      };
+    }
+
+    match TermParser::new_full_term(input) {
+      Ok(term) => self.eval_expr(term),
+      Err(term_err) => match parse_definitions(input) {
+        Ok(book) => self.add_definitions(book),
+        Err(book_err) if looks_like_definition(input) => Err(book_err.into()),
+        Err(_) => Err(term_err.into()),
+      },
+    }
+  }
+
+  fn eval_expr(&mut self, term: Term) -> Result<String, Diagnostics> {
+    self.run_opts.check()?;
+    let CompiledExpr { book, core_book, labels, diagnostics, .. } = self.compile_expr(term)?;
+    // `HVM.exit` can't stop the other threads, so an expression that exits is run on a single core.
+    let run_opts =
+      RunOpts { single_core: self.run_opts.single_core || uses_exit(&core_book), ..self.run_opts.clone() };
+    let adt_encoding = self.compile_opts.adt_encoding;
+
+    let book = Arc::new(book);
+    let labels = Arc::new(labels);
+    let debug_hook = run_opts.debug_hook(&book, &labels);
+    let exit_status = ExitStatus::default();
+    let io_policy = run_opts.io_policy.clone();
+    let host = create_host(
+      book.clone(),
+      labels.clone(),
+      adt_encoding,
+      io_policy,
+      &run_opts.host_fns,
+      exit_status.clone(),
+    );
+    host.lock().insert_book(&core_book);
+
+    let (res_lnet, stats) =
+      run_compiled(host, run_opts.max_memory, &run_opts, debug_hook, ENTRY_POINT, &exit_status);
+    let (mut res_term, readback_diagnostics) =
+      readback_hvmc(&res_lnet, &book, &labels, run_opts.linear, adt_encoding);
+    if run_opts.typed_nums {
+      res_term.resugar_typed_nums();
+    }
+    self.stats = Some(stats);
+
+    if let Some(code) = exit_status.get() {
+      Ok(format!("{diagnostics}{readback_diagnostics}Exited with status code {code}\n"))
+    } else if run_opts.pretty {
+      Ok(format!("{diagnostics}{readback_diagnostics}{}\n", res_term.display_pretty(0)))
+    } else {
+      Ok(format!("{diagnostics}{readback_diagnostics}{res_term}\n"))
+    }
+  }
+
+  /// Adds new definitions to the session, if they compile together with the previous ones.
+  ///
+  /// Only the new definitions are compiled and linked with the already compiled ones,
+  /// unless they declare datatypes, which can change how the rest of the program is compiled.
+  fn add_definitions(&mut self, new: Book) -> Result<String, Diagnostics> {
+    if !new.imports.is_empty() {
+      return Err(
+        "Imports are not supported in the repl, load a file that imports the modules instead."
+          .to_string()
+          .into(),
+      );
+    }
+
+    let mut book = self.book.clone();
+    for (nam, adt) in new.adts.iter() {
+      if let Some(old) = book.adts.get(nam) {
+        if old.builtin {
+          return Err(format!("{nam} is a built-in datatype and should not be overridden.").into());
+        }
+        for ctr in old.ctrs.keys() {
+          book.ctrs.shift_remove(ctr);
+        }
+      }
+      for ctr in adt.ctrs.keys() {
+        if let Some(other) = book.ctrs.get(ctr) {
+          return Err(format!("Constructor '{ctr}' is already defined by datatype '{other}'.").into());
+        }
+        book.ctrs.insert(ctr.clone(), nam.clone());
+      }
+      book.adts.insert(nam.clone(), adt.clone());
+    }
+
+    if !new.adts.is_empty() || self.program.is_none() {
+      for (nam, def) in new.defs {
+        book.defs.insert(nam, def);
+      }
+      let (program, diagnostics) =
+        compile_program(&book, &self.compile_opts, self.diagnostics_cfg, &self.run_opts.host_fns)?;
+      self.book = book;
+      self.program = Some(program);
+      return Ok(diagnostics.to_string());
+    }
+
+    let names = new.defs.keys().cloned().collect::<Vec<_>>();
+    let CompiledDefs { defs, nets, labels, mut diagnostics } = self.compile_defs(new.defs.clone())?;
+
+    let program = self.program.as_mut().unwrap();
+    let mut core_book = program.core_book.clone();
+    // Remove what the replaced definitions generated, it may not be generated again.
+    core_book.retain(|nam, _| !names.iter().any(|new| is_generated_by(nam, new)));
+    core_book.extend(nets);
+    mutual_recursion::check_cycles(&core_book, &mut diagnostics)?;
+
+    program.book.defs.retain(|nam, _| !names.iter().any(|new| is_generated_by(nam, new)));
+    program.book.defs.extend(defs.defs);
+    program.core_book = core_book;
+    program.labels = labels;
+    book.defs.extend(new.defs);
+    self.book = book;
+    Ok(diagnostics.to_string())
+  }
+
+  /// Returns the compiled definitions, compiling them if they changed.
+  fn program(&mut self) -> Result<&CompiledProgram, Diagnostics> {
+    if self.program.is_none() {
+      let (program, _) =
+        compile_program(&self.book, &self.compile_opts, self.diagnostics_cfg, &self.run_opts.host_fns)?;
+      self.program = Some(program);
+    }
+    Ok(self.program.as_ref().unwrap())
+  }
+
+  /// Compiles an expression as the entrypoint of the program, linked with the compiled definitions.
+  fn compile_expr(&mut self, term: Term) -> Result<CompiledExpr, Diagnostics> {
+    let main = Name::new(ENTRY_POINT);
+    let rule = Rule { pats: vec![], guard: None, body: term, loc: Loc::default() };
+    let mut defs = IndexMap::new();
+    defs.insert(main.clone(), Definition { name: main, rules: vec![rule], builtin: false });
+    let CompiledDefs { defs, nets, labels, diagnostics } = self.compile_defs(defs)?;
+
+    let program = self.program()?;
+    let mut full_book = program.book.clone();
+    full_book.defs.extend(defs.defs.iter().map(|(nam, def)| (nam.clone(), def.clone())));
+    full_book.entrypoint = defs.entrypoint.clone();
+    let mut core_book = program.core_book.clone();
+    for (nam, net) in nets.iter() {
+      core_book.insert(nam.clone(), net.clone());
+    }
+
+    Ok(CompiledExpr { defs, nets, book: full_book, core_book, labels, diagnostics })
+  }
+
+  /// Compiles some definitions on top of the already compiled ones.
+  ///
+  /// The other definitions of the session are replaced by placeholders so that only the given ones are compiled.
+  fn compile_defs(&mut self, new_defs: IndexMap<Name, Definition>) -> Result<CompiledDefs, Diagnostics> {
+    let compile_opts = self.compile_opts.clone();
+    let diagnostics_cfg = self.diagnostics_cfg;
+    let user_book = &self.book;
+
+    let mut book = Book { adts: user_book.adts.clone(), ctrs: user_book.ctrs.clone(), ..Book::default() };
+    let new_entrypoint = new_defs.keys().any(is_entrypoint);
+    for (nam, def) in user_book.defs.iter() {
+      let replaced = new_defs.contains_key(nam) || (new_entrypoint && is_entrypoint(nam));
+      if !replaced {
+        book.defs.insert(nam.clone(), placeholder(nam, def.builtin));
+      }
+    }
+    if !new_entrypoint && !book.defs.keys().any(is_entrypoint) {
+      let main = Name::new(ENTRY_POINT);
+      book.defs.insert(main.clone(), placeholder(&main, false));
+    }
+    let names = new_defs.keys().cloned().collect::<Vec<_>>();
+    book.defs.extend(new_defs);
+    self.run_opts.host_fns.declare(&mut book)?;
+
+    let mut diagnostics = desugar_book(&mut book, compile_opts.clone(), diagnostics_cfg, None)?;
+
+    let program = self.program()?;
+    // Keep only what the new definitions generated and what the program didn't need.
+    book.defs.retain(|nam, _| {
+      names.iter().any(|new| is_generated_by(nam, new)) || !program.book.defs.contains_key(nam)
+    });
+
+    let mut labels = program.labels.clone();
+    let nets = book_to_nets_with_labels(&book, &mut labels);
+    let mut nets = nets_to_hvmc(nets, &mut diagnostics)?;
+    if compile_opts.eta {
+      nets.values_mut().for_each(Net::eta_reduce);
+    }
+
+    Ok(CompiledDefs { defs: book, nets, labels, diagnostics })
+  }
+
+  fn show_type(&mut self, term: Term) -> Result<String, Diagnostics> {
+    let mut book = self.book.clone();
+    book.defs.retain(|nam, _| !is_entrypoint(nam));
+    let main = Name::new(ENTRY_POINT);
+    let rule = Rule { pats: vec![], guard: None, body: term, loc: Loc::default() };
+    book.defs.insert(main.clone(), Definition { name: main.clone(), rules: vec![rule], builtin: false });
+    self.run_opts.host_fns.declare(&mut book)?;
+
+    let (types, diagnostics) = infer_book_types(&mut book, self.compile_opts.clone(), self.diagnostics_cfg)?;
+    Ok(format!("{diagnostics}{}\n", types[&main]))
+  }
+
+  fn show_stats(&self) -> String {
+    match &self.stats {
+      Some(stats) => format!("{stats}\n"),
+      None => "No expression was evaluated yet.\n".to_string(),
+    }
+  }
+
+  fn toggle_lazy(&mut self) -> String {
+    self.run_opts.lazy_mode = !self.run_opts.lazy_mode;
+    if self.run_opts.lazy_mode {
+      // Parallel mode is not yet implemented for lazy mode.
+      self.run_opts.single_core = true;
+      "Switched to lazy mode.\n".to_string()
+    } else {
+      // Recursive definitions can expand infinitely in strict mode without floating their combinators.
+      if !self.compile_opts.float_combinators {
+        self.compile_opts.float_combinators = true;
+        self.program = None;
+      }
+      "Switched to strict mode.\n".to_string()
+    }
+  }
+}
+
+fn compile_program(
+  book: &Book,
+  compile_opts: &CompileOpts,
+  diagnostics_cfg: DiagnosticsConfig,
+  host_fns: &HostFns,
+) -> Result<(CompiledProgram, Diagnostics), Diagnostics> {
+  let mut book = book.clone();
+  host_fns.declare(&mut book)?;
+  if !book.defs.keys().any(is_entrypoint) {
+    // Expressions are compiled as the entrypoint, but the program needs one until then.
+    let main = Name::new(ENTRY_POINT);
+    book.defs.insert(main.clone(), placeholder(&main, false));
+  }
+
+  let mut diagnostics = desugar_book(&mut book, compile_opts.clone(), diagnostics_cfg, None)?;
+
+  let mut labels = Labels::default();
+  let nets = book_to_nets_with_labels(&book, &mut labels);
+  let mut core_book = nets_to_hvmc(nets, &mut diagnostics)?;
+  if compile_opts.eta {
+    core_book.values_mut().for_each(Net::eta_reduce);
+  }
+  mutual_recursion::check_cycles(&core_book, &mut diagnostics)?;
+
+  Ok((CompiledProgram { book, core_book, labels }, diagnostics))
+}
+
+fn parse_definitions(input: &str) -> Result<Book, String> {
+  let file = Arc::new(SourceFile { path: "<repl>".to_string(), code: input.to_string() });
+  TermParser::new_source_book(&file, Book::default())
+}
+
+/// Used to decide which parse error to show for inputs that are neither expressions nor definitions.
+fn looks_like_definition(input: &str) -> bool {
+  input.starts_with("data ")
+    || (input.contains('=') && !input.starts_with("let ") && !input.starts_with("use "))
+}
+
+/// Reads the lines of the next entry, continuing while its delimiters are not balanced.
+/// Returns `None` at the end of the input.
+pub fn read_entry(
+  lines: &mut impl Iterator<Item = io::Result<String>>,
+  output: &mut impl Write,
+) -> io::Result<Option<String>> {
+  let mut entry = String::new();
+  let mut prompt = "> ";
+  loop {
+    write!(output, "{prompt}")?;
+    output.flush()?;
+    let Some(line) = lines.next() else {
+      return Ok((!entry.trim().is_empty()).then_some(entry));
+    };
+    entry.push_str(&line?);
+    entry.push('\n');
+    if is_complete(&entry) {
+      return Ok(Some(entry));
+    }
+    prompt = "| ";
+  }
+}
+
+/// Whether all the parentheses, brackets and braces of an entry are closed.
+fn is_complete(entry: &str) -> bool {
+  let mut depth = 0i64;
+  let mut chars = entry.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '(' | '[' | '{' => depth += 1,
+      ')' | ']' | '}' => depth -= 1,
+      '"' | '\'' => {
+        while let Some(d) = chars.next() {
+          match d {
+            '\\' => _ = chars.next(),
+            _ if d == c => break,
+            _ => {}
+          }
+        }
+      }
+      '/' if chars.peek() == Some(&'/') => while chars.next_if(|&d| d != '\n').is_some() {},
+      _ => {}
+    }
+  }
+  depth <= 0
+}
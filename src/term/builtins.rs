@@ -1,5 +1,5 @@
 use super::{parser::TermParser, Book, Name, Pattern, Term};
-use crate::maybe_grow;
+use crate::{diagnostics::Loc, maybe_grow};
 
 const BUILTINS: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/term/builtins.hvm"));
 
@@ -22,6 +22,11 @@ pub const NAT: &str = "Nat";
 pub const NAT_SUCC: &str = "Nat.succ";
 pub const NAT_ZERO: &str = "Nat.zero";
 
+pub const IO: &str = "IO";
+pub const IO_PURE: &str = "IO.pure";
+pub const IO_CALL: &str = "IO.call";
+pub const IO_BIND: &str = "IO.bind";
+
 impl Book {
   pub fn builtins() -> Book {
     TermParser::new_book(BUILTINS, Book::default(), true)
@@ -32,19 +37,22 @@ impl Book {
     for def in self.defs.values_mut() {
       for rule in def.rules.iter_mut() {
         rule.pats.iter_mut().for_each(Pattern::encode_builtins);
-        rule.body.encode_builtins();
+        rule.terms_mut().for_each(Term::encode_builtins);
       }
     }
   }
 }
 
 impl Term {
-  fn encode_builtins(&mut self) {
+  pub(crate) fn encode_builtins(&mut self) {
     maybe_grow(|| match self {
-      Term::Lst { els } => *self = Term::encode_list(std::mem::take(els)),
-      Term::Str { val } => *self = Term::encode_str(val),
-      Term::Nat { val } => *self = Term::encode_nat(*val),
+      Term::Lst { els, loc } => *self = Term::encode_list(std::mem::take(els)).with_loc(std::mem::take(loc)),
+      Term::Str { val, loc } => *self = Term::encode_str(val).with_loc(std::mem::take(loc)),
+      Term::Nat { val, loc } => *self = Term::encode_nat(*val).with_loc(std::mem::take(loc)),
       _ => {
+        if let Term::Cas { arms, .. } = self {
+          arms.iter_mut().flat_map(|arm| &mut arm.pats).for_each(Pattern::encode_builtins);
+        }
         for child in self.children_mut() {
           child.encode_builtins();
         }
@@ -52,7 +60,7 @@ impl Term {
     })
   }
 
-  fn encode_list(elements: Vec<Term>) -> Term {
+  pub fn encode_list(elements: Vec<Term>) -> Term {
     elements.into_iter().rfold(Term::r#ref(LNIL), |acc, mut nxt| {
       nxt.encode_builtins();
       Term::call(Term::r#ref(LCONS), [nxt, acc])
@@ -60,9 +68,9 @@ impl Term {
   }
 
   pub fn encode_str(val: &str) -> Term {
-    val.chars().rfold(Term::r#ref(SNIL), |acc, char| {
-      Term::call(Term::r#ref(SCONS), [Term::Num { val: char as u64 }, acc])
-    })
+    val
+      .chars()
+      .rfold(Term::r#ref(SNIL), |acc, char| Term::call(Term::r#ref(SCONS), [Term::num(char as u64), acc]))
   }
 
   pub fn encode_nat(val: u64) -> Term {
@@ -81,9 +89,9 @@ impl Term {
 impl Pattern {
   pub fn encode_builtins(&mut self) {
     match self {
-      Pattern::Lst(pats) => *self = Self::encode_list(std::mem::take(pats)),
-      Pattern::Str(str) => *self = Self::encode_str(str),
-      Pattern::Ctr(_, pats) | Pattern::Tup(pats) => {
+      Pattern::Lst(pats, loc) => *self = Self::encode_list(std::mem::take(pats), loc.clone()),
+      Pattern::Str(str, loc) => *self = Self::encode_str(str, loc.clone()),
+      Pattern::Ctr(_, pats, _) | Pattern::Tup(pats, _) | Pattern::Or(pats, _) => {
         for pat in pats {
           pat.encode_builtins();
         }
@@ -92,21 +100,21 @@ impl Pattern {
     }
   }
 
-  fn encode_list(elements: Vec<Pattern>) -> Pattern {
-    let lnil = Pattern::Ctr(Name::new(LNIL), vec![]);
+  fn encode_list(elements: Vec<Pattern>, loc: Loc) -> Pattern {
+    let lnil = Pattern::Ctr(Name::new(LNIL), vec![], loc.clone());
 
     elements.into_iter().rfold(lnil, |acc, mut nxt| {
       nxt.encode_builtins();
-      Pattern::Ctr(Name::new(LCONS), vec![nxt, acc])
+      Pattern::Ctr(Name::new(LCONS), vec![nxt, acc], loc.clone())
     })
   }
 
-  fn encode_str(str: &str) -> Pattern {
-    let lnil = Pattern::Ctr(Name::new(SNIL), vec![]);
+  fn encode_str(str: &str, loc: Loc) -> Pattern {
+    let lnil = Pattern::Ctr(Name::new(SNIL), vec![], loc.clone());
 
     str.chars().rfold(lnil, |tail, head| {
-      let head = Pattern::Num(head as u64);
-      Pattern::Ctr(Name::new(SCONS), vec![head, tail])
+      let head = Pattern::Num(head as u64, loc.clone());
+      Pattern::Ctr(Name::new(SCONS), vec![head, tail], loc.clone())
     })
   }
 }
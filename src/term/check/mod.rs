@@ -1,3 +1,4 @@
 pub mod set_entrypoint;
 pub mod shared_names;
+pub mod type_check;
 pub mod unbound_vars;
@@ -0,0 +1,502 @@
+use crate::{
+  diagnostics::{Diagnostics, ToStringVerbose},
+  maybe_grow,
+  term::{
+    builtins::{LIST, NAT, STRING},
+    Book, Ctx, Definition, Name, Pattern, Term,
+  },
+};
+use std::{
+  collections::{HashMap, HashSet},
+  fmt,
+};
+
+/// The type of a term, as inferred by [`Ctx::check_types`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+  Var(usize),
+  Num,
+  Adt(Name),
+  Arr(Box<Type>, Box<Type>),
+  Tup(Vec<Type>),
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeErr {
+  Mismatch { expected: Type, found: Type },
+  Infinite { expected: Type, found: Type },
+  CtrArity { ctr: Name, expected: usize, found: usize },
+}
+
+impl Ctx<'_> {
+  /// Checks that all definitions are well typed, Hindley-Milner style.
+  ///
+  /// The fields of constructors are not annotated, so they are left unconstrained,
+  /// and definitions that mix types in ways the checker can't follow must be checked without `--types`.
+  /// Precondition: References have been resolved and pattern matching rules desugared into match terms.
+  pub fn check_types(&mut self) -> Result<(), Diagnostics> {
+    self.infer_types()?;
+    Ok(())
+  }
+
+  /// Infers the type of each definition, failing if any of them is not well typed.
+  pub fn infer_types(&mut self) -> Result<HashMap<Name, Type>, Diagnostics> {
+    self.info.start_pass();
+
+    let mut checker = TypeChecker::new(self.book);
+    for (def_name, def) in self.book.defs.iter() {
+      // Constructors and builtins are typed by their uses instead.
+      if !def.builtin && !self.book.ctrs.contains_key(def_name) {
+        checker.infer_def(def_name);
+      }
+    }
+
+    for (def_name, def) in self.book.defs.iter() {
+      if let Some(err) = checker.errs.remove(def_name) {
+        self.info.add_rule_error(err, def_name.clone(), def.span());
+      }
+    }
+
+    let types = checker.schemes.into_iter().map(|(nam, scheme)| (nam, scheme.typ)).collect();
+    self.info.fatal(types)
+  }
+}
+
+/// A type with its generalized type variables.
+#[derive(Debug, Clone)]
+struct Scheme {
+  vars: Vec<usize>,
+  typ: Type,
+}
+
+enum UnifyErr {
+  Mismatch,
+  Infinite,
+}
+
+struct TypeChecker<'book> {
+  book: &'book Book,
+  /// What each type variable was unified with.
+  subst: Vec<Option<Type>>,
+  /// The generalized types of the definitions already inferred.
+  schemes: HashMap<Name, Scheme>,
+  /// The definitions being inferred, with their still monomorphic types.
+  pending: Vec<(Name, Type)>,
+  /// The variables of each scopeless lambda of the current definition.
+  chns: HashMap<Name, Type>,
+  errs: HashMap<Name, TypeErr>,
+}
+
+type Env = HashMap<Name, Vec<Type>>;
+
+impl<'book> TypeChecker<'book> {
+  fn new(book: &'book Book) -> Self {
+    Self {
+      book,
+      subst: vec![],
+      schemes: HashMap::new(),
+      pending: vec![],
+      chns: HashMap::new(),
+      errs: HashMap::new(),
+    }
+  }
+
+  /// Infers the type of a definition, first inferring the definitions it depends on.
+  fn infer_def(&mut self, def_name: &Name) {
+    if self.schemes.contains_key(def_name) || self.pending.iter().any(|(nam, _)| nam == def_name) {
+      return;
+    }
+    let book = self.book;
+    let def = &book.defs[def_name];
+
+    let typ = self.fresh();
+    self.pending.push((def_name.clone(), typ.clone()));
+    let chns = std::mem::take(&mut self.chns);
+    let res = self.infer_rules(def).and_then(|found| self.unify(&typ, &found));
+    self.chns = chns;
+    self.pending.pop();
+
+    let scheme = match res {
+      Ok(()) => self.generalize(&typ),
+      Err(err) => {
+        // Keep checking the rest of the program as if this definition was untyped.
+        self.errs.insert(def_name.clone(), err);
+        self.any()
+      }
+    };
+    self.schemes.insert(def_name.clone(), scheme);
+  }
+
+  fn infer_rules(&mut self, def: &Definition) -> Result<Type, TypeErr> {
+    let typ = self.fresh();
+    for rule in def.rules.iter() {
+      let mut env = Env::new();
+      let pats = rule.pats.iter().map(|pat| self.infer_pat(pat, &mut env)).collect::<Vec<_>>();
+      let bod = self.infer(&rule.body, &mut env)?;
+      let rule_typ = pats.into_iter().rfold(bod, |bod, pat| Type::Arr(Box::new(pat), Box::new(bod)));
+      self.unify(&typ, &rule_typ)?;
+    }
+    Ok(typ)
+  }
+
+  fn infer_pat(&mut self, pat: &Pattern, env: &mut Env) -> Type {
+    for bind in pat.binds().flatten() {
+      let typ = self.fresh();
+      env.entry(bind.clone()).or_default().push(typ);
+    }
+    self.pat_type(pat, env)
+  }
+
+  /// The type of a pattern whose binds are already in scope.
+  fn pat_type(&mut self, pat: &Pattern, env: &Env) -> Type {
+    let book = self.book;
+    match pat {
+      Pattern::Var(Some(nam), _) => env[nam].last().unwrap().clone(),
+      Pattern::Ctr(nam, _, _) if book.ctrs.contains_key(nam) => Type::Adt(book.ctrs[nam].clone()),
+      Pattern::Num(..) => Type::Num,
+      Pattern::Tup(els, _) => Type::Tup(els.iter().map(|el| self.pat_type(el, env)).collect()),
+      Pattern::Lst(..) => Type::Adt(Name::new(LIST)),
+      Pattern::Str(..) => Type::Adt(Name::new(STRING)),
+      Pattern::Var(None, _) | Pattern::Ctr(..) | Pattern::Or(..) => self.fresh(),
+    }
+  }
+
+  fn infer(&mut self, term: &Term, env: &mut Env) -> Result<Type, TypeErr> {
+    maybe_grow(|| match term {
+      Term::Var { nam, .. } => {
+        Ok(env.get(nam).and_then(|typs| typs.last()).cloned().unwrap_or_else(|| self.fresh()))
+      }
+      Term::Lam { nam, bod, .. } => {
+        let var = self.fresh();
+        let bod = self.infer_scoped(bod, [(nam, var.clone())], env)?;
+        Ok(Type::Arr(Box::new(var), Box::new(bod)))
+      }
+      Term::Chn { nam, bod, .. } => {
+        let var = match nam {
+          Some(nam) => self.chn_type(nam),
+          None => self.fresh(),
+        };
+        let bod = self.infer(bod, env)?;
+        Ok(Type::Arr(Box::new(var), Box::new(bod)))
+      }
+      Term::Lnk { nam, .. } => Ok(self.chn_type(nam)),
+      Term::Let { nam, val, nxt, .. } | Term::Use { nam, val, nxt, .. } => {
+        let val = self.infer(val, env)?;
+        self.infer_scoped(nxt, [(nam, val)], env)
+      }
+      Term::App { fun, arg, .. } => {
+        self.check_ctr_arity(term)?;
+        let fun = self.infer(fun, env)?;
+        let arg = self.infer(arg, env)?;
+        let ret = self.fresh();
+        self.unify(&fun, &Type::Arr(Box::new(arg), Box::new(ret.clone())))?;
+        Ok(ret)
+      }
+      Term::Ltp { bnd, val, nxt, .. } => {
+        let val = self.infer(val, env)?;
+        let els = bnd.iter().map(|_| self.fresh()).collect::<Vec<_>>();
+        self.unify(&Type::Tup(els.clone()), &val)?;
+        self.infer_scoped(nxt, bnd.iter().zip(els), env)
+      }
+      Term::Tup { els, .. } => {
+        Ok(Type::Tup(els.iter().map(|el| self.infer(el, env)).collect::<Result<_, _>>()?))
+      }
+      Term::Dup { bnd, val, nxt, .. } => {
+        let val = self.infer(val, env)?;
+        self.infer_scoped(nxt, bnd.iter().map(|nam| (nam, val.clone())), env)
+      }
+      Term::Sup { els, .. } => {
+        let typ = self.fresh();
+        for el in els {
+          let el = self.infer(el, env)?;
+          self.unify(&typ, &el)?;
+        }
+        Ok(typ)
+      }
+      Term::Num { .. } => Ok(Type::Num),
+      Term::Nat { .. } => Ok(Type::Adt(Name::new(NAT))),
+      Term::Str { .. } => Ok(Type::Adt(Name::new(STRING))),
+      Term::Lst { els, .. } => {
+        for el in els {
+          self.infer(el, env)?;
+        }
+        Ok(Type::Adt(Name::new(LIST)))
+      }
+      Term::Opx { fst, snd, .. } => {
+        let fst = self.infer(fst, env)?;
+        self.unify(&Type::Num, &fst)?;
+        let snd = self.infer(snd, env)?;
+        self.unify(&Type::Num, &snd)?;
+        Ok(Type::Num)
+      }
+      Term::Mat { arg, bnd, with: _, arms, loc: _ } => {
+        let book = self.book;
+        let arg = self.infer(arg, env)?;
+        for (ctr, _, _) in arms {
+          if let Some(ctr) = ctr
+            && let Some(adt) = book.ctrs.get(ctr)
+          {
+            self.unify(&Type::Adt(adt.clone()), &arg)?;
+          }
+        }
+        let typ = self.fresh();
+        for (_, fields, bod) in arms {
+          let fields = fields.iter().map(|field| (field, self.fresh())).collect::<Vec<_>>();
+          let bod = self.infer_scoped(bod, [(bnd, arg.clone())].into_iter().chain(fields), env)?;
+          self.unify(&typ, &bod)?;
+        }
+        Ok(typ)
+      }
+      Term::Swt { arg, bnd, with: _, pred, arms, loc: _ } => {
+        let arg = self.infer(arg, env)?;
+        self.unify(&Type::Num, &arg)?;
+        let typ = self.fresh();
+        let (succ, nums) = arms.split_last().unwrap();
+        for num in nums {
+          let num = self.infer_scoped(num, [(bnd, Type::Num)], env)?;
+          self.unify(&typ, &num)?;
+        }
+        let succ = self.infer_scoped(succ, [(bnd, Type::Num), (pred, Type::Num)], env)?;
+        self.unify(&typ, &succ)?;
+        Ok(typ)
+      }
+      Term::Ref { nam, .. } => Ok(self.ref_type(nam)),
+      Term::Era { .. } | Term::Err { .. } => Ok(self.fresh()),
+      // Desugared into calls to `IO.bind` before type checking.
+      Term::Do { .. } => unreachable!(),
+      // Desugared into operations, calls and switches before type checking.
+      Term::Infix { .. } | Term::If { .. } => unreachable!(),
+      // Compiled into matches and switches before type checking.
+      Term::Cas { .. } => unreachable!(),
+    })
+  }
+
+  /// Infers the type of a term with some extra variables in scope.
+  fn infer_scoped<'a>(
+    &mut self,
+    term: &Term,
+    binds: impl IntoIterator<Item = (&'a Option<Name>, Type)>,
+    env: &mut Env,
+  ) -> Result<Type, TypeErr> {
+    let binds = binds.into_iter().filter_map(|(nam, typ)| Some((nam.as_ref()?, typ))).collect::<Vec<_>>();
+    for (nam, typ) in binds.iter() {
+      env.entry((*nam).clone()).or_default().push(typ.clone());
+    }
+    let res = self.infer(term, env);
+    for (nam, _) in binds {
+      env.get_mut(nam).unwrap().pop();
+    }
+    res
+  }
+
+  fn ref_type(&mut self, nam: &Name) -> Type {
+    let book = self.book;
+    if let Some(adt) = book.ctrs.get(nam) {
+      let fields = book.adts[adt].ctrs[nam].len();
+      return (0 .. fields)
+        .fold(Type::Adt(adt.clone()), |typ, _| Type::Arr(Box::new(self.fresh()), Box::new(typ)));
+    }
+
+    match book.defs.get(nam) {
+      // Builtin definitions are written in untyped terms.
+      Some(def) if !def.builtin => {
+        self.infer_def(nam);
+        if let Some((_, typ)) = self.pending.iter().find(|(pending, _)| pending == nam) {
+          typ.clone()
+        } else {
+          let scheme = self.schemes[nam].clone();
+          self.instantiate(&scheme)
+        }
+      }
+      _ => self.fresh(),
+    }
+  }
+
+  fn chn_type(&mut self, nam: &Name) -> Type {
+    if let Some(typ) = self.chns.get(nam) {
+      return typ.clone();
+    }
+    let typ = self.fresh();
+    self.chns.insert(nam.clone(), typ.clone());
+    typ
+  }
+
+  /// Checks that a constructor is not applied to more arguments than it has fields.
+  fn check_ctr_arity(&self, term: &Term) -> Result<(), TypeErr> {
+    let mut fun = term;
+    let mut found = 0;
+    while let Term::App { fun: nxt, .. } = fun {
+      fun = nxt;
+      found += 1;
+    }
+    if let Term::Ref { nam, .. } = fun
+      && let Some(adt) = self.book.ctrs.get(nam)
+    {
+      let expected = self.book.adts[adt].ctrs[nam].len();
+      if found > expected {
+        return Err(TypeErr::CtrArity { ctr: nam.clone(), expected, found });
+      }
+    }
+    Ok(())
+  }
+
+  fn fresh(&mut self) -> Type {
+    self.subst.push(None);
+    Type::Var(self.subst.len() - 1)
+  }
+
+  /// A scheme that fits any type.
+  fn any(&mut self) -> Scheme {
+    let Type::Var(var) = self.fresh() else { unreachable!() };
+    Scheme { vars: vec![var], typ: Type::Var(var) }
+  }
+
+  fn unify(&mut self, expected: &Type, found: &Type) -> Result<(), TypeErr> {
+    self.unify_types(expected, found).map_err(|err| {
+      let (expected, found) = (self.resolve(expected), self.resolve(found));
+      match err {
+        UnifyErr::Mismatch => TypeErr::Mismatch { expected, found },
+        UnifyErr::Infinite => TypeErr::Infinite { expected, found },
+      }
+    })
+  }
+
+  fn unify_types(&mut self, a: &Type, b: &Type) -> Result<(), UnifyErr> {
+    match (self.shallow(a), self.shallow(b)) {
+      (Type::Var(a), Type::Var(b)) if a == b => Ok(()),
+      (Type::Var(var), typ) | (typ, Type::Var(var)) => {
+        if self.occurs(var, &typ) {
+          return Err(UnifyErr::Infinite);
+        }
+        self.subst[var] = Some(typ);
+        Ok(())
+      }
+      (Type::Num, Type::Num) => Ok(()),
+      (Type::Adt(a), Type::Adt(b)) if a == b => Ok(()),
+      (Type::Arr(a_arg, a_ret), Type::Arr(b_arg, b_ret)) => {
+        self.unify_types(&a_arg, &b_arg)?;
+        self.unify_types(&a_ret, &b_ret)
+      }
+      (Type::Tup(a), Type::Tup(b)) if a.len() == b.len() => {
+        a.iter().zip(b.iter()).try_for_each(|(a, b)| self.unify_types(a, b))
+      }
+      _ => Err(UnifyErr::Mismatch),
+    }
+  }
+
+  /// Follows the substitution until the outermost type is known.
+  fn shallow(&self, typ: &Type) -> Type {
+    let mut typ = typ;
+    while let Type::Var(var) = typ
+      && let Some(nxt) = &self.subst[*var]
+    {
+      typ = nxt;
+    }
+    typ.clone()
+  }
+
+  /// Applies the substitution to the whole type.
+  fn resolve(&self, typ: &Type) -> Type {
+    maybe_grow(|| match self.shallow(typ) {
+      Type::Arr(arg, ret) => Type::Arr(Box::new(self.resolve(&arg)), Box::new(self.resolve(&ret))),
+      Type::Tup(els) => Type::Tup(els.iter().map(|el| self.resolve(el)).collect()),
+      typ => typ,
+    })
+  }
+
+  fn occurs(&self, var: usize, typ: &Type) -> bool {
+    let mut vars = HashSet::new();
+    self.resolve(typ).free_vars(&mut vars);
+    vars.contains(&var)
+  }
+
+  /// Generalizes the variables that don't appear in the definitions still being inferred.
+  fn generalize(&self, typ: &Type) -> Scheme {
+    let typ = self.resolve(typ);
+    let mut pending = HashSet::new();
+    for (_, typ) in self.pending.iter() {
+      self.resolve(typ).free_vars(&mut pending);
+    }
+    let mut vars = HashSet::new();
+    typ.free_vars(&mut vars);
+    Scheme { vars: vars.difference(&pending).copied().collect(), typ }
+  }
+
+  fn instantiate(&mut self, scheme: &Scheme) -> Type {
+    let vars = scheme.vars.iter().map(|var| (*var, self.fresh())).collect::<HashMap<_, _>>();
+    scheme.typ.replace_vars(&vars)
+  }
+}
+
+impl Type {
+  fn free_vars(&self, vars: &mut HashSet<usize>) {
+    maybe_grow(|| match self {
+      Type::Var(var) => {
+        vars.insert(*var);
+      }
+      Type::Arr(arg, ret) => {
+        arg.free_vars(vars);
+        ret.free_vars(vars);
+      }
+      Type::Tup(els) => els.iter().for_each(|el| el.free_vars(vars)),
+      Type::Num | Type::Adt(_) => {}
+    })
+  }
+
+  fn replace_vars(&self, vars: &HashMap<usize, Type>) -> Type {
+    maybe_grow(|| match self {
+      Type::Var(var) => vars.get(var).cloned().unwrap_or(Type::Var(*var)),
+      Type::Arr(arg, ret) => Type::Arr(Box::new(arg.replace_vars(vars)), Box::new(ret.replace_vars(vars))),
+      Type::Tup(els) => Type::Tup(els.iter().map(|el| el.replace_vars(vars)).collect()),
+      Type::Num | Type::Adt(_) => self.clone(),
+    })
+  }
+
+  /// Shows the type, naming its variables in order of appearance.
+  fn display(&self, names: &mut HashMap<usize, String>) -> String {
+    maybe_grow(|| match self {
+      Type::Var(var) => {
+        let len = names.len();
+        names.entry(*var).or_insert_with(|| var_name(len)).clone()
+      }
+      Type::Num => "Num".to_string(),
+      Type::Adt(nam) => nam.to_string(),
+      Type::Arr(arg, ret) => format!("({} -> {})", arg.display(names), ret.display(names)),
+      Type::Tup(els) => {
+        format!("({})", els.iter().map(|el| el.display(names)).collect::<Vec<_>>().join(", "))
+      }
+    })
+  }
+}
+
+impl fmt::Display for Type {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.display(&mut HashMap::new()))
+  }
+}
+
+fn var_name(idx: usize) -> String {
+  let letter = (b'a' + (idx % 26) as u8) as char;
+  if idx < 26 { letter.to_string() } else { format!("{letter}{}", idx / 26) }
+}
+
+impl ToStringVerbose for TypeErr {
+  fn to_string_verbose(&self, _verbose: bool) -> String {
+    let mut names = HashMap::new();
+    match self {
+      TypeErr::Mismatch { expected, found } => {
+        let expected = expected.display(&mut names);
+        let found = found.display(&mut names);
+        format!("Type mismatch: expected '{expected}', found '{found}'.")
+      }
+      TypeErr::Infinite { expected, found } => {
+        let expected = expected.display(&mut names);
+        let found = found.display(&mut names);
+        format!("Infinite type: can't match '{expected}' with '{found}'.")
+      }
+      TypeErr::CtrArity { ctr, expected, found } => {
+        format!("Too many arguments for constructor '{ctr}': expected {expected}, found {found}.")
+      }
+    }
+  }
+}
@@ -1,5 +1,5 @@
 use crate::{
-  diagnostics::{Diagnostics, ToStringVerbose},
+  diagnostics::{Diagnostics, Span, ToStringVerbose},
   maybe_grow,
   term::{Ctx, Name, Term},
 };
@@ -17,18 +17,18 @@ impl Ctx<'_> {
     self.info.start_pass();
 
     for (def_name, def) in self.book.defs.iter_mut() {
-      let mut errs = Vec::new();
       for rule in &mut def.rules {
+        let mut errs = Vec::new();
         let mut scope = HashMap::new();
         for pat in &rule.pats {
           pat.binds().for_each(|nam| push_scope(nam.as_ref(), &mut scope));
         }
 
         rule.body.check_unbound_vars(&mut scope, &mut errs);
-      }
 
-      for err in errs {
-        self.info.add_rule_error(err, def_name.clone());
+        for (err, span) in errs {
+          self.info.add_rule_error(err, def_name.clone(), span.or_else(|| rule.loc.span()));
+        }
       }
     }
 
@@ -39,45 +39,61 @@ impl Ctx<'_> {
 impl Term {
   /// Checks that all variables are bound.
   /// Precondition: References have been resolved, implicit binds have been solved.
-
+  ///
+  /// The errors are returned with the location of the variable they refer to, if known.
   pub fn check_unbound_vars<'a>(
     &'a mut self,
     scope: &mut HashMap<&'a Name, u64>,
-    errs: &mut Vec<UnboundVarErr>,
+    errs: &mut Vec<(UnboundVarErr, Option<Span>)>,
   ) {
     let mut globals = HashMap::new();
     check_uses(self, scope, &mut globals, errs);
 
     // Check global vars
-    for (nam, (declared, used)) in globals.into_iter().filter(|(_, (d, u))| !(*d == 1 && *u == 1)) {
-      errs.push(UnboundVarErr::Global { var: nam.clone(), declared, used });
+    for (nam, (declared, used, decl_span, use_span)) in globals {
+      if declared == 1 && used == 1 {
+        continue;
+      }
+      // Points at the uses when the error is about them, and at the declarations otherwise.
+      let span = if declared <= 1 && used > 0 { use_span } else { decl_span };
+      errs.push((UnboundVarErr::Global { var: nam.clone(), declared, used }, span));
     }
   }
 }
 
+/// How many times each global var name was declared and used, and where it was first declared and used.
+pub type Globals<'a> = HashMap<&'a Name, (usize, usize, Option<Span>, Option<Span>)>;
+
 /// Scope has the number of times a name was declared in the current scope
-/// Globals has how many times a global var name was declared and used.
 pub fn check_uses<'a>(
   term: &'a mut Term,
   scope: &mut HashMap<&'a Name, u64>,
-  globals: &mut HashMap<&'a Name, (usize, usize)>,
-  errs: &mut Vec<UnboundVarErr>,
+  globals: &mut Globals<'a>,
+  errs: &mut Vec<(UnboundVarErr, Option<Span>)>,
 ) {
   maybe_grow(move || match term {
-    Term::Var { nam } => {
+    Term::Var { nam, loc } => {
       if !scope.contains_key(nam) {
-        errs.push(UnboundVarErr::Local(nam.clone()));
-        *term = Term::Err;
+        errs.push((UnboundVarErr::Local(nam.clone()), loc.span()));
+        *term = Term::default();
       }
     }
-    Term::Chn { nam, bod, .. } => {
+    Term::Chn { nam, bod, loc, .. } => {
       if let Some(nam) = nam {
-        globals.entry(nam).or_default().0 += 1;
+        let global = globals.entry(nam).or_default();
+        global.0 += 1;
+        if global.2.is_none() {
+          global.2 = loc.span();
+        }
       }
       check_uses(bod, scope, globals, errs);
     }
-    Term::Lnk { nam } => {
-      globals.entry(nam).or_default().1 += 1;
+    Term::Lnk { nam, loc } => {
+      let global = globals.entry(nam).or_default();
+      global.1 += 1;
+      if global.3.is_none() {
+        global.3 = loc.span();
+      }
     }
 
     _ => {
@@ -1,4 +1,4 @@
-use super::{Book, Definition, Name, Pattern, Rule, Tag, Term};
+use super::{Book, Definition, Name, NumType, Pattern, Rule, Tag, Term, NUM_SIGN_BIT, U60_MASK};
 use crate::maybe_grow;
 use std::{fmt, ops::Deref};
 
@@ -43,26 +43,33 @@ macro_rules! display {
 impl fmt::Display for Term {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     maybe_grow(|| match self {
-      Term::Lam { tag, nam, bod } => {
+      Term::Lam { tag, nam, bod, .. } => {
         write!(f, "{}λ{} {}", tag.display_padded(), var_as_str(nam), bod)
       }
-      Term::Var { nam } => write!(f, "{nam}"),
-      Term::Chn { tag, nam, bod } => {
+      Term::Var { nam, .. } => write!(f, "{nam}"),
+      Term::Chn { tag, nam, bod, .. } => {
         write!(f, "{}λ${} {}", tag.display_padded(), var_as_str(nam), bod)
       }
-      Term::Lnk { nam } => write!(f, "${nam}"),
-      Term::Let { nam, val, nxt } => {
+      Term::Lnk { nam, .. } => write!(f, "${nam}"),
+      Term::Let { nam, val, nxt, .. } => {
         write!(f, "let {} = {}; {}", var_as_str(nam), val, nxt)
       }
-      Term::Use { nam, val, nxt } => {
+      Term::Use { nam, val, nxt, .. } => {
         let Some(nam) = nam else { unreachable!() };
         write!(f, "use {} = {}; {}", nam, val, nxt)
       }
-      Term::Ref { nam: def_name } => write!(f, "{def_name}"),
-      Term::App { tag, fun, arg } => {
+      Term::Do { .. } => {
+        write!(
+          f,
+          "do {{ {} }}",
+          DisplayJoin(|| self.do_stmts().map(|(nam, val)| display_do_stmt(nam, val)), "; ")
+        )
+      }
+      Term::Ref { nam: def_name, .. } => write!(f, "{def_name}"),
+      Term::App { tag, fun, arg, .. } => {
         write!(f, "{}({} {})", tag.display_padded(), fun.display_app(tag), arg)
       }
-      Term::Mat { arg, bnd, with, arms: rules } => {
+      Term::Mat { arg, bnd, with, arms: rules, loc: _ } => {
         let with: Box<dyn std::fmt::Display> = if with.is_empty() {
           Box::new(display!(""))
         } else {
@@ -77,7 +84,32 @@ impl fmt::Display for Term {
           DisplayJoin(|| rules.iter().map(|rule| display!("{}: {}", var_as_str(&rule.0), rule.2)), "; "),
         )
       }
-      Term::Swt { arg, bnd, with, pred: _, arms } => {
+      Term::Cas { args, bnds, with, arms, loc: _ } => {
+        let with: Box<dyn std::fmt::Display> = if with.is_empty() {
+          Box::new(display!(""))
+        } else {
+          Box::new(display!(" with {}", DisplayJoin(|| with, ", ")))
+        };
+        let args = DisplayJoin(
+          || bnds.iter().zip(args).map(|(bnd, arg)| display!("{} = {}", var_as_str(bnd), arg)),
+          ", ",
+        );
+        let arms = DisplayJoin(
+          || {
+            arms.iter().map(|rule| {
+              display!(
+                "{}{}: {}",
+                DisplayJoin(|| rule.pats.iter(), ", "),
+                display_guard(&rule.guard),
+                rule.body
+              )
+            })
+          },
+          "; ",
+        );
+        write!(f, "match {}{} {{ {} }}", args, with, arms)
+      }
+      Term::Swt { arg, bnd, with, pred: _, arms, loc: _ } => {
         let with: Box<dyn std::fmt::Display> = if with.is_empty() {
           Box::new(display!(""))
         } else {
@@ -93,25 +125,32 @@ impl fmt::Display for Term {
         );
         write!(f, "switch {} = {}{} {{ {} }}", bnd.as_ref().unwrap(), arg, with, arms)
       }
-      Term::Ltp { bnd, val, nxt } => {
+      Term::Ltp { bnd, val, nxt, .. } => {
         write!(f, "let ({}) = {}; {}", DisplayJoin(|| bnd.iter().map(var_as_str), ", "), val, nxt)
       }
-      Term::Tup { els } => write!(f, "({})", DisplayJoin(|| els.iter(), ", "),),
-      Term::Dup { tag, bnd, val, nxt } => {
+      Term::Tup { els, .. } => write!(f, "({})", DisplayJoin(|| els.iter(), ", "),),
+      Term::Dup { tag, bnd, val, nxt, .. } => {
         write!(f, "let {}{{{}}} = {}; {}", tag, DisplayJoin(|| bnd.iter().map(var_as_str), " "), val, nxt)
       }
-      Term::Sup { tag, els } => {
+      Term::Sup { tag, els, .. } => {
         write!(f, "{}{{{}}}", tag, DisplayJoin(|| els, " "))
       }
-      Term::Era => write!(f, "*"),
-      Term::Num { val } => write!(f, "{val}"),
-      Term::Nat { val } => write!(f, "#{val}"),
-      Term::Str { val } => write!(f, "{val:?}"),
-      Term::Opx { opr, fst, snd } => {
+      Term::Era { .. } => write!(f, "*"),
+      Term::Num { val, typ, .. } => write!(f, "{}", display_num(*val, *typ)),
+      Term::Nat { val, .. } => write!(f, "#{val}"),
+      Term::Str { val, .. } => write!(f, "{val:?}"),
+      Term::Opx { opr, fst, snd, .. } => {
         write!(f, "({} {} {})", opr, fst, snd)
       }
-      Term::Lst { els } => write!(f, "[{}]", DisplayJoin(|| els.iter(), ", "),),
-      Term::Err => write!(f, "<Invalid>"),
+      Term::Infix { fst, rest, .. } => {
+        let rest = DisplayJoin(|| rest.iter().map(|(opr, el)| display!(" {} {}", opr.symbol, el)), "");
+        write!(f, "({}{})", fst, rest)
+      }
+      Term::If { cond, then, els, .. } => {
+        write!(f, "if {} {{ {} }} else {{ {} }}", cond, then, els)
+      }
+      Term::Lst { els, .. } => write!(f, "[{}]", DisplayJoin(|| els.iter(), ", "),),
+      Term::Err { .. } => write!(f, "<Invalid>"),
     })
   }
 }
@@ -130,15 +169,16 @@ impl fmt::Display for Tag {
 impl fmt::Display for Pattern {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     match self {
-      Pattern::Var(None) => write!(f, "*"),
-      Pattern::Var(Some(nam)) => write!(f, "{nam}"),
-      Pattern::Ctr(nam, pats) => {
+      Pattern::Var(None, _) => write!(f, "*"),
+      Pattern::Var(Some(nam), _) => write!(f, "{nam}"),
+      Pattern::Ctr(nam, pats, _) => {
         write!(f, "({}{})", nam, DisplayJoin(|| pats.iter().map(|p| display!(" {p}")), ""))
       }
-      Pattern::Num(num) => write!(f, "{num}"),
-      Pattern::Tup(pats) => write!(f, "({})", DisplayJoin(|| pats, ", ")),
-      Pattern::Lst(pats) => write!(f, "[{}]", DisplayJoin(|| pats, ", ")),
-      Pattern::Str(str) => write!(f, "\"{str}\""),
+      Pattern::Num(num, _) => write!(f, "{num}"),
+      Pattern::Tup(pats, _) => write!(f, "({})", DisplayJoin(|| pats, ", ")),
+      Pattern::Lst(pats, _) => write!(f, "[{}]", DisplayJoin(|| pats, ", ")),
+      Pattern::Str(str, _) => write!(f, "\"{str}\""),
+      Pattern::Or(alts, _) => write!(f, "({})", DisplayJoin(|| alts, " | ")),
     }
   }
 }
@@ -146,9 +186,10 @@ impl fmt::Display for Pattern {
 impl Rule {
   pub fn display<'a>(&'a self, def_name: &'a Name) -> impl fmt::Display + 'a {
     display!(
-      "({}{}) = {}",
+      "({}{}){} = {}",
       def_name,
       DisplayJoin(|| self.pats.iter().map(|x| display!(" {x}")), ""),
+      display_guard(&self.guard),
       self.body
     )
   }
@@ -161,8 +202,11 @@ impl fmt::Display for Definition {
 }
 
 impl fmt::Display for Book {
+  /// Shows the definitions of the program. The builtin functions, which every book has, are skipped,
+  /// but the constructors of the builtin datatypes are shown once they're encoded as definitions.
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    write!(f, "{}", DisplayJoin(|| self.defs.values(), "\n\n"))
+    let defs = || self.defs.values().filter(|def| !def.builtin || self.ctrs.contains_key(&def.name));
+    write!(f, "{}", DisplayJoin(defs, "\n\n"))
   }
 }
 
@@ -176,7 +220,7 @@ impl Term {
   fn display_app<'a>(&'a self, tag: &'a Tag) -> impl fmt::Display + 'a {
     maybe_grow(|| {
       DisplayFn(move |f| match self {
-        Term::App { tag: tag2, fun, arg } if tag2 == tag => {
+        Term::App { tag: tag2, fun, arg, .. } if tag2 == tag => {
           write!(f, "{} {}", fun.display_app(tag), arg)
         }
         _ => write!(f, "{}", self),
@@ -196,10 +240,52 @@ impl Tag {
   }
 }
 
-fn var_as_str(nam: &Option<Name>) -> &str {
+impl Term {
+  /// The statements of a `do` block, with the bind of each one. The last one is never bound.
+  pub(super) fn do_stmts(&self) -> impl Iterator<Item = (Option<&Option<Name>>, &Term)> {
+    let mut term = Some(self);
+    std::iter::from_fn(move || match term? {
+      Term::Do { nam, val, nxt, .. } => {
+        term = Some(nxt.as_ref());
+        Some((Some(nam), val.as_ref()))
+      }
+      last => {
+        term = None;
+        Some((None, last))
+      }
+    })
+  }
+}
+
+/// Displays a statement of a `do` block, with its bind if it has one.
+fn display_do_stmt<'a>(nam: Option<&'a Option<Name>>, val: &'a impl fmt::Display) -> impl fmt::Display + 'a {
+  DisplayFn(move |f| match nam {
+    Some(Some(nam)) => write!(f, "{nam} <- {val}"),
+    Some(None) | None => write!(f, "{val}"),
+  })
+}
+
+/// Displays the guard of a rule or match arm, with a leading space, if it has one.
+fn display_guard(guard: &Option<Term>) -> impl fmt::Display + '_ {
+  DisplayFn(move |f| match guard {
+    Some(guard) => write!(f, " if {guard}"),
+    None => Ok(()),
+  })
+}
+
+pub(super) fn var_as_str(nam: &Option<Name>) -> &str {
   nam.as_ref().map_or("*", Name::deref)
 }
 
+/// Displays a native number according to how it was written: signed, float or unsigned.
+pub(super) fn display_num(val: u64, typ: NumType) -> impl fmt::Display {
+  DisplayFn(move |f| match typ {
+    NumType::Signed if val & NUM_SIGN_BIT != 0 => write!(f, "-{}", val.wrapping_neg() & U60_MASK),
+    NumType::Float => write!(f, "{:?}", f32::from_bits(val as u32)),
+    NumType::Signed | NumType::Unsigned => write!(f, "{val}"),
+  })
+}
+
 /* Pretty printing  */
 
 impl Book {
@@ -229,19 +315,19 @@ impl Term {
   pub fn display_pretty(&self, tab: usize) -> impl fmt::Display + '_ {
     maybe_grow(|| {
       DisplayFn(move |f| match self {
-        Term::Lam { tag, nam, bod } => {
+        Term::Lam { tag, nam, bod, .. } => {
           write!(f, "{}λ{} {}", tag.display_padded(), var_as_str(nam), bod.display_pretty(tab))
         }
 
-        Term::Var { nam } => write!(f, "{nam}"),
+        Term::Var { nam, .. } => write!(f, "{nam}"),
 
-        Term::Chn { tag, nam, bod } => {
+        Term::Chn { tag, nam, bod, .. } => {
           write!(f, "{}λ${} {}", tag, var_as_str(nam), bod.display_pretty(tab))
         }
 
-        Term::Lnk { nam } => write!(f, "${nam}"),
+        Term::Lnk { nam, .. } => write!(f, "${nam}"),
 
-        Term::Let { nam, val, nxt } => {
+        Term::Let { nam, val, nxt, .. } => {
           write!(
             f,
             "let {} = {};\n{:tab$}{}",
@@ -252,7 +338,7 @@ impl Term {
           )
         }
 
-        Term::Use { nam, val, nxt } => {
+        Term::Use { nam, val, nxt, .. } => {
           write!(
             f,
             "use {} = {};\n{:tab$}{}",
@@ -263,7 +349,15 @@ impl Term {
           )
         }
 
-        Term::App { tag, fun, arg } => {
+        Term::Do { .. } => {
+          writeln!(f, "do {{")?;
+          for (nam, val) in self.do_stmts() {
+            writeln!(f, "{:tab$}{};", "", display_do_stmt(nam, &val.display_pretty(tab + 2)), tab = tab + 2)?;
+          }
+          write!(f, "{:tab$}}}", "")
+        }
+
+        Term::App { tag, fun, arg, .. } => {
           write!(
             f,
             "{}({} {})",
@@ -273,7 +367,7 @@ impl Term {
           )
         }
 
-        Term::Ltp { bnd, val, nxt } => {
+        Term::Ltp { bnd, val, nxt, .. } => {
           write!(
             f,
             "let ({}) = {};\n{:tab$}{}",
@@ -284,11 +378,11 @@ impl Term {
           )
         }
 
-        Term::Tup { els } => {
+        Term::Tup { els, .. } => {
           write!(f, "({})", DisplayJoin(|| els.iter().map(|e| e.display_pretty(tab)), " "))
         }
 
-        Term::Dup { tag, bnd, val, nxt } => {
+        Term::Dup { tag, bnd, val, nxt, .. } => {
           write!(
             f,
             "let {}{{{}}} = {};\n{:tab$}{}",
@@ -300,7 +394,7 @@ impl Term {
           )
         }
 
-        Term::Sup { tag, els } => {
+        Term::Sup { tag, els, .. } => {
           write!(
             f,
             "{}{{{}}}",
@@ -309,15 +403,31 @@ impl Term {
           )
         }
 
-        Term::Lst { els } => {
+        Term::Lst { els, .. } => {
           write!(f, "[{}]", DisplayJoin(|| els.iter().map(|e| e.display_pretty(tab)), " "))
         }
 
-        Term::Opx { opr, fst, snd } => {
+        Term::Opx { opr, fst, snd, .. } => {
           write!(f, "({} {} {})", opr, fst.display_pretty(tab), snd.display_pretty(tab))
         }
 
-        Term::Mat { bnd, arg, with, arms } => {
+        Term::Infix { fst, rest, .. } => {
+          let rest = DisplayJoin(
+            || rest.iter().map(|(opr, el)| display!(" {} {}", opr.symbol, el.display_pretty(tab))),
+            "",
+          );
+          write!(f, "({}{})", fst.display_pretty(tab), rest)
+        }
+
+        Term::If { cond, then, els, .. } => {
+          writeln!(f, "if {} {{", cond.display_pretty(tab))?;
+          writeln!(f, "{:tab$}{}", "", then.display_pretty(tab + 2), tab = tab + 2)?;
+          writeln!(f, "{:tab$}}} else {{", "")?;
+          writeln!(f, "{:tab$}{}", "", els.display_pretty(tab + 2), tab = tab + 2)?;
+          write!(f, "{:tab$}}}", "")
+        }
+
+        Term::Mat { bnd, arg, with, arms, loc: _ } => {
           let with: Box<dyn std::fmt::Display> = if with.is_empty() {
             Box::new(DisplayFn(|f| write!(f, "")))
           } else {
@@ -340,7 +450,38 @@ impl Term {
           Ok(())
         }
 
-        Term::Swt { bnd, arg, with, pred: _, arms } => {
+        Term::Cas { args, bnds, with, arms, loc: _ } => {
+          let with: Box<dyn std::fmt::Display> = if with.is_empty() {
+            Box::new(display!(""))
+          } else {
+            Box::new(display!("with {} ", DisplayJoin(|| with, ", ")))
+          };
+          let args = DisplayJoin(
+            || {
+              bnds
+                .iter()
+                .zip(args)
+                .map(|(bnd, arg)| display!("{} = {}", var_as_str(bnd), arg.display_pretty(tab)))
+            },
+            ", ",
+          );
+          writeln!(f, "match {} {}{{", args, with)?;
+          for rule in arms {
+            writeln!(
+              f,
+              "{:tab$}{}{}: {};",
+              "",
+              DisplayJoin(|| rule.pats.iter(), ", "),
+              display_guard(&rule.guard),
+              rule.body.display_pretty(tab + 4),
+              tab = tab + 2
+            )?;
+          }
+          write!(f, "{:tab$}}}", "")?;
+          Ok(())
+        }
+
+        Term::Swt { bnd, arg, with, pred: _, arms, loc: _ } => {
           let with: Box<dyn std::fmt::Display> = if with.is_empty() {
             Box::new(display!(""))
           } else {
@@ -361,12 +502,12 @@ impl Term {
           Ok(())
         }
 
-        Term::Nat { val } => write!(f, "#{val}"),
-        Term::Num { val } => write!(f, "{val}"),
-        Term::Str { val } => write!(f, "{val:?}"),
-        Term::Ref { nam } => write!(f, "{nam}"),
-        Term::Era => write!(f, "*"),
-        Term::Err => write!(f, "<Error>"),
+        Term::Nat { val, .. } => write!(f, "#{val}"),
+        Term::Num { val, typ, .. } => write!(f, "{}", display_num(*val, *typ)),
+        Term::Str { val, .. } => write!(f, "{val:?}"),
+        Term::Ref { nam, .. } => write!(f, "{nam}"),
+        Term::Era { .. } => write!(f, "*"),
+        Term::Err { .. } => write!(f, "<Error>"),
       })
     })
   }
@@ -374,7 +515,7 @@ impl Term {
   fn display_app_pretty<'a>(&'a self, tag: &'a Tag, tab: usize) -> impl fmt::Display + 'a {
     maybe_grow(|| {
       DisplayFn(move |f| match self {
-        Term::App { tag: tag2, fun, arg } if tag2 == tag => {
+        Term::App { tag: tag2, fun, arg, .. } if tag2 == tag => {
           write!(f, "{} {}", fun.display_app_pretty(tag, tab), arg.display_pretty(tab))
         }
         _ => write!(f, "{}", self.display_pretty(tab)),
@@ -0,0 +1,441 @@
+use super::{
+  display::{display_num, var_as_str},
+  parser::{Operator, TermParser, TopLevel},
+  Adt, Assoc, Name, Pattern, Rule, Term,
+};
+use crate::{diagnostics::Loc, maybe_grow};
+use itertools::Itertools;
+use std::{collections::VecDeque, ops::Range};
+
+/// Datatype declarations longer than this are written with one constructor per line.
+const MAX_LINE_WIDTH: usize = 100;
+
+/// Formats the source code of a file in the canonical layout.
+///
+/// Comments between declarations are kept in place, and blank lines between them are collapsed into one.
+/// Comments inside a declaration are written on their own line, before the term they were written next to.
+/// Literals are written the way they were in the source.
+pub fn format_code(code: &str) -> Result<String, String> {
+  let (top_levels, comments) = TermParser::new_top_levels(code)?;
+
+  let mut entries = vec![];
+  let mut comments = comments.into_iter().peekable();
+  let mut top_levels = top_levels.into_iter().peekable();
+  loop {
+    let comment_first = match (top_levels.peek(), comments.peek()) {
+      (Some((_, range)), Some(comment)) => comment.start < range.start,
+      (None, comment) => comment.is_some(),
+      (Some(_), None) => false,
+    };
+    if comment_first {
+      let comment = comments.next().unwrap();
+      let lines = vec![code[comment.clone()].trim_end().to_string()];
+      entries.push(Entry { range: comment, lines, rule_head: None });
+    } else if let Some((top_level, range)) = top_levels.next() {
+      let mut inner = VecDeque::new();
+      while let Some(comment) = comments.next_if(|comment| comment.start < range.end) {
+        inner.push_back(comment);
+      }
+      let (comments, text, rule_head) = Formatter { code, comments: inner }.top_level(&top_level);
+      for comment in comments.lines() {
+        let lines = vec![comment.to_string()];
+        entries.push(Entry { range: range.start .. range.start, lines, rule_head: None });
+      }
+      let lines = text.lines().map(str::to_string).collect::<Vec<_>>();
+      let single_line = lines.len() == 1;
+      entries.push(Entry { range, lines, rule_head: rule_head.filter(|_| single_line) });
+    } else {
+      break;
+    }
+  }
+
+  align_rules(&mut entries, code);
+
+  let mut out = String::new();
+  let mut prev_end = None;
+  for Entry { range, lines, .. } in entries {
+    if let Some(prev_end) = prev_end {
+      let between = &code[prev_end .. range.start];
+      if !between.contains('\n') && lines[0].starts_with("//") {
+        // A comment at the end of the line of the previous declaration.
+        out.pop();
+        out.push(' ');
+      } else if between.matches('\n').count() > 1 {
+        out.push('\n');
+      }
+    }
+    for line in lines {
+      out.push_str(&line);
+      out.push('\n');
+    }
+    prev_end = Some(range.end);
+  }
+  Ok(out)
+}
+
+/// A declaration or a comment, with the source range it was in and the lines it formats to.
+struct Entry {
+  range: Range<usize>,
+  lines: Vec<String>,
+  /// For rules written in a single line, the name of the definition and the length of the rule head.
+  rule_head: Option<(Name, usize)>,
+}
+
+/// Aligns the `=` of consecutive single-line rules of the same definition.
+fn align_rules(entries: &mut [Entry], code: &str) {
+  let mut i = 0;
+  while i < entries.len() {
+    let mut j = i + 1;
+    if let Some((name, _)) = &entries[i].rule_head {
+      while j < entries.len()
+        && entries[j].rule_head.as_ref().is_some_and(|(other, _)| other == name)
+        && !code[entries[j - 1].range.end .. entries[j].range.start].contains("\n\n")
+      {
+        j += 1;
+      }
+      let width = entries[i .. j].iter().map(head_width).max().unwrap();
+      for entry in &mut entries[i .. j] {
+        let (_, head_len) = entry.rule_head.as_ref().unwrap();
+        let (head, body) = entry.lines[0].split_at(*head_len);
+        entry.lines[0] = format!("{head}{:pad$}{body}", "", pad = width - head.chars().count());
+      }
+    }
+    i = j;
+  }
+}
+
+fn head_width(entry: &Entry) -> usize {
+  let (_, head_len) = entry.rule_head.as_ref().unwrap();
+  entry.lines[0][.. *head_len].chars().count()
+}
+
+/// Formats the terms of a declaration, placing each comment inside it on its own line.
+struct Formatter<'a> {
+  code: &'a str,
+  /// The comments of the declaration that haven't been placed yet, in the order they were written.
+  comments: VecDeque<Range<usize>>,
+}
+
+/// A `match` or `switch` arm: where it starts and where its patterns end, its patterns and its body.
+type Arm<'t> = (usize, usize, String, &'t Term);
+
+impl<'a> Formatter<'a> {
+  /// Formats a declaration. For rules, also returns the name of the definition and the length of the head.
+  /// Also returns the comments that go before the declaration, since they aren't next to a term that starts a line,
+  /// like the ones in the head of a rule or in a datatype declaration.
+  fn top_level(&mut self, top_level: &TopLevel) -> (String, String, Option<(Name, usize)>) {
+    let mut comments = String::new();
+    let (text, rule_head) = match top_level {
+      TopLevel::Import(module) => (format!("import {module}"), None),
+      TopLevel::Adt(name, adt) => (format_adt(name, adt), None),
+      TopLevel::Operator(Operator { symbol, prec, assoc, def }) => {
+        let keyword = match assoc {
+          Assoc::Left => "infixl",
+          Assoc::Right => "infixr",
+        };
+        (format!("{keyword} {prec} {symbol} = {def}"), None)
+      }
+      TopLevel::Rule(name, rule) => {
+        let mut head = if rule.pats.is_empty() {
+          name.to_string()
+        } else {
+          format!("({name} {})", rule.pats.iter().map(|pat| self.pattern(pat)).join(" "))
+        };
+        if let Some(guard) = &rule.guard {
+          head.push_str(&format!(" if {}", self.expr(guard, 0)));
+        }
+        comments.push_str(&self.comments_before(head_end(rule), 0));
+        (after(&format!("{head} ="), self.tail(&rule.body, 0)), Some((name.clone(), head.len())))
+      }
+    };
+    comments.push_str(&self.comments_before(usize::MAX, 0));
+    (comments, text, rule_head)
+  }
+
+  /// Takes the comments written before `idx`, each followed by a new line indented by `indent`.
+  fn comments_before(&mut self, idx: usize, indent: usize) -> String {
+    let mut out = String::new();
+    while self.comments.front().is_some_and(|comment| comment.start < idx) {
+      let comment = self.comments.pop_front().unwrap();
+      out.push_str(&format!("{}\n{:indent$}", self.code[comment].trim_end(), ""));
+    }
+    out
+  }
+
+  /// The text of a term or pattern as it was written in the source.
+  fn written(&self, loc: &Loc) -> Option<&'a str> {
+    loc.span().map(|span| &self.code[span.ini .. span.end])
+  }
+
+  /// Formats a pattern, writing its literals the way they were in the source.
+  fn pattern(&self, pat: &Pattern) -> String {
+    match pat {
+      Pattern::Num(..) | Pattern::Str(..) => {
+        self.written(pat.loc()).map_or_else(|| pat.to_string(), str::to_string)
+      }
+      Pattern::Var(..) => pat.to_string(),
+      Pattern::Ctr(nam, pats, _) => {
+        format!("({nam}{})", pats.iter().map(|pat| format!(" {}", self.pattern(pat))).join(""))
+      }
+      Pattern::Tup(pats, _) => format!("({})", pats.iter().map(|pat| self.pattern(pat)).join(", ")),
+      Pattern::Lst(pats, _) => format!("[{}]", pats.iter().map(|pat| self.pattern(pat)).join(", ")),
+      Pattern::Or(alts, _) => format!("({})", alts.iter().map(|pat| self.pattern(pat)).join(" | ")),
+    }
+  }
+
+  /// Formats a term that ends its line, so that a sequence of `let`s in it can be written one per line.
+  fn tail(&mut self, term: &Term, indent: usize) -> String {
+    maybe_grow(|| match term {
+      Term::Let { .. } | Term::Use { .. } | Term::Ltp { .. } | Term::Dup { .. } => {
+        format!("\n{:indent$}{}", "", self.block(term, indent + 2), indent = indent + 2)
+      }
+      Term::Lam { tag, nam, bod, .. } => {
+        after(&format!("{}λ{}", tag.display_padded(), var_as_str(nam)), self.tail(bod, indent))
+      }
+      Term::Chn { tag, nam, bod, .. } => {
+        after(&format!("{}λ${}", tag.display_padded(), var_as_str(nam)), self.tail(bod, indent))
+      }
+      _ => self.expr(term, indent),
+    })
+  }
+
+  /// Formats a sequence of `let`s, one per line, followed by the term they bind in.
+  /// The comments before each line and inside the terms written in it go before the line.
+  fn block(&mut self, term: &Term, indent: usize) -> String {
+    maybe_grow(|| {
+      let comments = self.comments_before(start(term.loc()), indent);
+      match let_bind(term) {
+        Some((bind, val, nxt)) => {
+          let line = format!("{bind} = {}", self.expr(val, indent));
+          let inner = self.comments_before(end(val.loc()), indent);
+          let nxt = self.block(nxt, indent);
+          format!("{comments}{inner}{line}\n{:indent$}{nxt}", "")
+        }
+        None => {
+          let line = self.tail(term, indent);
+          let inner = self.comments_before(end(term.loc()), indent);
+          format!("{comments}{inner}{line}")
+        }
+      }
+    })
+  }
+
+  /// Formats a term in the middle of a line. Only `match`, `switch`, `if` and `do` span multiple lines.
+  fn expr(&mut self, term: &Term, indent: usize) -> String {
+    maybe_grow(|| match term {
+      Term::Lam { tag, nam, bod, .. } => {
+        format!("{}λ{} {}", tag.display_padded(), var_as_str(nam), self.expr(bod, indent))
+      }
+      Term::Chn { tag, nam, bod, .. } => {
+        format!("{}λ${} {}", tag.display_padded(), var_as_str(nam), self.expr(bod, indent))
+      }
+      Term::Var { nam, .. } | Term::Ref { nam, .. } => nam.to_string(),
+      Term::Lnk { nam, .. } => format!("${nam}"),
+      Term::Let { .. } | Term::Use { .. } | Term::Ltp { .. } | Term::Dup { .. } => {
+        let (bind, val, nxt) = let_bind(term).unwrap();
+        format!("{bind} = {}; {}", self.expr(val, indent), self.expr(nxt, indent))
+      }
+      Term::App { tag, .. } => {
+        let mut args = vec![];
+        let mut fun = term;
+        while let Term::App { tag: fun_tag, fun: next, arg, .. } = fun
+          && fun_tag == tag
+        {
+          args.push(arg.as_ref());
+          fun = next;
+        }
+        args.reverse();
+        let mut els = vec![self.expr(fun, indent)];
+        for (i, arg) in args.iter().enumerate() {
+          if i == 0 && args.len() > 1 && matches!(arg, Term::Era { .. }) {
+            // A `*` right after the function would be read as a multiplication.
+            els.push("(*)".to_string());
+          } else {
+            els.push(self.expr(arg, indent));
+          }
+        }
+        format!("{}({})", tag.display_padded(), els.join(" "))
+      }
+      Term::Mat { arg, bnd, with, arms, loc } => {
+        let arms = arms.iter().map(|(nam, _, bod)| {
+          let start = start(bod.loc());
+          (start, start, var_as_str(nam).to_string(), bod)
+        });
+        self.format_match("match", &[(bnd, arg.as_ref())], with, arms.collect(), end(loc), indent)
+      }
+      Term::Cas { args, bnds, with, arms, loc } => {
+        let args = bnds.iter().zip(args.iter()).collect::<Vec<_>>();
+        let arms = arms.iter().map(|rule| {
+          let mut pats = rule.pats.iter().map(|pat| self.pattern(pat)).join(", ");
+          if let Some(guard) = &rule.guard {
+            pats.push_str(&format!(" if {}", self.expr(guard, indent + 2)));
+          }
+          (start(&rule.loc), head_end(rule), pats, &rule.body)
+        });
+        let arms = arms.collect();
+        self.format_match("match", &args, with, arms, end(loc), indent)
+      }
+      Term::If { cond, then, els, loc } => self.format_if(cond, then, els, end(loc), indent),
+      Term::Swt { arg, bnd, with, pred: _, arms, loc } => {
+        let arms = arms.iter().enumerate().map(|(i, bod)| {
+          let pat = if i == arms.len() - 1 { "_".to_string() } else { i.to_string() };
+          let start = start(bod.loc());
+          (start, start, pat, bod)
+        });
+        self.format_match("switch", &[(bnd, arg.as_ref())], with, arms.collect(), end(loc), indent)
+      }
+      Term::Do { loc, .. } => {
+        let end = end(loc);
+        let mut out = "do {\n".to_string();
+        let mut term = term;
+        while let Term::Do { nam, val, nxt, .. } = term {
+          let comments = self.comments_before(start(term.loc()), indent + 2);
+          let stmt = match nam {
+            Some(nam) => format!("{nam} <- {}", self.expr(val, indent + 2)),
+            None => self.expr(val, indent + 2),
+          };
+          let inner = self.comments_before(self::end(val.loc()), indent + 2);
+          out.push_str(&format!("{:indent$}{comments}{inner}{stmt}\n", "", indent = indent + 2));
+          term = nxt;
+        }
+        let comments = self.comments_before(start(term.loc()), indent + 2);
+        let ret = self.expr(term, indent + 2);
+        let inner = self.comments_before(self::end(term.loc()), indent + 2);
+        out.push_str(&format!("{:indent$}{comments}{inner}{ret}\n", "", indent = indent + 2));
+        out.push_str(&self.closing_brace(end, indent));
+        out
+      }
+      Term::Tup { els, .. } => format!("({})", els.iter().map(|el| self.expr(el, indent)).join(", ")),
+      Term::Sup { tag, els, .. } => {
+        format!("{}{{{}}}", tag, els.iter().map(|el| self.expr(el, indent)).join(", "))
+      }
+      Term::Lst { els, .. } => format!("[{}]", els.iter().map(|el| self.expr(el, indent)).join(", ")),
+      Term::Opx { opr, fst, snd, .. } => {
+        format!("({opr} {} {})", self.expr(fst, indent), self.expr(snd, indent))
+      }
+      Term::Infix { fst, rest, .. } => {
+        let fst = self.expr(fst, indent);
+        let rest = rest.iter().map(|(opr, el)| format!(" {} {}", opr.symbol, self.expr(el, indent)));
+        format!("({fst}{})", rest.collect::<String>())
+      }
+      Term::Num { val, typ, loc } => {
+        self.written(loc).map_or_else(|| display_num(*val, *typ).to_string(), str::to_string)
+      }
+      Term::Nat { val, .. } => format!("#{val}"),
+      Term::Str { val, loc } => self.written(loc).map_or_else(|| format!("{val:?}"), str::to_string),
+      Term::Era { .. } => "*".to_string(),
+      Term::Err { .. } => "<Invalid>".to_string(),
+    })
+  }
+
+  /// Formats an `if`, writing the nested ones in its `else` branch as `else if`.
+  fn format_if(&mut self, cond: &Term, then: &Term, els: &Term, end: usize, indent: usize) -> String {
+    let mut out = if let Term::Infix { .. } = cond {
+      // The condition of an `if` doesn't need parentheses around an infix expression.
+      let cond = self.expr(cond, indent);
+      format!("if {} {{\n", &cond[1 .. cond.len() - 1])
+    } else {
+      format!("if {} {{\n", self.expr(cond, indent))
+    };
+    out.push_str(&format!("{:indent$}{}\n", "", self.block(then, indent + 2), indent = indent + 2));
+    out.push_str(&format!("{:indent$}}} else ", ""));
+    if let Term::If { cond, then, els, .. } = els {
+      out.push_str(&self.format_if(cond, then, els, end, indent));
+    } else {
+      out.push_str("{\n");
+      out.push_str(&format!("{:indent$}{}\n", "", self.block(els, indent + 2), indent = indent + 2));
+      out.push_str(&self.closing_brace(end, indent));
+    }
+    out
+  }
+
+  fn format_match(
+    &mut self,
+    keyword: &str,
+    args: &[(&Option<Name>, &Term)],
+    with: &[Name],
+    arms: Vec<Arm>,
+    end: usize,
+    indent: usize,
+  ) -> String {
+    let args = args.iter().map(|(bnd, arg)| {
+      if matches!(arg, Term::Var { nam, .. } if Some(nam) == bnd.as_ref()) {
+        var_as_str(bnd).to_string()
+      } else {
+        format!("{} = {}", var_as_str(bnd), self.expr(arg, indent))
+      }
+    });
+    let mut out = format!("{keyword} {}", args.collect::<Vec<_>>().join(", "));
+    if !with.is_empty() {
+      out.push_str(&format!(" with {}", with.iter().join(", ")));
+    }
+    out.push_str(" {\n");
+    for (start, head_end, pat, bod) in arms {
+      let comments = self.comments_before(start, indent + 2);
+      let head_comments = self.comments_before(head_end, indent + 2);
+      let arm = after(&format!("{pat}:"), self.tail(bod, indent + 2));
+      let inner = self.comments_before(self::end(bod.loc()), indent + 2);
+      out.push_str(&format!("{:indent$}{comments}{head_comments}{inner}{arm}\n", "", indent = indent + 2));
+    }
+    out.push_str(&self.closing_brace(end, indent));
+    out
+  }
+
+  /// Closes a block that ends at `end`, after the comments left inside it.
+  fn closing_brace(&mut self, end: usize, indent: usize) -> String {
+    let comments = self.comments_before(end, indent + 2);
+    if comments.is_empty() {
+      format!("{:indent$}}}", "")
+    } else {
+      format!("{:inner$}{}\n{:indent$}}}", "", comments.trim_end(), "", inner = indent + 2)
+    }
+  }
+}
+
+fn format_adt(name: &Name, adt: &Adt) -> String {
+  let ctrs = adt.ctrs.iter().map(|(ctr, fields)| {
+    if fields.is_empty() { ctr.to_string() } else { format!("({ctr} {})", fields.iter().join(" ")) }
+  });
+  let ctrs = ctrs.collect::<Vec<_>>();
+  let one_line = format!("data {name} = {}", ctrs.join(" | "));
+  if one_line.chars().count() <= MAX_LINE_WIDTH {
+    one_line
+  } else {
+    format!("data {name}\n  = {}", ctrs.join("\n  | "))
+  }
+}
+
+/// Joins the start of a line with a term that ends it, which may continue on the next lines.
+fn after(head: &str, tail: String) -> String {
+  if tail.starts_with('\n') { format!("{head}{tail}") } else { format!("{head} {tail}") }
+}
+
+/// Splits a `let`-like term into its binding, its value and the term it binds in.
+fn let_bind(term: &Term) -> Option<(String, &Term, &Term)> {
+  let (bind, val, nxt) = match term {
+    Term::Let { nam, val, nxt, .. } => (format!("let {}", var_as_str(nam)), val, nxt),
+    Term::Use { nam, val, nxt, .. } => (format!("use {}", var_as_str(nam)), val, nxt),
+    Term::Ltp { bnd, val, nxt, .. } => (format!("let ({})", bnd.iter().map(var_as_str).join(", ")), val, nxt),
+    Term::Dup { tag, bnd, val, nxt, .. } => {
+      (format!("let {}{{{}}}", tag, bnd.iter().map(var_as_str).join(", ")), val, nxt)
+    }
+    _ => return None,
+  };
+  Some((bind, val, nxt))
+}
+
+/// Where a term or pattern starts in the source, or 0 if it wasn't parsed from it.
+fn start(loc: &Loc) -> usize {
+  loc.span().map_or(0, |span| span.ini)
+}
+
+/// Where a term or pattern ends in the source, or 0 if it wasn't parsed from it.
+fn end(loc: &Loc) -> usize {
+  loc.span().map_or(0, |span| span.end)
+}
+
+/// Where the patterns and the guard of a rule end in the source.
+fn head_end(rule: &Rule) -> usize {
+  let last = rule.guard.as_ref().map(Term::loc).or_else(|| rule.pats.last().map(Pattern::loc));
+  last.map_or(0, end)
+}
@@ -1,5 +1,10 @@
-use super::{parser::TermParser, Book};
-use std::path::Path;
+use super::{parser::TermParser, Book, Constructors, Name, Pattern, Term};
+use crate::{diagnostics::SourceFile, maybe_grow};
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
 
 /// Reads a file and parses to a definition book.
 pub fn load_file_to_book(path: &Path) -> Result<Book, String> {
@@ -7,6 +12,232 @@ pub fn load_file_to_book(path: &Path) -> Result<Book, String> {
   do_parse_book(&code, path)
 }
 
+/// Parses a file into a definition book, loading all the modules it imports.
 pub fn do_parse_book(code: &str, path: &Path) -> Result<Book, String> {
-  TermParser::new_book(code, Book::builtins(), false).map_err(|e| format!("In {} :\n{}", path.display(), e))
+  let mut book = parse_source(code, path, Book::builtins())?;
+  let imports = std::mem::take(&mut book.imports);
+
+  let root = (canonical_path(path), path.to_path_buf(), Name::default());
+  let mut loader = ModuleLoader { lib_dirs: lib_dirs(), loaded: HashMap::new(), stack: vec![root] };
+  for import in imports {
+    loader.load_import(&mut book, &import)?;
+  }
+
+  Ok(book)
+}
+
+fn parse_source(code: &str, path: &Path, default_book: Book) -> Result<Book, String> {
+  let file = Arc::new(SourceFile { path: path.display().to_string(), code: code.to_string() });
+  TermParser::new_source_book(&file, default_book).map_err(|e| format!("In {} :\n{}", path.display(), e))
+}
+
+/// The environment variable with the library directories where modules are also looked for.
+pub const LIB_PATH_VAR: &str = "HVML_PATH";
+
+/// The library directories listed in [`LIB_PATH_VAR`], in the platform's `PATH` format.
+fn lib_dirs() -> Vec<PathBuf> {
+  std::env::var_os(LIB_PATH_VAR).map(|paths| std::env::split_paths(&paths).collect()).unwrap_or_default()
+}
+
+/// Loads the modules imported by a program into its book.
+///
+/// `import Data.List` loads the file `Data/List.hvm`, relative to the importing file,
+/// or relative to one of the library directories if there's no such file.
+/// Every definition, datatype and constructor of a module is prefixed by its namespace,
+/// so `map` defined in `Data/List.hvm` is referred to as `Data.List.map`.
+/// The namespace is the path relative to the main file, or to the library directory the module was found in.
+struct ModuleLoader {
+  /// The directories of shared modules, searched in order.
+  lib_dirs: Vec<PathBuf>,
+  /// The modules that were already loaded, with their namespace and the names they define.
+  loaded: HashMap<PathBuf, (Name, Vec<Name>)>,
+  /// The modules currently being loaded, used to detect import cycles.
+  /// Stores the canonical path, the path shown in messages and the namespace of each module.
+  stack: Vec<(PathBuf, PathBuf, Name)>,
+}
+
+impl ModuleLoader {
+  /// Loads the module imported by the file at the top of the stack.
+  /// Returns the namespace of the module and the names that it defines.
+  fn load_import(&mut self, book: &mut Book, import: &Name) -> Result<(Name, Vec<Name>), String> {
+    let (_, importer, importer_namespace) = self.stack.last().unwrap();
+    let file_name = format!("{}.hvm", import.replace('.', "/"));
+    let relative_path = importer.parent().unwrap_or(Path::new("")).join(&file_name);
+    let (display_path, namespace) = if relative_path.is_file() {
+      // Relative imports are namespaced relative to the importing module.
+      let namespace = match importer_namespace.rsplit_once('.') {
+        Some((dir, _)) => Name::new(format!("{dir}.{import}")),
+        None => import.clone(),
+      };
+      (relative_path, namespace)
+    } else if let Some(lib_path) = self.lib_dirs.iter().map(|dir| dir.join(&file_name)).find(|p| p.is_file())
+    {
+      (lib_path, import.clone())
+    } else {
+      return Err(format!(
+        "In {} :\nCould not find module '{import}' at '{}'.",
+        importer.display(),
+        relative_path.display()
+      ));
+    };
+    let path = canonical_path(&display_path);
+
+    if let Some(cycle_start) = self.stack.iter().position(|(p, ..)| *p == path) {
+      let cycle = self.stack[cycle_start ..].iter().map(|(_, p, _)| p).chain([&display_path]);
+      let cycle = cycle.map(|p| p.display().to_string()).collect::<Vec<_>>();
+      return Err(format!("Import cycle detected: {}", cycle.join(" -> ")));
+    }
+
+    if let Some(module) = self.loaded.get(&path) {
+      return Ok(module.clone());
+    }
+
+    let code = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let mut module = parse_source(&code, &display_path, Book::default())?;
+
+    // Local names are prefixed by the module namespace.
+    let names =
+      module.defs.keys().chain(module.adts.keys()).chain(module.ctrs.keys()).cloned().collect::<Vec<_>>();
+    let mut renames = HashMap::new();
+    for name in names.iter() {
+      renames.insert(name.clone(), Name::new(format!("{namespace}.{name}")));
+    }
+
+    // Names from other modules are written relative to this module, but namespaced from the main file.
+    self.stack.push((path.clone(), display_path, namespace.clone()));
+    for import in std::mem::take(&mut module.imports) {
+      let (import_namespace, names) = self.load_import(book, &import)?;
+      if import != import_namespace {
+        for name in names {
+          renames
+            .insert(Name::new(format!("{import}.{name}")), Name::new(format!("{import_namespace}.{name}")));
+        }
+      }
+    }
+    self.stack.pop();
+
+    module.rename_module_names(&renames);
+    book.add_module(module, &namespace)?;
+
+    self.loaded.insert(path, (namespace.clone(), names.clone()));
+    Ok((namespace, names))
+  }
+}
+
+fn canonical_path(path: &Path) -> PathBuf {
+  path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl Book {
+  /// Renames the references to definitions and constructors of a module.
+  fn rename_module_names(&mut self, renames: &HashMap<Name, Name>) {
+    for adt in self.adts.values_mut() {
+      adt.ctrs = std::mem::take(&mut adt.ctrs)
+        .into_iter()
+        .map(|(ctr, fields)| (rename(ctr, renames), fields))
+        .collect();
+    }
+    self.adts =
+      std::mem::take(&mut self.adts).into_iter().map(|(nam, adt)| (rename(nam, renames), adt)).collect();
+    self.ctrs = std::mem::take(&mut self.ctrs)
+      .into_iter()
+      .map(|(ctr, adt)| (rename(ctr, renames), rename(adt, renames)))
+      .collect();
+
+    let defs = std::mem::take(&mut self.defs);
+    for (nam, mut def) in defs {
+      let nam = rename(nam, renames);
+      def.name = nam.clone();
+      for rule in def.rules.iter_mut() {
+        for pat in rule.pats.iter_mut() {
+          pat.rename_module_ctrs(&self.ctrs, renames);
+        }
+        // Names bound by the patterns shadow the module names.
+        let mut renames = renames.clone();
+        for bind in rule.pats.iter().flat_map(Pattern::binds).flatten() {
+          renames.remove(bind);
+        }
+        for term in rule.terms_mut() {
+          term.rename_module_names(&self.ctrs, &renames);
+        }
+      }
+      self.defs.insert(nam, def);
+    }
+  }
+
+  /// Adds the definitions and datatypes of an already namespaced module to this book.
+  fn add_module(&mut self, module: Book, namespace: &Name) -> Result<(), String> {
+    for (nam, adt) in module.adts {
+      if self.adts.contains_key(&nam) {
+        return Err(format!("Datatype '{nam}' from module '{namespace}' is already defined."));
+      }
+      for ctr in adt.ctrs.keys() {
+        if self.ctrs.insert(ctr.clone(), nam.clone()).is_some() {
+          return Err(format!("Constructor '{ctr}' from module '{namespace}' is already defined."));
+        }
+      }
+      self.adts.insert(nam, adt);
+    }
+    for (nam, def) in module.defs {
+      if self.defs.contains_key(&nam) {
+        return Err(format!("Definition '{nam}' from module '{namespace}' is already defined."));
+      }
+      self.defs.insert(nam, def);
+    }
+    Ok(())
+  }
+}
+
+impl Term {
+  fn rename_module_names(&mut self, ctrs: &Constructors, renames: &HashMap<Name, Name>) {
+    maybe_grow(|| {
+      match self {
+        Term::Var { nam, .. } => *nam = rename(std::mem::take(nam), renames),
+        Term::Mat { arms, .. } => {
+          for (ctr, _, _) in arms.iter_mut() {
+            *ctr = ctr.take().map(|ctr| rename(ctr, renames));
+          }
+        }
+        Term::Cas { arms, .. } => {
+          for pat in arms.iter_mut().flat_map(|arm| &mut arm.pats) {
+            pat.rename_module_ctrs(ctrs, renames);
+          }
+        }
+        _ => {}
+      }
+
+      for (child, binds) in self.children_mut_with_binds() {
+        let shadowed = binds.flatten().filter(|bind| renames.contains_key(*bind)).collect::<Vec<_>>();
+        if shadowed.is_empty() {
+          child.rename_module_names(ctrs, renames);
+        } else {
+          let mut renames = renames.clone();
+          for bind in shadowed {
+            renames.remove(bind);
+          }
+          child.rename_module_names(ctrs, &renames);
+        }
+      }
+    })
+  }
+}
+
+impl Pattern {
+  fn rename_module_ctrs(&mut self, ctrs: &Constructors, renames: &HashMap<Name, Name>) {
+    match self {
+      Pattern::Ctr(nam, _, _) => *nam = rename(std::mem::take(nam), renames),
+      // A var pattern with the name of a constructor is a constructor without fields.
+      Pattern::Var(Some(nam), _) if renames.get(nam).is_some_and(|new| ctrs.contains_key(new)) => {
+        *nam = rename(std::mem::take(nam), renames)
+      }
+      _ => {}
+    }
+    for child in self.children_mut() {
+      child.rename_module_ctrs(ctrs, renames);
+    }
+  }
+}
+
+fn rename(nam: Name, renames: &HashMap<Name, Name>) -> Name {
+  renames.get(&nam).cloned().unwrap_or(nam)
 }
@@ -1,5 +1,5 @@
 use crate::{
-  diagnostics::{Diagnostics, DiagnosticsConfig},
+  diagnostics::{Diagnostics, DiagnosticsConfig, Loc, Span},
   maybe_grow,
   term::builtins::*,
   ENTRY_POINT,
@@ -12,6 +12,7 @@ use std::{borrow::Cow, collections::HashMap, ops::Deref};
 pub mod builtins;
 pub mod check;
 pub mod display;
+pub mod format;
 pub mod load_book;
 pub mod net_to_term;
 pub mod parser;
@@ -23,6 +24,38 @@ pub use net_to_term::{net_to_term, ReadbackError};
 pub use term_to_net::{book_to_nets, term_to_compat_net};
 
 pub static STRINGS: GlobalPool<String> = GlobalPool::new();
+
+/// Native numbers are 60-bit values.
+pub const U60_MASK: u64 = (1 << 60) - 1;
+/// Signed numbers are stored in two's complement, so negative numbers have the highest bit set.
+pub const NUM_SIGN_BIT: u64 = 1 << 59;
+/// Floats are stored as the bits of an `f32`, marked with this tag.
+pub const NUM_FLOAT_TAG: u64 = 1 << 58;
+
+/// How a native number is displayed: as it was written for literals, and as unsigned for
+/// numbers read back from a net, unless they are read back with the `typed_nums` run option.
+///
+/// The bits of a number don't tell its type, so two numbers with the same bits are equal, whatever their type.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum NumType {
+  #[default]
+  Unsigned,
+  Signed,
+  Float,
+}
+
+impl PartialEq for NumType {
+  fn eq(&self, _: &Self) -> bool {
+    true
+  }
+}
+
+impl Eq for NumType {}
+
+impl std::hash::Hash for NumType {
+  fn hash<H: std::hash::Hasher>(&self, _: &mut H) {}
+}
+
 #[derive(Debug)]
 pub struct Ctx<'book> {
   pub book: &'book mut Book,
@@ -49,6 +82,15 @@ pub struct Book {
 
   /// A custom or default "main" entrypoint.
   pub entrypoint: Option<Name>,
+
+  /// The modules imported by the program, as written in the `import` declarations.
+  pub imports: Vec<Name>,
+
+  /// The builtins implemented by registered host functions, which work like the [`CORE_BUILTINS`],
+  /// with the definitions that their results can use.
+  ///
+  /// [`CORE_BUILTINS`]: crate::builtins::CORE_BUILTINS
+  pub host_builtins: IndexMap<Name, Vec<Name>>,
 }
 
 pub type Adts = IndexMap<Name, Adt>;
@@ -66,80 +108,115 @@ pub struct Definition {
 #[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
 pub struct Rule {
   pub pats: Vec<Pattern>,
+  /// A condition checked after the patterns match. If it's false, the next rules are tried.
+  pub guard: Option<Term>,
   pub body: Term,
+  /// Where the rule was written, if it came from a source file.
+  pub loc: Loc,
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Hash)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub enum Term {
   Lam {
     tag: Tag,
     nam: Option<Name>,
     bod: Box<Term>,
+    loc: Loc,
   },
   Var {
     nam: Name,
+    loc: Loc,
   },
   /// Like a scopeless lambda, where the variable can occur outside the body
   Chn {
     tag: Tag,
     nam: Option<Name>,
     bod: Box<Term>,
+    loc: Loc,
   },
   /// The use of a Channel variable.
   Lnk {
     nam: Name,
+    loc: Loc,
   },
   Let {
     nam: Option<Name>,
     val: Box<Term>,
     nxt: Box<Term>,
+    loc: Loc,
   },
   Use {
     nam: Option<Name>,
     val: Box<Term>,
     nxt: Box<Term>,
+    loc: Loc,
+  },
+  /// A statement of a `do` block, which runs the IO action `val` and binds its result in `nxt`.
+  Do {
+    nam: Option<Name>,
+    val: Box<Term>,
+    nxt: Box<Term>,
+    loc: Loc,
   },
   App {
     tag: Tag,
     fun: Box<Term>,
     arg: Box<Term>,
+    loc: Loc,
   },
   /// "let tup" tuple destructor
   Ltp {
     bnd: Vec<Option<Name>>,
     val: Box<Term>,
     nxt: Box<Term>,
+    loc: Loc,
   },
   Tup {
     els: Vec<Term>,
+    loc: Loc,
   },
   Dup {
     tag: Tag,
     bnd: Vec<Option<Name>>,
     val: Box<Term>,
     nxt: Box<Term>,
+    loc: Loc,
   },
   Sup {
     tag: Tag,
     els: Vec<Term>,
+    loc: Loc,
   },
   Num {
     val: u64,
+    typ: NumType,
+    loc: Loc,
   },
   Nat {
     val: u64,
+    loc: Loc,
   },
   Str {
     val: GlobalString,
+    loc: Loc,
   },
   Lst {
     els: Vec<Term>,
+    loc: Loc,
   },
   /// A numeric operation between built-in numbers.
   Opx {
     opr: Op,
     fst: Box<Term>,
     snd: Box<Term>,
+    loc: Loc,
+  },
+  /// An infix expression as it was written, like `(a + b * c)`, with its operators in order.
+  /// Desugared into operations and calls according to the precedence of the operators.
+  Infix {
+    fst: Box<Term>,
+    rest: Vec<(InfixOper, Term)>,
+    loc: Loc,
   },
   /// Pattern matching on an ADT.
   Mat {
@@ -147,6 +224,16 @@ pub enum Term {
     bnd: Option<Name>,
     with: Vec<Name>,
     arms: Vec<MatchRule>,
+    loc: Loc,
+  },
+  /// Pattern matching on several values or with nested patterns.
+  /// Compiled into `Mat` and `Swt` terms like the rules of a definition.
+  Cas {
+    args: Vec<Term>,
+    bnds: Vec<Option<Name>>,
+    with: Vec<Name>,
+    arms: Vec<Rule>,
+    loc: Loc,
   },
   /// Native pattern matching on numbers
   Swt {
@@ -155,25 +242,39 @@ pub enum Term {
     with: Vec<Name>,
     pred: Option<Name>,
     arms: Vec<Term>,
+    loc: Loc,
+  },
+  /// `if cond { then } else { els }`, desugared into a switch on the condition.
+  If {
+    cond: Box<Term>,
+    then: Box<Term>,
+    els: Box<Term>,
+    loc: Loc,
   },
   Ref {
     nam: Name,
+    loc: Loc,
+  },
+  Era {
+    loc: Loc,
+  },
+  Err {
+    loc: Loc,
   },
-  Era,
-  #[default]
-  Err,
 }
 
 pub type MatchRule = (Option<Name>, Vec<Option<Name>>, Term);
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Pattern {
-  Var(Option<Name>),
-  Ctr(Name, Vec<Pattern>),
-  Num(u64),
-  Tup(Vec<Pattern>),
-  Lst(Vec<Pattern>),
-  Str(GlobalString),
+  Var(Option<Name>, Loc),
+  Ctr(Name, Vec<Pattern>, Loc),
+  Num(u64, Loc),
+  Tup(Vec<Pattern>, Loc),
+  Lst(Vec<Pattern>, Loc),
+  Str(GlobalString, Loc),
+  /// Matches if any of the alternatives matches. All of them must bind the same variables.
+  Or(Vec<Pattern>, Loc),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
@@ -185,11 +286,40 @@ pub enum Tag {
   Static,
 }
 
+/// An operator of an infix expression.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InfixOper {
+  /// The operator as it was written, with its type prefix if it has one, like `i32.+`.
+  pub symbol: String,
+  pub kind: InfixKind,
+  pub prec: u8,
+  pub assoc: Assoc,
+}
+
+/// What an operator of an infix expression turns into.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InfixKind {
+  /// A numeric operation.
+  Num(Op),
+  /// `&&` and `||`, which turn into a switch on their first operand.
+  Bool(IntOp),
+  /// A call to the definition of a declared operator.
+  Def(Name),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Assoc {
+  Left,
+  Right,
+}
+
 /// A user defined datatype
 #[derive(Debug, Clone, Default)]
 pub struct Adt {
   pub ctrs: IndexMap<Name, Vec<Name>>,
   pub builtin: bool,
+  /// Where the datatype was declared, if it was parsed from a file.
+  pub span: Option<Span>,
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
@@ -198,6 +328,10 @@ pub enum AdtEncoding {
 
   #[default]
   TaggedScott,
+
+  /// Constructors are superpositions of their index in the datatype and their fields,
+  /// and are matched with a native numeric switch on the index.
+  NumTagged,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -302,41 +436,82 @@ impl Tag {
 impl Clone for Term {
   fn clone(&self) -> Self {
     maybe_grow(|| match self {
-      Self::Lam { tag, nam, bod } => Self::Lam { tag: tag.clone(), nam: nam.clone(), bod: bod.clone() },
-      Self::Var { nam } => Self::Var { nam: nam.clone() },
-      Self::Chn { tag, nam, bod } => Self::Chn { tag: tag.clone(), nam: nam.clone(), bod: bod.clone() },
-      Self::Lnk { nam } => Self::Lnk { nam: nam.clone() },
-      Self::Let { nam, val, nxt } => Self::Let { nam: nam.clone(), val: val.clone(), nxt: nxt.clone() },
-      Self::Use { nam, val, nxt } => Self::Use { nam: nam.clone(), val: val.clone(), nxt: nxt.clone() },
-      Self::App { tag, fun, arg } => Self::App { tag: tag.clone(), fun: fun.clone(), arg: arg.clone() },
-      Self::Ltp { bnd, val, nxt } => Self::Ltp { bnd: bnd.clone(), val: val.clone(), nxt: nxt.clone() },
-      Self::Tup { els } => Self::Tup { els: els.clone() },
-      Self::Dup { tag, bnd, val, nxt } => {
-        Self::Dup { tag: tag.clone(), bnd: bnd.clone(), val: val.clone(), nxt: nxt.clone() }
-      }
-      Self::Sup { tag, els } => Self::Sup { tag: tag.clone(), els: els.clone() },
-      Self::Num { val } => Self::Num { val: *val },
-      Self::Nat { val } => Self::Nat { val: *val },
-      Self::Str { val } => Self::Str { val: val.clone() },
-      Self::Lst { els } => Self::Lst { els: els.clone() },
-      Self::Opx { opr, fst, snd } => Self::Opx { opr: *opr, fst: fst.clone(), snd: snd.clone() },
-      Self::Mat { arg, bnd, with, arms } => {
-        Self::Mat { arg: arg.clone(), bnd: bnd.clone(), with: with.clone(), arms: arms.clone() }
-      }
-      Self::Swt { arg, bnd, with, pred, arms } => Self::Swt {
+      Self::Lam { tag, nam, bod, loc } => {
+        Self::Lam { tag: tag.clone(), nam: nam.clone(), bod: bod.clone(), loc: loc.clone() }
+      }
+      Self::Var { nam, loc } => Self::Var { nam: nam.clone(), loc: loc.clone() },
+      Self::Chn { tag, nam, bod, loc } => {
+        Self::Chn { tag: tag.clone(), nam: nam.clone(), bod: bod.clone(), loc: loc.clone() }
+      }
+      Self::Lnk { nam, loc } => Self::Lnk { nam: nam.clone(), loc: loc.clone() },
+      Self::Let { nam, val, nxt, loc } => {
+        Self::Let { nam: nam.clone(), val: val.clone(), nxt: nxt.clone(), loc: loc.clone() }
+      }
+      Self::Use { nam, val, nxt, loc } => {
+        Self::Use { nam: nam.clone(), val: val.clone(), nxt: nxt.clone(), loc: loc.clone() }
+      }
+      Self::Do { nam, val, nxt, loc } => {
+        Self::Do { nam: nam.clone(), val: val.clone(), nxt: nxt.clone(), loc: loc.clone() }
+      }
+      Self::App { tag, fun, arg, loc } => {
+        Self::App { tag: tag.clone(), fun: fun.clone(), arg: arg.clone(), loc: loc.clone() }
+      }
+      Self::Ltp { bnd, val, nxt, loc } => {
+        Self::Ltp { bnd: bnd.clone(), val: val.clone(), nxt: nxt.clone(), loc: loc.clone() }
+      }
+      Self::Tup { els, loc } => Self::Tup { els: els.clone(), loc: loc.clone() },
+      Self::Dup { tag, bnd, val, nxt, loc } => {
+        Self::Dup { tag: tag.clone(), bnd: bnd.clone(), val: val.clone(), nxt: nxt.clone(), loc: loc.clone() }
+      }
+      Self::Sup { tag, els, loc } => Self::Sup { tag: tag.clone(), els: els.clone(), loc: loc.clone() },
+      Self::Num { val, typ, loc } => Self::Num { val: *val, typ: *typ, loc: loc.clone() },
+      Self::Nat { val, loc } => Self::Nat { val: *val, loc: loc.clone() },
+      Self::Str { val, loc } => Self::Str { val: val.clone(), loc: loc.clone() },
+      Self::Lst { els, loc } => Self::Lst { els: els.clone(), loc: loc.clone() },
+      Self::Opx { opr, fst, snd, loc } => {
+        Self::Opx { opr: *opr, fst: fst.clone(), snd: snd.clone(), loc: loc.clone() }
+      }
+      Self::Infix { fst, rest, loc } => {
+        Self::Infix { fst: fst.clone(), rest: rest.clone(), loc: loc.clone() }
+      }
+      Self::Mat { arg, bnd, with, arms, loc } => Self::Mat {
+        arg: arg.clone(),
+        bnd: bnd.clone(),
+        with: with.clone(),
+        arms: arms.clone(),
+        loc: loc.clone(),
+      },
+      Self::Cas { args, bnds, with, arms, loc } => Self::Cas {
+        args: args.clone(),
+        bnds: bnds.clone(),
+        with: with.clone(),
+        arms: arms.clone(),
+        loc: loc.clone(),
+      },
+      Self::Swt { arg, bnd, with, pred, arms, loc } => Self::Swt {
         arg: arg.clone(),
         bnd: bnd.clone(),
         with: with.clone(),
         pred: pred.clone(),
         arms: arms.clone(),
+        loc: loc.clone(),
       },
-      Self::Ref { nam } => Self::Ref { nam: nam.clone() },
-      Self::Era => Self::Era,
-      Self::Err => Self::Err,
+      Self::If { cond, then, els, loc } => {
+        Self::If { cond: cond.clone(), then: then.clone(), els: els.clone(), loc: loc.clone() }
+      }
+      Self::Ref { nam, loc } => Self::Ref { nam: nam.clone(), loc: loc.clone() },
+      Self::Era { loc } => Self::Era { loc: loc.clone() },
+      Self::Err { loc } => Self::Err { loc: loc.clone() },
     })
   }
 }
 
+impl Default for Term {
+  fn default() -> Self {
+    Self::Err { loc: Loc::default() }
+  }
+}
+
 impl Drop for Term {
   fn drop(&mut self) {
     loop {
@@ -363,7 +538,7 @@ impl Drop for Term {
       //  / \              / \
       // c   d            d   e
       // ```
-      let tmp = Term::Err;
+      let tmp = Term::default();
       let d = std::mem::replace(b.children_mut().next_back().unwrap(), tmp);
       let b = std::mem::replace(b, d);
       let a = std::mem::replace(self, b);
@@ -376,31 +551,39 @@ impl Drop for Term {
 impl Term {
   /* Common construction patterns */
   pub fn lam(nam: Option<Name>, bod: Term) -> Self {
-    Term::Lam { tag: Tag::Static, nam, bod: Box::new(bod) }
+    Term::Lam { tag: Tag::Static, nam, bod: Box::new(bod), loc: Loc::default() }
   }
 
   pub fn named_lam(nam: Name, bod: Term) -> Self {
-    Term::Lam { tag: Tag::Static, nam: Some(nam), bod: Box::new(bod) }
+    Term::Lam { tag: Tag::Static, nam: Some(nam), bod: Box::new(bod), loc: Loc::default() }
   }
 
   pub fn erased_lam(bod: Term) -> Self {
-    Term::Lam { tag: Tag::Static, nam: None, bod: Box::new(bod) }
+    Term::Lam { tag: Tag::Static, nam: None, bod: Box::new(bod), loc: Loc::default() }
   }
 
   pub fn tagged_lam(tag: Tag, nam: Option<Name>, bod: Term) -> Self {
-    Term::Lam { tag, nam, bod: Box::new(bod) }
+    Term::Lam { tag, nam, bod: Box::new(bod), loc: Loc::default() }
+  }
+
+  pub fn var(nam: Name) -> Self {
+    Term::Var { nam, loc: Loc::default() }
+  }
+
+  pub fn era() -> Self {
+    Term::Era { loc: Loc::default() }
   }
 
   pub fn var_or_era(nam: Option<Name>) -> Self {
-    if let Some(nam) = nam { Term::Var { nam } } else { Term::Era }
+    if let Some(nam) = nam { Term::var(nam) } else { Term::era() }
   }
 
   pub fn app(fun: Term, arg: Term) -> Self {
-    Term::App { tag: Tag::Static, fun: Box::new(fun), arg: Box::new(arg) }
+    Term::App { tag: Tag::Static, fun: Box::new(fun), arg: Box::new(arg), loc: Loc::default() }
   }
 
   pub fn tagged_app(tag: Tag, fun: Term, arg: Term) -> Self {
-    Term::App { tag, fun: Box::new(fun), arg: Box::new(arg) }
+    Term::App { tag, fun: Box::new(fun), arg: Box::new(arg), loc: Loc::default() }
   }
 
   /// Make a call term by folding args around a called function term with applications.
@@ -414,20 +597,27 @@ impl Term {
 
   /// Apply a variable to a term by the var name.
   pub fn arg_call(fun: Term, arg: Name) -> Self {
-    Term::app(fun, Term::Var { nam: arg })
+    Term::app(fun, Term::var(arg))
   }
 
   pub fn r#ref(name: &str) -> Self {
-    Term::Ref { nam: Name::new(name) }
+    Term::Ref { nam: Name::new(name), loc: Loc::default() }
   }
 
   pub fn str(str: &str) -> Self {
-    Term::Str { val: STRINGS.get(str) }
+    Term::Str { val: STRINGS.get(str), loc: Loc::default() }
   }
 
   pub fn switch(arg: Term, bnd: Name, zero: Term, succ: Term) -> Term {
     let pred = Some(Name::new(format!("{bnd}-1")));
-    Term::Swt { arg: Box::new(arg), bnd: Some(bnd), with: vec![], pred, arms: vec![zero, succ] }
+    Term::Swt {
+      arg: Box::new(arg),
+      bnd: Some(bnd),
+      with: vec![],
+      pred,
+      arms: vec![zero, succ],
+      loc: Loc::default(),
+    }
   }
 
   pub fn sub_num(arg: Term, val: u64) -> Term {
@@ -437,11 +627,27 @@ impl Term {
       Term::Opx {
         opr: Op { ty: OpType::U60, op: IntOp::Sub },
         fst: Box::new(arg),
-        snd: Box::new(Term::Num { val }),
+        snd: Box::new(Term::num(val)),
+        loc: Loc::default(),
       }
     }
   }
 
+  /// An unsigned native number.
+  pub fn num(val: u64) -> Term {
+    Term::Num { val, typ: NumType::Unsigned, loc: Loc::default() }
+  }
+
+  /// A signed native number, in two's complement.
+  pub fn signed_num(val: i64) -> Term {
+    Term::Num { val: val as u64 & U60_MASK, typ: NumType::Signed, loc: Loc::default() }
+  }
+
+  /// A floating point native number.
+  pub fn float_num(val: f32) -> Term {
+    Term::Num { val: val.to_bits() as u64 | NUM_FLOAT_TAG, typ: NumType::Float, loc: Loc::default() }
+  }
+
   pub fn add_num(arg: Term, val: u64) -> Term {
     if val == 0 {
       arg
@@ -449,24 +655,100 @@ impl Term {
       Term::Opx {
         opr: Op { ty: OpType::U60, op: IntOp::Add },
         fst: Box::new(arg),
-        snd: Box::new(Term::Num { val }),
+        snd: Box::new(Term::num(val)),
+        loc: Loc::default(),
       }
     }
   }
 
+  /// Where the term was written, if it came from a source file.
+  pub fn loc(&self) -> &Loc {
+    match self {
+      Term::Lam { loc, .. }
+      | Term::Var { loc, .. }
+      | Term::Chn { loc, .. }
+      | Term::Lnk { loc, .. }
+      | Term::Let { loc, .. }
+      | Term::Use { loc, .. }
+      | Term::Do { loc, .. }
+      | Term::App { loc, .. }
+      | Term::Ltp { loc, .. }
+      | Term::Tup { loc, .. }
+      | Term::Dup { loc, .. }
+      | Term::Sup { loc, .. }
+      | Term::Num { loc, .. }
+      | Term::Nat { loc, .. }
+      | Term::Str { loc, .. }
+      | Term::Lst { loc, .. }
+      | Term::Opx { loc, .. }
+      | Term::Infix { loc, .. }
+      | Term::Mat { loc, .. }
+      | Term::Cas { loc, .. }
+      | Term::Swt { loc, .. }
+      | Term::If { loc, .. }
+      | Term::Ref { loc, .. }
+      | Term::Era { loc }
+      | Term::Err { loc } => loc,
+    }
+  }
+
+  pub fn loc_mut(&mut self) -> &mut Loc {
+    match self {
+      Term::Lam { loc, .. }
+      | Term::Var { loc, .. }
+      | Term::Chn { loc, .. }
+      | Term::Lnk { loc, .. }
+      | Term::Let { loc, .. }
+      | Term::Use { loc, .. }
+      | Term::Do { loc, .. }
+      | Term::App { loc, .. }
+      | Term::Ltp { loc, .. }
+      | Term::Tup { loc, .. }
+      | Term::Dup { loc, .. }
+      | Term::Sup { loc, .. }
+      | Term::Num { loc, .. }
+      | Term::Nat { loc, .. }
+      | Term::Str { loc, .. }
+      | Term::Lst { loc, .. }
+      | Term::Opx { loc, .. }
+      | Term::Infix { loc, .. }
+      | Term::Mat { loc, .. }
+      | Term::Cas { loc, .. }
+      | Term::Swt { loc, .. }
+      | Term::If { loc, .. }
+      | Term::Ref { loc, .. }
+      | Term::Era { loc }
+      | Term::Err { loc } => loc,
+    }
+  }
+
+  /// Returns the term with its location replaced by `loc`.
+  pub fn with_loc(mut self, loc: Loc) -> Self {
+    *self.loc_mut() = loc;
+    self
+  }
+
   /* Iterators */
   pub fn children(&self) -> impl DoubleEndedIterator<Item = &Term> + Clone {
-    multi_iterator!(ChildrenIter { Zero, One, Two, Vec, Mat, Swt });
+    multi_iterator!(ChildrenIter { Zero, One, Two, Three, Vec, Infix, Mat, Cas, Swt });
     match self {
-      Term::Mat { arg, bnd: _, with: _, arms } => {
+      Term::Mat { arg, bnd: _, with: _, arms, loc: _ } => {
         ChildrenIter::Mat([arg.as_ref()].into_iter().chain(arms.iter().map(|r| &r.2)))
       }
-      Term::Swt { arg, bnd: _, with: _, pred: _, arms } => {
+      Term::Cas { args, bnds: _, with: _, arms, loc: _ } => {
+        ChildrenIter::Cas(args.iter().chain(arms.iter().flat_map(Rule::terms)))
+      }
+      Term::Swt { arg, bnd: _, with: _, pred: _, arms, loc: _ } => {
         ChildrenIter::Swt([arg.as_ref()].into_iter().chain(arms))
       }
-      Term::Tup { els } | Term::Sup { els, .. } | Term::Lst { els } => ChildrenIter::Vec(els),
+      Term::If { cond, then, els, .. } => ChildrenIter::Three([cond.as_ref(), then.as_ref(), els.as_ref()]),
+      Term::Tup { els, .. } | Term::Sup { els, .. } | Term::Lst { els, .. } => ChildrenIter::Vec(els),
+      Term::Infix { fst, rest, .. } => {
+        ChildrenIter::Infix([fst.as_ref()].into_iter().chain(rest.iter().map(|(_, el)| el)))
+      }
       Term::Let { val: fst, nxt: snd, .. }
       | Term::Use { val: fst, nxt: snd, .. }
+      | Term::Do { val: fst, nxt: snd, .. }
       | Term::App { fun: fst, arg: snd, .. }
       | Term::Ltp { val: fst, nxt: snd, .. }
       | Term::Dup { val: fst, nxt: snd, .. }
@@ -478,23 +760,31 @@ impl Term {
       | Term::Nat { .. }
       | Term::Str { .. }
       | Term::Ref { .. }
-      | Term::Era
-      | Term::Err => ChildrenIter::Zero([]),
+      | Term::Era { .. }
+      | Term::Err { .. } => ChildrenIter::Zero([]),
     }
   }
 
   pub fn children_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut Term> {
-    multi_iterator!(ChildrenIter { Zero, One, Two, Vec, Mat, Swt });
+    multi_iterator!(ChildrenIter { Zero, One, Two, Three, Vec, Infix, Mat, Cas, Swt });
     match self {
-      Term::Mat { arg, bnd: _, with: _, arms: rules } => {
+      Term::Mat { arg, bnd: _, with: _, arms: rules, loc: _ } => {
         ChildrenIter::Mat([arg.as_mut()].into_iter().chain(rules.iter_mut().map(|r| &mut r.2)))
       }
-      Term::Swt { arg, bnd: _, with: _, pred: _, arms } => {
+      Term::Cas { args, bnds: _, with: _, arms, loc: _ } => {
+        ChildrenIter::Cas(args.iter_mut().chain(arms.iter_mut().flat_map(Rule::terms_mut)))
+      }
+      Term::Swt { arg, bnd: _, with: _, pred: _, arms, loc: _ } => {
         ChildrenIter::Swt([arg.as_mut()].into_iter().chain(arms))
       }
-      Term::Tup { els } | Term::Sup { els, .. } | Term::Lst { els } => ChildrenIter::Vec(els),
+      Term::If { cond, then, els, .. } => ChildrenIter::Three([cond.as_mut(), then.as_mut(), els.as_mut()]),
+      Term::Tup { els, .. } | Term::Sup { els, .. } | Term::Lst { els, .. } => ChildrenIter::Vec(els),
+      Term::Infix { fst, rest, .. } => {
+        ChildrenIter::Infix([fst.as_mut()].into_iter().chain(rest.iter_mut().map(|(_, el)| el)))
+      }
       Term::Let { val: fst, nxt: snd, .. }
       | Term::Use { val: fst, nxt: snd, .. }
+      | Term::Do { val: fst, nxt: snd, .. }
       | Term::App { fun: fst, arg: snd, .. }
       | Term::Ltp { val: fst, nxt: snd, .. }
       | Term::Dup { val: fst, nxt: snd, .. }
@@ -506,8 +796,8 @@ impl Term {
       | Term::Nat { .. }
       | Term::Str { .. }
       | Term::Ref { .. }
-      | Term::Era
-      | Term::Err => ChildrenIter::Zero([]),
+      | Term::Era { .. }
+      | Term::Err { .. } => ChildrenIter::Zero([]),
     }
   }
 
@@ -523,15 +813,21 @@ impl Term {
     &self,
   ) -> impl DoubleEndedIterator<Item = (&Term, impl DoubleEndedIterator<Item = &Option<Name>> + Clone)> + Clone
   {
-    multi_iterator!(ChildrenIter { Zero, One, Two, Vec, Mat, Swt });
-    multi_iterator!(BindsIter { Zero, One, Two, Dup, Mat });
+    multi_iterator!(ChildrenIter { Zero, One, Two, Three, Vec, Infix, Mat, Cas, Swt });
+    multi_iterator!(BindsIter { Zero, One, Two, Dup, Mat, Cas });
     match self {
-      Term::Mat { arg, bnd, with: _, arms: rules } => ChildrenIter::Mat(
+      Term::Mat { arg, bnd, with: _, arms: rules, loc: _ } => ChildrenIter::Mat(
         [(arg.as_ref(), BindsIter::Zero([]))]
           .into_iter()
           .chain(rules.iter().map(move |r| (&r.2, BindsIter::Mat([bnd].into_iter().chain(r.1.iter()))))),
       ),
-      Term::Swt { arg, bnd, with: _, pred, arms: rules } => {
+      Term::Cas { args, bnds, with: _, arms, loc: _ } => ChildrenIter::Cas(
+        args.iter().map(|arg| (arg, BindsIter::Zero([]))).chain(arms.iter().flat_map(move |r| {
+          let binds = bnds.iter().chain(r.pats.iter().flat_map(|p| p.binds()));
+          r.terms().map(move |term| (term, BindsIter::Cas(binds.clone())))
+        })),
+      ),
+      Term::Swt { arg, bnd, with: _, pred, arms: rules, loc: _ } => {
         let (succ, nums) = rules.split_last().unwrap();
         ChildrenIter::Swt(
           [(arg.as_ref(), BindsIter::Zero([]))]
@@ -540,13 +836,21 @@ impl Term {
             .chain([(succ, BindsIter::Two([bnd, pred]))]),
         )
       }
-      Term::Tup { els } | Term::Sup { els, .. } | Term::Lst { els } => {
+      Term::If { cond, then, els, .. } => ChildrenIter::Three([
+        (cond.as_ref(), BindsIter::Zero([])),
+        (then.as_ref(), BindsIter::Zero([])),
+        (els.as_ref(), BindsIter::Zero([])),
+      ]),
+      Term::Tup { els, .. } | Term::Sup { els, .. } | Term::Lst { els, .. } => {
         ChildrenIter::Vec(els.iter().map(|el| (el, BindsIter::Zero([]))))
       }
+      Term::Infix { fst, rest, .. } => ChildrenIter::Infix(
+        [fst.as_ref()].into_iter().chain(rest.iter().map(|(_, el)| el)).map(|el| (el, BindsIter::Zero([]))),
+      ),
       Term::Let { nam, val, nxt, .. } => {
         ChildrenIter::Two([(val.as_ref(), BindsIter::Zero([])), (nxt.as_ref(), BindsIter::One([nam]))])
       }
-      Term::Use { nam, val, nxt, .. } => {
+      Term::Use { nam, val, nxt, .. } | Term::Do { nam, val, nxt, .. } => {
         ChildrenIter::Two([(val.as_ref(), BindsIter::Zero([])), (nxt.as_ref(), BindsIter::One([nam]))])
       }
       Term::Ltp { bnd, val, nxt, .. } | Term::Dup { bnd, val, nxt, .. } => {
@@ -563,8 +867,8 @@ impl Term {
       | Term::Nat { .. }
       | Term::Str { .. }
       | Term::Ref { .. }
-      | Term::Era
-      | Term::Err => ChildrenIter::Zero([]),
+      | Term::Era { .. }
+      | Term::Err { .. } => ChildrenIter::Zero([]),
     }
   }
 
@@ -573,16 +877,25 @@ impl Term {
     &mut self,
   ) -> impl DoubleEndedIterator<Item = (&mut Term, impl DoubleEndedIterator<Item = &Option<Name>> + Clone)>
   {
-    multi_iterator!(ChildrenIter { Zero, One, Two, Vec, Mat, Swt });
-    multi_iterator!(BindsIter { Zero, One, Two, Dup, Mat });
+    multi_iterator!(ChildrenIter { Zero, One, Two, Three, Vec, Infix, Mat, Cas, Swt });
+    multi_iterator!(BindsIter { Zero, One, Two, Dup, Mat, Cas });
     match self {
-      Term::Mat { arg, bnd, with: _, arms: rules } => {
+      Term::Mat { arg, bnd, with: _, arms: rules, loc: _ } => {
         let bnd = &*bnd;
         ChildrenIter::Mat([(arg.as_mut(), BindsIter::Zero([]))].into_iter().chain(
           rules.iter_mut().map(move |r| (&mut r.2, BindsIter::Mat([bnd].into_iter().chain(r.1.iter())))),
         ))
       }
-      Term::Swt { arg, bnd, with: _, pred, arms: rules } => {
+      Term::Cas { args, bnds, with: _, arms, loc: _ } => {
+        let bnds = &*bnds;
+        ChildrenIter::Cas(args.iter_mut().map(|arg| (arg, BindsIter::Zero([]))).chain(
+          arms.iter_mut().flat_map(move |r| {
+            let binds = bnds.iter().chain(r.pats.iter().flat_map(|p| p.binds()));
+            r.guard.iter_mut().chain([&mut r.body]).map(move |term| (term, BindsIter::Cas(binds.clone())))
+          }),
+        ))
+      }
+      Term::Swt { arg, bnd, with: _, pred, arms: rules, loc: _ } => {
         let bnd = &*bnd;
         let (succ, nums) = rules.split_last_mut().unwrap();
         ChildrenIter::Swt(
@@ -592,13 +905,24 @@ impl Term {
             .chain([(succ, BindsIter::Two([bnd, &*pred]))]),
         )
       }
-      Term::Tup { els } | Term::Sup { els, .. } | Term::Lst { els } => {
+      Term::If { cond, then, els, .. } => ChildrenIter::Three([
+        (cond.as_mut(), BindsIter::Zero([])),
+        (then.as_mut(), BindsIter::Zero([])),
+        (els.as_mut(), BindsIter::Zero([])),
+      ]),
+      Term::Tup { els, .. } | Term::Sup { els, .. } | Term::Lst { els, .. } => {
         ChildrenIter::Vec(els.iter_mut().map(|el| (el, BindsIter::Zero([]))))
       }
+      Term::Infix { fst, rest, .. } => ChildrenIter::Infix(
+        [fst.as_mut()]
+          .into_iter()
+          .chain(rest.iter_mut().map(|(_, el)| el))
+          .map(|el| (el, BindsIter::Zero([]))),
+      ),
       Term::Let { nam, val, nxt, .. } => {
         ChildrenIter::Two([(val.as_mut(), BindsIter::Zero([])), (nxt.as_mut(), BindsIter::One([&*nam]))])
       }
-      Term::Use { nam, val, nxt } => {
+      Term::Use { nam, val, nxt, .. } | Term::Do { nam, val, nxt, .. } => {
         ChildrenIter::Two([(val.as_mut(), BindsIter::Zero([])), (nxt.as_mut(), BindsIter::One([&*nam]))])
       }
       Term::Ltp { bnd, val, nxt, .. } | Term::Dup { bnd, val, nxt, .. } => {
@@ -615,24 +939,34 @@ impl Term {
       | Term::Nat { .. }
       | Term::Str { .. }
       | Term::Ref { .. }
-      | Term::Era
-      | Term::Err => ChildrenIter::Zero([]),
+      | Term::Era { .. }
+      | Term::Err { .. } => ChildrenIter::Zero([]),
     }
   }
 
   /// Must only be called after fix_matches.
+  ///
+  /// The binds of the patterns of a `match` arm can only be borrowed once,
+  /// so they're returned with its body and the guard is returned without them.
   pub fn children_mut_with_binds_mut(
     &mut self,
   ) -> impl DoubleEndedIterator<Item = (&mut Term, impl DoubleEndedIterator<Item = &mut Option<Name>>)> {
-    multi_iterator!(ChildrenIter { Zero, One, Two, Vec, Mat, Swt });
-    multi_iterator!(BindsIter { Zero, One, Dup, Mat });
+    multi_iterator!(ChildrenIter { Zero, One, Two, Three, Vec, Infix, Mat, Cas, Swt });
+    multi_iterator!(BindsIter { Zero, One, Dup, Mat, Cas });
     match self {
-      Term::Mat { arg, bnd: _, with: _, arms: rules } => ChildrenIter::Mat(
+      Term::Mat { arg, bnd: _, with: _, arms: rules, loc: _ } => ChildrenIter::Mat(
         [(arg.as_mut(), BindsIter::Zero([]))]
           .into_iter()
           .chain(rules.iter_mut().map(|r| (&mut r.2, BindsIter::Mat(r.1.iter_mut())))),
       ),
-      Term::Swt { arg, bnd: _, with: _, pred, arms: rules } => {
+      Term::Cas { args, bnds: _, with: _, arms, loc: _ } => ChildrenIter::Cas(
+        args.iter_mut().map(|arg| (arg, BindsIter::Zero([]))).chain(arms.iter_mut().flat_map(|r| {
+          let guard = r.guard.as_mut().map(|guard| (guard, BindsIter::Zero([])));
+          let body = (&mut r.body, BindsIter::Cas(r.pats.iter_mut().flat_map(|p| p.binds_mut())));
+          guard.into_iter().chain([body])
+        })),
+      ),
+      Term::Swt { arg, bnd: _, with: _, pred, arms: rules, loc: _ } => {
         let (succ, nums) = rules.split_last_mut().unwrap();
         ChildrenIter::Swt(
           [(arg.as_mut(), BindsIter::Zero([]))]
@@ -641,10 +975,21 @@ impl Term {
             .chain([(succ, BindsIter::One([pred]))]),
         )
       }
-      Term::Tup { els } | Term::Sup { els, .. } | Term::Lst { els } => {
+      Term::If { cond, then, els, .. } => ChildrenIter::Three([
+        (cond.as_mut(), BindsIter::Zero([])),
+        (then.as_mut(), BindsIter::Zero([])),
+        (els.as_mut(), BindsIter::Zero([])),
+      ]),
+      Term::Tup { els, .. } | Term::Sup { els, .. } | Term::Lst { els, .. } => {
         ChildrenIter::Vec(els.iter_mut().map(|el| (el, BindsIter::Zero([]))))
       }
-      Term::Use { nam, val, nxt } => {
+      Term::Infix { fst, rest, .. } => ChildrenIter::Infix(
+        [fst.as_mut()]
+          .into_iter()
+          .chain(rest.iter_mut().map(|(_, el)| el))
+          .map(|el| (el, BindsIter::Zero([]))),
+      ),
+      Term::Use { nam, val, nxt, .. } | Term::Do { nam, val, nxt, .. } => {
         ChildrenIter::Two([(val.as_mut(), BindsIter::Zero([])), (nxt.as_mut(), BindsIter::One([nam]))])
       }
       Term::Let { nam, val, nxt, .. } => {
@@ -664,8 +1009,8 @@ impl Term {
       | Term::Nat { .. }
       | Term::Str { .. }
       | Term::Ref { .. }
-      | Term::Era
-      | Term::Err => ChildrenIter::Zero([]),
+      | Term::Era { .. }
+      | Term::Err { .. } => ChildrenIter::Zero([]),
     }
   }
 
@@ -687,7 +1032,7 @@ impl Term {
       }
     });
 
-    if let Term::Var { nam } = self
+    if let Term::Var { nam, .. } = self
       && nam == from
     {
       *self = to.clone();
@@ -704,7 +1049,7 @@ impl Term {
       }
     });
 
-    if let Term::Lnk { nam } = self
+    if let Term::Lnk { nam, .. } = self
       && nam == from
     {
       *self = to.clone();
@@ -716,7 +1061,7 @@ impl Term {
   pub fn free_vars(&self) -> HashMap<Name, u64> {
     fn go(term: &Term, free_vars: &mut HashMap<Name, u64>) {
       maybe_grow(|| {
-        if let Term::Var { nam } = term {
+        if let Term::Var { nam, .. } = term {
           *free_vars.entry(nam.clone()).or_default() += 1;
         }
 
@@ -746,7 +1091,7 @@ impl Term {
           Term::Chn { nam: Some(nam), .. } => {
             decls.insert(nam.clone());
           }
-          Term::Lnk { nam } => {
+          Term::Lnk { nam, .. } => {
             uses.insert(nam.clone());
           }
           _ => {}
@@ -767,7 +1112,7 @@ impl Term {
 impl Pattern {
   pub fn binds(&self) -> impl DoubleEndedIterator<Item = &Option<Name>> + Clone {
     self.iter().filter_map(|pat| match pat {
-      Pattern::Var(nam) => Some(nam),
+      Pattern::Var(nam, _) => Some(nam),
       _ => None,
     })
   }
@@ -778,7 +1123,7 @@ impl Pattern {
     let mut to_visit = vec![self];
     while let Some(pat) = to_visit.pop() {
       match pat {
-        Pattern::Var(nam) => binds.push(nam),
+        Pattern::Var(nam, _) => binds.push(nam),
         _ => to_visit.extend(pat.children_mut().rev()),
       }
     }
@@ -790,16 +1135,20 @@ impl Pattern {
   pub fn children(&self) -> impl DoubleEndedIterator<Item = &Pattern> + Clone {
     multi_iterator!(ChildrenIter { Zero, Vec });
     match self {
-      Pattern::Ctr(_, els) | Pattern::Tup(els) | Pattern::Lst(els) => ChildrenIter::Vec(els.iter()),
-      Pattern::Var(_) | Pattern::Num(_) | Pattern::Str(_) => ChildrenIter::Zero([]),
+      Pattern::Ctr(_, els, _) | Pattern::Tup(els, _) | Pattern::Lst(els, _) | Pattern::Or(els, _) => {
+        ChildrenIter::Vec(els.iter())
+      }
+      Pattern::Var(..) | Pattern::Num(..) | Pattern::Str(..) => ChildrenIter::Zero([]),
     }
   }
 
   pub fn children_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut Pattern> {
     multi_iterator!(ChildrenIter { Zero, Vec });
     match self {
-      Pattern::Ctr(_, els) | Pattern::Tup(els) | Pattern::Lst(els) => ChildrenIter::Vec(els.iter_mut()),
-      Pattern::Var(_) | Pattern::Num(_) | Pattern::Str(_) => ChildrenIter::Zero([]),
+      Pattern::Ctr(_, els, _) | Pattern::Tup(els, _) | Pattern::Lst(els, _) | Pattern::Or(els, _) => {
+        ChildrenIter::Vec(els.iter_mut())
+      }
+      Pattern::Var(..) | Pattern::Num(..) | Pattern::Str(..) => ChildrenIter::Zero([]),
     }
   }
 
@@ -816,18 +1165,53 @@ impl Pattern {
   }
 
   pub fn is_wildcard(&self) -> bool {
-    matches!(self, Pattern::Var(_))
+    matches!(self, Pattern::Var(..))
   }
 
-  pub fn to_term(&self) -> Term {
-    match self {
-      Pattern::Var(nam) => Term::var_or_era(nam.clone()),
-      Pattern::Ctr(ctr, args) => {
-        Term::call(Term::Ref { nam: ctr.clone() }, args.iter().map(|arg| arg.to_term()))
+  /// The term that builds the values matched by the pattern.
+  /// Returns `None` for or-patterns, since they match values built by different terms.
+  pub fn to_term(&self) -> Option<Term> {
+    let term = match self {
+      Pattern::Var(nam, _) => Term::var_or_era(nam.clone()),
+      Pattern::Ctr(ctr, args, _) => {
+        let args = args.iter().map(Pattern::to_term).collect::<Option<Vec<_>>>()?;
+        Term::call(Term::Ref { nam: ctr.clone(), loc: Loc::default() }, args)
+      }
+      Pattern::Num(val, _) => Term::num(*val),
+      Pattern::Tup(els, _) => {
+        Term::Tup { els: els.iter().map(Pattern::to_term).collect::<Option<_>>()?, loc: Loc::default() }
+      }
+      Pattern::Lst(els, _) => {
+        Term::Lst { els: els.iter().map(Pattern::to_term).collect::<Option<_>>()?, loc: Loc::default() }
       }
-      Pattern::Num(val) => Term::Num { val: *val },
-      Pattern::Tup(args) => Term::Tup { els: args.iter().map(|p| p.to_term()).collect() },
-      Pattern::Lst(_) | Pattern::Str(_) => todo!(),
+      Pattern::Str(val, _) => Term::Str { val: val.clone(), loc: Loc::default() },
+      Pattern::Or(..) => return None,
+    };
+    Some(term.with_loc(self.loc().clone()))
+  }
+
+  /// Where the pattern was written, if it came from a source file.
+  pub fn loc(&self) -> &Loc {
+    match self {
+      Pattern::Var(_, loc)
+      | Pattern::Ctr(_, _, loc)
+      | Pattern::Num(_, loc)
+      | Pattern::Tup(_, loc)
+      | Pattern::Lst(_, loc)
+      | Pattern::Str(_, loc)
+      | Pattern::Or(_, loc) => loc,
+    }
+  }
+
+  pub fn loc_mut(&mut self) -> &mut Loc {
+    match self {
+      Pattern::Var(_, loc)
+      | Pattern::Ctr(_, _, loc)
+      | Pattern::Num(_, loc)
+      | Pattern::Tup(_, loc)
+      | Pattern::Lst(_, loc)
+      | Pattern::Str(_, loc)
+      | Pattern::Or(_, loc) => loc,
     }
   }
 }
@@ -836,6 +1220,15 @@ impl Rule {
   pub fn arity(&self) -> usize {
     self.pats.len()
   }
+
+  /// The guard of the rule, if it has one, and its body.
+  pub fn terms(&self) -> impl DoubleEndedIterator<Item = &Term> + Clone {
+    self.guard.iter().chain([&self.body])
+  }
+
+  pub fn terms_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut Term> {
+    self.guard.iter_mut().chain([&mut self.body])
+  }
 }
 
 impl Definition {
@@ -843,6 +1236,11 @@ impl Definition {
     self.rules[0].arity()
   }
 
+  /// The source location of the definition, taken from its first rule.
+  pub fn span(&self) -> Option<Span> {
+    self.rules.first().and_then(|rule| rule.loc.span())
+  }
+
   #[track_caller]
   pub fn assert_no_pattern_matching_rules(&self) {
     assert!(self.rules.len() == 1, "Definition rules should have been removed in earlier pass");
@@ -1,8 +1,8 @@
 use crate::{
-  diagnostics::{DiagnosticOrigin, Diagnostics, Severity},
+  diagnostics::{DiagnosticOrigin, Diagnostics, Loc, Severity},
   maybe_grow,
   net::{INet, NodeId, NodeKind::*, Port, SlotId, ROOT},
-  term::{num_to_name, term_to_net::Labels, Book, Name, Tag, Term},
+  term::{num_to_name, term_to_net::Labels, Book, Name, Tag, Term, U60_MASK},
 };
 use std::collections::{BTreeSet, HashMap, HashSet};
 
@@ -78,7 +78,7 @@ impl Reader<'_> {
     maybe_grow(|| {
       if self.dup_paths.is_none() && !self.seen.insert(next) {
         self.error(ReadbackError::Cyclic);
-        return Term::Var { nam: Name::new("...") };
+        return Term::var(Name::new("..."));
       }
 
       let node = next.node();
@@ -87,7 +87,7 @@ impl Reader<'_> {
         Era => {
           // Only the main port actually exists in an ERA, the aux ports are just an artifact of this representation.
           debug_assert!(next.slot() == 0);
-          Term::Era
+          Term::era()
         }
         // If we're visiting a con node...
         Con { lab } => match next.slot() {
@@ -95,15 +95,20 @@ impl Reader<'_> {
           0 => {
             let nam = self.namegen.decl_name(self.net, Port(node, 1));
             let bod = self.read_term(self.net.enter_port(Port(node, 2)));
-            Term::Lam { tag: self.labels.con.to_tag(*lab), nam, bod: Box::new(bod) }
+            Term::Lam { tag: self.labels.con.to_tag(*lab), nam, bod: Box::new(bod), loc: Loc::default() }
           }
           // If we're visiting a port 1, then it is a variable.
-          1 => Term::Var { nam: self.namegen.var_name(next) },
+          1 => Term::var(self.namegen.var_name(next)),
           // If we're visiting a port 2, then it is an application.
           2 => {
             let fun = self.read_term(self.net.enter_port(Port(node, 0)));
             let arg = self.read_term(self.net.enter_port(Port(node, 1)));
-            Term::App { tag: self.labels.con.to_tag(*lab), fun: Box::new(fun), arg: Box::new(arg) }
+            Term::App {
+              tag: self.labels.con.to_tag(*lab),
+              fun: Box::new(fun),
+              arg: Box::new(arg),
+              loc: Loc::default(),
+            }
           }
           _ => unreachable!(),
         },
@@ -120,7 +125,7 @@ impl Reader<'_> {
             if *sel_kind != (Con { lab: None }) {
               // TODO: Is there any case where we expect a different node type here on readback?
               self.error(ReadbackError::InvalidNumericMatch);
-              Term::switch(arg, self.namegen.unique(), Term::Err, Term::Err)
+              Term::switch(arg, self.namegen.unique(), Term::default(), Term::default())
             } else {
               let zero_term = self.read_term(self.net.enter_port(Port(sel_node, 1)));
               let mut succ_term = self.read_term(self.net.enter_port(Port(sel_node, 2)));
@@ -128,7 +133,7 @@ impl Reader<'_> {
               match &mut succ_term {
                 Term::Lam { nam, bod, .. } => {
                   // Extract non-var args so we can refer to the pred.
-                  let (arg, bind) = if let Term::Var { nam } = &mut arg {
+                  let (arg, bind) = if let Term::Var { nam, .. } = &mut arg {
                     (std::mem::take(nam), None)
                   } else {
                     (self.namegen.unique(), Some(arg))
@@ -139,12 +144,12 @@ impl Reader<'_> {
 
                   // Rename the pred variable to indicate it's arg-1.
                   if let Some(nam) = &nam {
-                    bod.subst(nam, &Term::Var { nam: Name::new(format!("{arg}-1")) });
+                    bod.subst(nam, &Term::var(Name::new(format!("{arg}-1"))));
                   }
 
-                  let swt = Term::switch(Term::Var { nam: arg.clone() }, arg.clone(), zero_term, *bod);
+                  let swt = Term::switch(Term::var(arg.clone()), arg.clone(), zero_term, *bod);
                   if let Some(bind) = bind {
-                    Term::Let { nam: Some(arg), val: Box::new(bind), nxt: Box::new(swt) }
+                    Term::Let { nam: Some(arg), val: Box::new(bind), nxt: Box::new(swt), loc: Loc::default() }
                   } else {
                     swt
                   }
@@ -158,7 +163,7 @@ impl Reader<'_> {
           }
           _ => {
             self.error(ReadbackError::InvalidNumericMatch);
-            Term::Err
+            Term::default()
           }
         },
         Ref { def_name } => {
@@ -170,7 +175,7 @@ impl Reader<'_> {
 
             term
           } else {
-            Term::Ref { nam: def_name.clone() }
+            Term::Ref { nam: def_name.clone(), loc: Loc::default() }
           }
         }
         // If we're visiting a fan node...
@@ -194,7 +199,11 @@ impl Reader<'_> {
             .unwrap_or_else(|| {
               // If no Dup with same label in the path, we can't resolve the Sup, so keep it as a term.
               self.decay_or_get_ports(node).map_or_else(
-                |(fst, snd)| Term::Sup { tag: self.labels.dup.to_tag(Some(*lab)), els: vec![fst, snd] },
+                |(fst, snd)| Term::Sup {
+                  tag: self.labels.dup.to_tag(Some(*lab)),
+                  els: vec![fst, snd],
+                  loc: Loc::default(),
+                },
                 |term| term,
               )
             })
@@ -211,39 +220,39 @@ impl Reader<'_> {
               if self.seen_fans.insert(node) {
                 self.scope.insert(node);
               }
-              Term::Var { nam: self.namegen.var_name(next) }
+              Term::var(self.namegen.var_name(next))
             }
           }
           _ => unreachable!(),
         },
-        Num { val } => Term::Num { val: *val & ((1 << 60) - 1) },
+        Num { val } => Term::num(*val & U60_MASK),
         Op2 { opr } => match next.slot() {
           2 => {
             let fst = self.read_term(self.net.enter_port(Port(node, 0)));
             let snd = self.read_term(self.net.enter_port(Port(node, 1)));
             let (opr, fst, snd) = if is_op_swapped(*opr) { (opr.swap(), snd, fst) } else { (*opr, fst, snd) };
-            Term::Opx { opr, fst: Box::new(fst), snd: Box::new(snd) }
+            Term::Opx { opr, fst: Box::new(fst), snd: Box::new(snd), loc: Loc::default() }
           }
           _ => {
             self.error(ReadbackError::InvalidNumericOp);
-            Term::Err
+            Term::default()
           }
         },
         Rot => {
           self.error(ReadbackError::ReachedRoot);
-          Term::Err
+          Term::default()
         }
         Tup => match next.slot() {
           // If we're visiting a port 0, then it is a Tup.
           0 => self
             .decay_or_get_ports(node)
-            .map_or_else(|(fst, snd)| Term::Tup { els: vec![fst, snd] }, |term| term),
+            .map_or_else(|(fst, snd)| Term::Tup { els: vec![fst, snd], loc: Loc::default() }, |term| term),
           // If we're visiting a port 1 or 2, then it is a variable.
           1 | 2 => {
             if self.seen_fans.insert(node) {
               self.scope.insert(node);
             }
-            Term::Var { nam: self.namegen.var_name(next) }
+            Term::var(self.namegen.var_name(next))
           }
           _ => unreachable!(),
         },
@@ -350,7 +359,7 @@ impl Term {
   fn insert_split(&mut self, split: &mut Split, threshold: usize) -> Option<usize> {
     maybe_grow(|| {
       let mut n = match self {
-        Term::Var { nam } => usize::from(split.fst == *nam || split.snd == *nam),
+        Term::Var { nam, .. } => usize::from(split.fst == *nam || split.snd == *nam),
         _ => 0,
       };
       for child in self.children_mut() {
@@ -361,8 +370,8 @@ impl Term {
         let Split { tag, fst, snd, val } = std::mem::take(split);
         let nxt = Box::new(std::mem::take(self));
         *self = match tag {
-          None => Term::Ltp { bnd: vec![fst, snd], val: Box::new(val), nxt },
-          Some(tag) => Term::Dup { tag, bnd: vec![fst, snd], val: Box::new(val), nxt },
+          None => Term::Ltp { bnd: vec![fst, snd], val: Box::new(val), nxt, loc: Loc::default() },
+          Some(tag) => Term::Dup { tag, bnd: vec![fst, snd], val: Box::new(val), nxt, loc: Loc::default() },
         };
         None
       } else {
@@ -376,13 +385,13 @@ impl Term {
       if let Some(nam) = nam {
         let name = Name::new(num_to_name(*id_counter));
         *id_counter += 1;
-        bod.subst(nam, &Term::Var { nam: name.clone() });
+        bod.subst(nam, &Term::var(name.clone()));
         *nam = name;
       }
     }
 
     maybe_grow(|| match self {
-      Term::Ref { nam: def_name } => {
+      Term::Ref { nam: def_name, .. } => {
         if def_name.is_generated() {
           let def = book.defs.get(def_name).unwrap();
           let mut term = def.rule().body.clone();
@@ -482,7 +491,7 @@ impl std::fmt::Display for ReadbackError {
 impl Term {
   pub fn collect_unscoped(&self, unscoped: &mut HashSet<Name>, scope: &mut Vec<Name>) {
     match self {
-      Term::Var { nam } if !scope.contains(nam) => _ = unscoped.insert(nam.clone()),
+      Term::Var { nam, .. } if !scope.contains(nam) => _ = unscoped.insert(nam.clone()),
       _ => {}
     }
     for (child, binds) in self.children_with_binds() {
@@ -499,10 +508,16 @@ impl Term {
 
   pub fn apply_unscoped(&mut self, unscoped: &HashSet<Name>) {
     match self {
-      Term::Var { nam } if unscoped.contains(nam) => *self = Term::Lnk { nam: std::mem::take(nam) },
-      Term::Lam { tag, nam: Some(nam), bod } if unscoped.contains(nam) => {
-        *self =
-          Term::Chn { tag: std::mem::take(tag), nam: Some(std::mem::take(nam)), bod: std::mem::take(bod) };
+      Term::Var { nam, loc } if unscoped.contains(nam) => {
+        *self = Term::Lnk { nam: std::mem::take(nam), loc: std::mem::take(loc) }
+      }
+      Term::Lam { tag, nam: Some(nam), bod, loc } if unscoped.contains(nam) => {
+        *self = Term::Chn {
+          tag: std::mem::take(tag),
+          nam: Some(std::mem::take(nam)),
+          bod: std::mem::take(bod),
+          loc: std::mem::take(loc),
+        };
       }
       _ => {}
     }
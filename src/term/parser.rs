@@ -1,24 +1,33 @@
 use crate::{
+  diagnostics::{Loc, SourceFile, Span},
   maybe_grow,
   term::{
-    display::DisplayFn, Adt, Book, Definition, IntOp, MatchRule, Name, Op, OpType, Pattern, Rule, Tag, Term,
-    STRINGS,
+    display::DisplayFn, Adt, Assoc, Book, Definition, InfixKind, InfixOper, IntOp, Name, Op, OpType, Pattern,
+    Rule, Tag, Term, STRINGS,
   },
 };
 use highlight_error::highlight_error;
+use std::{collections::HashMap, ops::Range, sync::Arc};
 use TSPL::Parser;
 
 // hvml grammar description:
-// <Book>       ::= (<Data> | <Rule>)*
+// <Book>       ::= (<Data> | <Import> | <Infix> | <Rule>)*
+// <Import>     ::= "import" <Name>
+// <Infix>      ::= ("infixl" | "infixr") <Precedence> <Symbol> "=" <Name>
 // <Data>       ::= "data" <Name> "=" ( <Name> | "(" <Name> (<Name>)* ")" )+
-// <Rule>       ::= ("(" <Name> <Pattern>* ")" | <Name> <Pattern>*) "=" <Term>
-// <Pattern>    ::= "(" <Name> <Pattern>* ")" | <NameEra> | <Number> | "(" <Pattern> ("," <Pattern>)+ ")"
+// <Rule>       ::= ("(" <Name> <Pattern>* ")" | <Name> <Pattern>*) <Guard>? "=" <Term>
+// <Guard>      ::= "if" <Term>
+// <Pattern>    ::=
+//   "(" <Name> <Pattern>* ")" | <NameEra> | <Number> | "(" <Pattern> ("," <Pattern>)+ ")" |
+//   "(" <Alternative> ("|" <Alternative>)+ ")"
+// <Alternative>::= <Name> <Pattern>* | <Pattern>
 // <Term>       ::=
-//   <Number> | <NumOp> | <Tup> | <App> | <Group> | <Nat> | <Lam> | <UnscopedLam> |
-//   <Use> | <Dup> | <LetTup> | <Let> | <Match> | <Switch> | <Era> | <UnscopedVar> | <Var>
+//   <Number> | <NumOp> | <InfixOp> | <Tup> | <App> | <Group> | <Nat> | <Lam> | <UnscopedLam> |
+//   <Use> | <Dup> | <LetTup> | <Let> | <Match> | <Switch> | <If> | <Do> | <Era> | <UnscopedVar> | <Var>
 // <Lam>        ::= <Tag>? ("λ"|"@") <NameEra> <Term>
 // <UnscopedLam>::= <Tag>? ("λ"|"@") "$" <Name> <Term>
-// <NumOp>      ::= "(" <Operator> <Term> <Term> ")"
+// <NumOp>      ::= "(" <OpType>? <Operator> <Term> <Term> ")"
+// <InfixOp>    ::= "(" <Term> (<OpType>? <Symbol> <Term>)+ ")"
 // <Tup>        ::= "(" <Term> ("," <Term>)+ ")"
 // <App>        ::= <Tag>? "(" <Term> (<Term>)+ ")"
 // <Group>      ::= "(" <Term> ")"
@@ -29,31 +38,210 @@ use TSPL::Parser;
 // <List>       ::= "[" (<Term> ","?)* "]"
 // <String>     ::= "\"" (escape sequence | [^"])* "\""
 // <Char>       ::= "'" (escape sequence | [^']) "'"
-// <Match>      ::= "match" <Name> ("=" <Term>)? ("with" <Var> (","? <Var>)*)? "{" <MatchArm>+ "}"
-// <MatchArm>   ::= "|"? <Pattern> ":" <Term> ";"?
+// <Match>      ::= "match" <MatchArg> ("," <MatchArg>)* ("with" <Var> (","? <Var>)*)? "{" <MatchArm>+ "}"
+// <MatchArg>   ::= <Name> ("=" <Term>)?
+// <MatchArm>   ::= "|"? <Pattern> ("," <Pattern>)* <Guard>? ":" <Term> ";"?
 // <Switch>     ::= "switch" <Name> ("=" <Term>)? ("with" <Var> (","? <Var>)*)? "{" <SwitchArm>+ "}"
 // <SwitchArm>  ::= "|"? (<Num>|"_") ":" <Term> ";"?
+// <If>         ::= "if" <Term> (<OpType>? <Symbol> <Term>)* "{" <Term> "}" "else" (<If> | "{" <Term> "}")
+// <Do>         ::= "do" "{" (<DoBind> ";"?)* <Term> ";"? "}"
+// <DoBind>     ::= (<NameEra> "<-")? <Term>
 // <Var>        ::= <Name>
 // <UnscopedVar>::= "$" <Name>
 // <NameEra>    ::= <Name> | "*"
-// <Era>        ::= "*"
+// <Era>        ::= "*" | "(" "*" ")"
 // <Tag>        ::= "#" <Name>
 // <Name>       ::= [_\-./a-zA-Z0-9]+
-// <Number>     ::= ([0-9]+ | "0x"[0-9a-fA-F]+ | "0b"[01]+)
+// <Number>     ::= "-"? ([0-9]+ | "0x"[0-9a-fA-F]+ | "0b"[01]+) | "-"? [0-9]+ "." [0-9]+
+// <OpType>     ::= ( "u8" | "u16" | "u32" | "u60" | "i8" | "i16" | "i32" | "f32" ) "."
 // <Operator>   ::= ( "+" | "-" | "*" | "/" | "%" | "==" | "!=" | "<<" | ">>" | "<=" | ">=" | "<" | ">" | "^" )
+// <Symbol>     ::= [+\-*/%&|<>^=!~]+
+// <Precedence> ::= [0-9]+
 
-TSPL::new_parser!(TermParser);
+/// The prefixes of typed numeric operators, like `i32.+`.
+/// Operators without a prefix are `u60`.
+const OP_TYPES: [(&str, OpType); 8] = [
+  ("u8.", OpType::U8),
+  ("u16.", OpType::U16),
+  ("u32.", OpType::U32),
+  ("u60.", OpType::U60),
+  ("i8.", OpType::I8),
+  ("i16.", OpType::I16),
+  ("i32.", OpType::I32),
+  ("f32.", OpType::F32),
+];
+
+/// The characters that infix operators are made of.
+const OPER_CHARS: &str = "+-*/%&|<>^=!~";
+
+/// The highest precedence that a declared operator can have.
+const MAX_PRECEDENCE: u64 = 10;
+
+/// The builtin numeric infix operators with their precedences, from the loosest to the tightest binding.
+/// They are all left-associative.
+const INFIX_OPERS: [(&str, u8, IntOp); 16] = [
+  ("==", 3, IntOp::Eq),
+  ("!=", 3, IntOp::Ne),
+  ("<=", 3, IntOp::Le),
+  (">=", 3, IntOp::Ge),
+  ("<", 3, IntOp::Lt),
+  (">", 3, IntOp::Gt),
+  ("|", 4, IntOp::Or),
+  ("^", 5, IntOp::Xor),
+  ("&", 6, IntOp::And),
+  ("<<", 7, IntOp::Shl),
+  (">>", 7, IntOp::Shr),
+  ("+", 8, IntOp::Add),
+  ("-", 8, IntOp::Sub),
+  ("*", 9, IntOp::Mul),
+  ("/", 9, IntOp::Div),
+  ("%", 9, IntOp::Rem),
+];
+
+/// The builtin boolean infix operators, which bind looser than all the numeric ones.
+/// They only evaluate their second operand when the first one doesn't decide the result.
+const BOOL_OPERS: [(&str, u8, IntOp); 2] = [("||", 1, IntOp::Or), ("&&", 2, IntOp::And)];
+
+pub struct TermParser<'i> {
+  input: &'i str,
+  index: usize,
+  /// The byte ranges of the comments skipped so far, if they are being kept.
+  comments: Option<Vec<Range<usize>>>,
+  /// The operators declared so far, by their symbol.
+  operators: HashMap<String, Operator>,
+  /// The file being parsed, if any, to record where each rule and term was written.
+  file: Option<Arc<SourceFile>>,
+  /// The declared names and the names in patterns parsed so far, if they are being kept.
+  names: Option<Vec<ParsedName>>,
+}
+
+impl<'i> Parser<'i> for TermParser<'i> {
+  fn input(&mut self) -> &'i str {
+    self.input
+  }
+
+  fn index(&mut self) -> &mut usize {
+    &mut self.index
+  }
+}
+
+/// A name written in a declaration or pattern, with its byte range in the source.
+#[derive(Debug, Clone)]
+pub struct ParsedName {
+  pub name: Name,
+  pub role: NameRole,
+  pub range: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameRole {
+  /// The name of a definition, at the start of one of its rules.
+  Def,
+  /// The name of a datatype, in its declaration.
+  Adt,
+  /// The name of a constructor, in the declaration of its datatype.
+  Ctr,
+  /// A name in a pattern, which is either a variable or a constructor.
+  Pattern,
+}
+
+/// A top-level declaration of a file, as written in the source.
+#[derive(Debug, Clone)]
+pub enum TopLevel {
+  Import(Name),
+  Adt(Name, Adt),
+  Operator(Operator),
+  Rule(Name, Rule),
+}
+
+/// A user-declared infix operator, like `infixr 5 ++ = List.concat`.
+/// `(a ++ b)` is a call to the definition, `(List.concat a b)`.
+#[derive(Debug, Clone)]
+pub struct Operator {
+  pub symbol: String,
+  pub prec: u8,
+  pub assoc: Assoc,
+  pub def: Name,
+}
 
 impl<'a> TermParser<'a> {
+  pub fn new(input: &'a str) -> Self {
+    Self { input, index: 0, comments: None, operators: HashMap::new(), file: None, names: None }
+  }
+
   // TODO: Since TSPL doesn't expose `new` we need something that creates the parser.
   pub fn new_book(input: &'a str, default_book: Book, builtin: bool) -> Result<Book, String> {
     Self::new(input).parse_book(default_book, builtin)
   }
 
+  /// Parses a book from a source file, recording the span of each rule and of the terms used in diagnostics.
+  pub fn new_source_book(file: &'a Arc<SourceFile>, default_book: Book) -> Result<Book, String> {
+    Self::new_source(file).parse_book(default_book, false)
+  }
+
+  /// Like [`Self::new_source_book`], but also returns the declared names and the names in patterns.
+  /// On errors, returns the index where the parser stopped.
+  pub fn new_source_book_with_names(
+    file: &'a Arc<SourceFile>,
+    default_book: Book,
+  ) -> Result<(Book, Vec<ParsedName>), (String, usize)> {
+    let mut parser = Self { names: Some(vec![]), ..Self::new_source(file) };
+    match parser.parse_book(default_book, false) {
+      Ok(book) => Ok((book, parser.names.unwrap_or_default())),
+      Err(e) => Err((e, *parser.index())),
+    }
+  }
+
+  fn new_source(file: &'a Arc<SourceFile>) -> Self {
+    Self { file: Some(file.clone()), ..Self::new(&file.code) }
+  }
+
+  /// Parses the top-level declarations of a file in the order they were written, with their byte ranges.
+  /// Also returns the byte ranges of all the comments in the file, which are otherwise skipped.
+  ///
+  /// The terms and patterns are spanned over `input`, so that the way each one was written can be recovered.
+  #[allow(clippy::type_complexity)]
+  pub fn new_top_levels(
+    input: &'a str,
+  ) -> Result<(Vec<(TopLevel, Range<usize>)>, Vec<Range<usize>>), String> {
+    let file = Arc::new(SourceFile { path: String::new(), code: input.to_string() });
+    let mut parser = Self { comments: Some(vec![]), file: Some(file), ..Self::new(input) };
+    let mut top_levels = vec![];
+    parser.skip_trivia();
+    while !parser.is_eof() {
+      let ini_idx = *parser.index();
+      let top_level = parser.parse_top_level(false)?;
+      // Looking ahead for more of the declaration may have skipped the trivia after it.
+      let mut end_idx = *parser.index();
+      loop {
+        end_idx = ini_idx + input[ini_idx .. end_idx].trim_end().len();
+        let comments = parser.comments.as_deref().unwrap_or_default();
+        match comments.iter().rfind(|comment| comment.start < end_idx && end_idx <= comment.end) {
+          Some(comment) => end_idx = comment.start,
+          None => break,
+        }
+      }
+      top_levels.push((top_level, ini_idx .. end_idx));
+      parser.skip_trivia();
+    }
+    Ok((top_levels, parser.comments.unwrap_or_default()))
+  }
+
   pub fn new_term(input: &'a str) -> Result<Term, String> {
     Self::new(input).parse_term()
   }
 
+  /// Parses a term that must span the whole input.
+  pub fn new_full_term(input: &'a str) -> Result<Term, String> {
+    let mut parser = Self::new(input);
+    let term = parser.parse_term()?;
+    parser.skip_trivia();
+    if !parser.is_eof() {
+      return parser.expected("end of input");
+    }
+    Ok(term)
+  }
+
   /* AST parsing functions */
 
   fn parse_book(&mut self, default_book: Book, builtin: bool) -> Result<Book, String> {
@@ -61,15 +249,21 @@ impl<'a> TermParser<'a> {
     self.skip_trivia();
     while !self.is_eof() {
       let ini_idx = *self.index();
-      if self.skip_starts_with("data") {
-        // adt declaration
-        let (nam, adt) = self.parse_datatype(builtin)?;
-        let end_idx = *self.index();
-        book.add_adt(nam, adt).map_err(|e| add_ctx(&e, ini_idx, end_idx, self.input()))?;
-      } else {
-        // function declaration rule
-        let (name, rule) = self.parse_rule()?;
-        book.add_rule(name, rule, builtin);
+      let top_level = self.parse_top_level(builtin)?;
+      let end_idx = *self.index();
+      let span = self.span_from(ini_idx);
+      match top_level {
+        TopLevel::Import(module) => book.imports.push(module),
+        // Operators are only used while parsing the rest of the file.
+        TopLevel::Operator(_) => {}
+        TopLevel::Adt(nam, mut adt) => {
+          adt.span = span;
+          book.add_adt(nam, adt).map_err(|e| add_ctx(&e, ini_idx, end_idx, self.input()))?;
+        }
+        TopLevel::Rule(name, mut rule) => {
+          rule.loc = span.into();
+          book.add_rule(name, rule, builtin);
+        }
       }
       self.skip_trivia();
     }
@@ -77,61 +271,85 @@ impl<'a> TermParser<'a> {
     Ok(book)
   }
 
+  fn parse_top_level(&mut self, builtin: bool) -> Result<TopLevel, String> {
+    if self.skip_starts_with("data") {
+      // adt declaration
+      let (nam, adt) = self.parse_datatype(builtin)?;
+      Ok(TopLevel::Adt(nam, adt))
+    } else if self.starts_with_keyword("import") {
+      // module import
+      self.consume("import")?;
+      let module = self.labelled(|p| p.parse_hvml_name(), "module name")?;
+      Ok(TopLevel::Import(module))
+    } else if self.starts_with_keyword("infixl") || self.starts_with_keyword("infixr") {
+      // operator declaration
+      let oper = self.parse_operator()?;
+      self.operators.insert(oper.symbol.clone(), oper.clone());
+      Ok(TopLevel::Operator(oper))
+    } else {
+      // function declaration rule
+      let (name, rule) = self.parse_rule()?;
+      Ok(TopLevel::Rule(name, rule))
+    }
+  }
+
   fn parse_datatype(&mut self, builtin: bool) -> Result<(Name, Adt), String> {
     // data name = ctr (| ctr)*
     self.consume("data")?;
-    let name = self.labelled(|p| p.parse_hvml_name(), "datatype name")?;
+    let name = self.parse_recorded_name(NameRole::Adt, "datatype name")?;
     self.consume("=")?;
     let mut ctrs = vec![self.parse_datatype_ctr()?];
     while self.try_consume("|") {
       ctrs.push(self.parse_datatype_ctr()?);
     }
     let ctrs = ctrs.into_iter().collect();
-    let adt = Adt { ctrs, builtin };
+    let adt = Adt { ctrs, builtin, span: None };
     Ok((name, adt))
   }
 
   fn parse_datatype_ctr(&mut self) -> Result<(Name, Vec<Name>), String> {
-    if self.skip_starts_with("(") {
+    if self.try_consume("(") {
       // (name field*)
+      let name = self.parse_recorded_name(NameRole::Ctr, "datatype constructor name")?;
       let field_parser = |p: &mut Self| p.labelled(|p| p.parse_hvml_name(), "datatype constructor field");
-      let mut els = self.list_like(field_parser, "(", ")", "", false, 1)?;
-      let fields = els.split_off(1);
-      let name = els.into_iter().next().unwrap();
+      let fields = self.list_like(field_parser, "", ")", "", false, 0)?;
       Ok((name, fields))
     } else {
       // name
-      let name = self.labelled(|p| p.parse_hvml_name(), "datatype constructor name")?;
+      let name = self.parse_recorded_name(NameRole::Ctr, "datatype constructor name")?;
       Ok((name, vec![]))
     }
   }
 
   fn parse_rule(&mut self) -> Result<(Name, Rule), String> {
     let (name, pats) = if self.try_consume("(") {
-      let name = self.labelled(|p| p.parse_hvml_name(), "function name")?;
+      let name = self.parse_recorded_name(NameRole::Def, "function name")?;
       let pats = self.list_like(|p| p.parse_rule_pattern(), "", ")", "", false, 0)?;
       (name, pats)
     } else {
-      let name = self.labelled(|p| p.parse_hvml_name(), "top-level definition")?;
+      let name = self.parse_recorded_name(NameRole::Def, "top-level definition")?;
       let mut pats = vec![];
-      while !self.skip_starts_with("=") {
+      while !self.skip_starts_with("=") && !self.starts_with_keyword("if") {
         pats.push(self.parse_rule_pattern()?);
       }
       (name, pats)
     };
 
+    let guard = self.parse_guard()?;
+
     self.consume("=")?;
 
     let body = self.parse_term()?;
 
-    let rule = Rule { pats, body };
+    let rule = Rule { pats, guard, body, loc: Loc::default() };
     Ok((name, rule))
   }
 
   fn parse_rule_pattern(&mut self) -> Result<Pattern, String> {
     maybe_grow(|| {
       let Some(head) = self.skip_peek_one() else { return self.expected("pattern-matching pattern") };
-      let pat = match head {
+      let ini_idx = *self.index();
+      let mut pat = match head {
         // Ctr or Tup
         '(' => {
           self.consume("(")?;
@@ -143,42 +361,81 @@ impl<'a> TermParser<'a> {
             // Tup
             let mut els = self.list_like(|p| p.parse_rule_pattern(), "", ")", ",", true, 1)?;
             els.insert(0, head);
-            Pattern::Tup(els)
+            Pattern::Tup(els, Loc::default())
           } else {
-            // Ctr
-            let Pattern::Var(Some(name)) = head else {
-              return self.expected_spanned("constructor name", head_ini_idx, head_end_idx);
-            };
-            let els = self.list_like(|p| p.parse_rule_pattern(), "", ")", "", false, 0)?;
-            Pattern::Ctr(name, els)
+            // Ctr or Or
+            let mut alts = vec![];
+            let (mut head, mut head_ini_idx, mut head_end_idx) = (head, head_ini_idx, head_end_idx);
+            loop {
+              let mut els = vec![];
+              while !self.skip_starts_with(")") && !self.skip_starts_with("|") {
+                els.push(self.parse_rule_pattern()?);
+              }
+              alts.push((head, els, head_ini_idx, head_end_idx));
+              if !self.try_consume("|") {
+                break;
+              }
+              head_ini_idx = *self.index();
+              head = self.parse_rule_pattern()?;
+              head_end_idx = *self.index();
+            }
+            self.consume(")")?;
+
+            if alts.len() == 1 {
+              let (head, els, head_ini_idx, head_end_idx) = alts.pop().unwrap();
+              let Pattern::Var(Some(name), _) = head else {
+                return self.expected_spanned("constructor name", head_ini_idx, head_end_idx);
+              };
+              Pattern::Ctr(name, els, Loc::default())
+            } else {
+              let mut pats = vec![];
+              for (head, els, head_ini_idx, head_end_idx) in alts {
+                let pat = match head {
+                  // A single pattern, like `*` or `Nil` in `(* | Nil)`.
+                  head if els.is_empty() => head,
+                  Pattern::Var(Some(name), _) => {
+                    let end_idx = els.last().and_then(|el| el.loc().span()).map_or(head_end_idx, |el| el.end);
+                    Pattern::Ctr(name, els, self.span_between(head_ini_idx, end_idx).into())
+                  }
+                  _ => return self.expected_spanned("constructor name", head_ini_idx, head_end_idx),
+                };
+                pats.push(pat);
+              }
+              Pattern::Or(pats, Loc::default())
+            }
           }
         }
         // List
         '[' => {
           let els = self.list_like(|p| p.parse_rule_pattern(), "[", "]", ",", false, 0)?;
-          Pattern::Lst(els)
+          Pattern::Lst(els, Loc::default())
         }
         // String
         '\"' => {
           let str = self.parse_quoted_string()?;
-          Pattern::Str(STRINGS.get(str))
+          Pattern::Str(STRINGS.get(str), Loc::default())
         }
         // Char
         '\'' => {
           let char = self.parse_quoted_char()?;
-          Pattern::Num(char as u64)
+          Pattern::Num(char as u64, Loc::default())
         }
         // Number
         c if c.is_ascii_digit() => {
           let num = self.parse_u64()?;
-          Pattern::Num(num)
+          Pattern::Num(num, Loc::default())
         }
         // Var
         _ => {
           let name = self.parse_name_or_era()?;
-          Pattern::Var(name)
+          if let Some(name) = &name {
+            self.record_name(name, NameRole::Pattern, ini_idx);
+          }
+          Pattern::Var(name, Loc::default())
         }
       };
+      // The patterns are built without a location, which is only known once they're fully parsed.
+      *pat.loc_mut() = self.span_from(ini_idx).into();
       Ok(pat)
     })
   }
@@ -186,73 +443,89 @@ impl<'a> TermParser<'a> {
   fn parse_term(&mut self) -> Result<Term, String> {
     maybe_grow(|| {
       let Some(head) = self.skip_peek_one() else { return self.expected("term") };
+      let ini_idx = *self.index();
       let term = match head {
         // Lambda, unscoped lambda
         'λ' | '@' => self.parse_lambda(Tag::Static)?,
         // App, Tup, Num Op
         '(' => {
           self.consume("(")?;
-          let starts_with_oper = self.skip_peek_one().map_or(false, |c| "+-*/%&|<>^=!".contains(c));
-          if starts_with_oper {
+          if self.starts_with_oper() {
             let opr = self.parse_oper()?;
-            if self.skip_starts_with(",")
+            if let Op { ty: _, op: IntOp::Mul } = opr
+              && self.try_consume(")")
+            {
+              // An erased term, for passing an erased argument right after a function, like in `(f (*) x)`
+              Term::era()
+            } else if self.skip_starts_with(",")
               && let Op { ty: _, op: IntOp::Mul } = opr
             {
               // jk, actually a tuple
-              let mut els = vec![Term::Era];
+              let mut els = vec![Term::era()];
               while self.try_consume(",") {
                 els.push(self.parse_term()?);
               }
               self.consume(")")?;
-              Term::Tup { els }
+              Term::Tup { els, loc: Loc::default() }
             } else {
               let fst = self.parse_term()?;
               let snd = self.parse_term()?;
               self.consume(")")?;
-              Term::Opx { opr, fst: Box::new(fst), snd: Box::new(snd) }
+              Term::Opx { opr, fst: Box::new(fst), snd: Box::new(snd), loc: Loc::default() }
             }
           } else {
-            // Tup or App
+            // Tup, Infix op or App
             let head = self.parse_term()?;
-            if self.skip_starts_with(",") {
+            if let Term::Var { nam, .. } = &head
+              && self.starts_with_ambiguous_star()
+            {
+              let ini_idx = *self.index();
+              let exp = format!(
+                "'(* {nam} <term>)' to multiply, or '({nam} (*) <term>)' to apply '{nam}' to an erased argument"
+              );
+              return self.expected_spanned(&exp, ini_idx, ini_idx + 1);
+            }
+            if self.starts_with_infix_oper() {
+              self.parse_infix_expr(head, ")")?
+            } else if self.skip_starts_with(",") {
               // Tup
               let mut els = vec![head];
               while self.try_consume(",") {
                 els.push(self.parse_term()?);
               }
               self.consume(")")?;
-              Term::Tup { els }
+              Term::Tup { els, loc: Loc::default() }
             } else {
               // App
               let els = self.list_like(|p| p.parse_term(), "", ")", "", false, 0)?;
-              els.into_iter().fold(head, |fun, arg| Term::App {
-                tag: Tag::Static,
-                fun: Box::new(fun),
-                arg: Box::new(arg),
-              })
+              if els.is_empty() {
+                // A term in parentheses keeps the location of the term itself.
+                return Ok(head);
+              }
+              els.into_iter().fold(head, |fun, arg| self.make_app(Tag::Static, fun, arg, ini_idx))
             }
           }
         }
         // List
         '[' => {
           let els = self.list_like(|p| p.parse_term(), "[", "]", ",", false, 0)?;
-          Term::Lst { els }
+          Term::Lst { els, loc: Loc::default() }
         }
         // Sup
         '{' => {
           let els = self.list_like(|p| p.parse_term(), "{", "}", ",", false, 2)?;
-          Term::Sup { tag: Tag::Auto, els }
+          Term::Sup { tag: Tag::Auto, els, loc: Loc::default() }
         }
         // Unscoped var
         '$' => {
           self.consume("$")?;
           let nam = self.parse_hvml_name()?;
-          Term::Lnk { nam }
+          Term::Lnk { nam, loc: Loc::default() }
         }
         // Era
         '*' => {
           self.consume("*")?;
-          Term::Era
+          Term::era()
         }
         // Nat, tagged lambda, tagged sup, tagged app
         '#' => {
@@ -262,7 +535,7 @@ impl<'a> TermParser<'a> {
             // Nat
             self.consume("#")?;
             let val = self.parse_u64()?;
-            Term::Nat { val }
+            Term::Nat { val, loc: Loc::default() }
           } else {
             // Tagged term
             let tag = self.parse_tag()?;
@@ -271,15 +544,12 @@ impl<'a> TermParser<'a> {
               // Tagged app
               '(' => {
                 let els = self.list_like(|p| p.parse_term(), "(", ")", "", false, 2)?;
-                els
-                  .into_iter()
-                  .reduce(|fun, arg| Term::App { tag: tag.clone(), fun: Box::new(fun), arg: Box::new(arg) })
-                  .unwrap()
+                els.into_iter().reduce(|fun, arg| self.make_app(tag.clone(), fun, arg, ini_idx)).unwrap()
               }
               // Tagged sup
               '{' => {
                 let els = self.list_like(|p| p.parse_term(), "{", "}", ",", false, 2)?;
-                Term::Sup { tag, els }
+                Term::Sup { tag, els, loc: Loc::default() }
               }
               // Tagged lambda
               'λ' | '@' => self.parse_lambda(tag)?,
@@ -290,27 +560,35 @@ impl<'a> TermParser<'a> {
         // String
         '"' => {
           let val = self.parse_quoted_string()?;
-          Term::Str { val: STRINGS.get(val) }
+          Term::Str { val: STRINGS.get(val), loc: Loc::default() }
         }
         // Char
         '\'' => {
           let chr = self.parse_quoted_char()?;
-          Term::Num { val: chr as u64 }
+          Term::num(chr as u64)
         }
         // Native num
-        c if c.is_ascii_digit() => {
-          let val = self.parse_u64()?;
-          Term::Num { val }
+        c if c.is_ascii_digit() => self.parse_number()?,
+        '-' if self.peek_many(2).is_some_and(|s| s[1 ..].starts_with(|c: char| c.is_ascii_digit())) => {
+          self.parse_number()?
         }
         _ => {
-          if self.try_consume("use") {
+          if self.starts_with_keyword("do") {
+            // do block
+            self.consume("do")?;
+            self.parse_do_block()?
+          } else if self.starts_with_keyword("if") {
+            // if
+            self.consume("if")?;
+            self.parse_if()?
+          } else if self.try_consume("use") {
             // Use
             let nam = self.parse_hvml_name()?;
             self.consume("=")?;
             let val = self.parse_term()?;
             self.try_consume(";");
             let nxt = self.parse_term()?;
-            Term::Use { nam: Some(nam), val: Box::new(val), nxt: Box::new(nxt) }
+            Term::Use { nam: Some(nam), val: Box::new(val), nxt: Box::new(nxt), loc: Loc::default() }
           } else if self.try_consume("let") {
             // Let, let tup, dup, tagged dup
             let Some(head) = self.skip_peek_one() else { return self.expected("let bind") };
@@ -323,7 +601,7 @@ impl<'a> TermParser<'a> {
                 let val = self.parse_term()?;
                 self.try_consume(";");
                 let nxt = self.parse_term()?;
-                Term::Dup { tag, bnd, val: Box::new(val), nxt: Box::new(nxt) }
+                Term::Dup { tag, bnd, val: Box::new(val), nxt: Box::new(nxt), loc: Loc::default() }
               }
               // dup
               '{' => {
@@ -332,7 +610,7 @@ impl<'a> TermParser<'a> {
                 let val = self.parse_term()?;
                 self.try_consume(";");
                 let nxt = self.parse_term()?;
-                Term::Dup { tag: Tag::Auto, bnd, val: Box::new(val), nxt: Box::new(nxt) }
+                Term::Dup { tag: Tag::Auto, bnd, val: Box::new(val), nxt: Box::new(nxt), loc: Loc::default() }
               }
               // Let tup
               '(' => {
@@ -341,7 +619,7 @@ impl<'a> TermParser<'a> {
                 let val = self.parse_term()?;
                 self.try_consume(";");
                 let nxt = self.parse_term()?;
-                Term::Ltp { bnd, val: Box::new(val), nxt: Box::new(nxt) }
+                Term::Ltp { bnd, val: Box::new(val), nxt: Box::new(nxt), loc: Loc::default() }
               }
               // let
               _ => {
@@ -350,65 +628,212 @@ impl<'a> TermParser<'a> {
                 let val = self.parse_term()?;
                 self.try_consume(";");
                 let nxt = self.parse_term()?;
-                Term::Let { nam, val: Box::new(val), nxt: Box::new(nxt) }
+                Term::Let { nam, val: Box::new(val), nxt: Box::new(nxt), loc: Loc::default() }
               }
             }
           } else if self.try_consume("match") {
             // match
-            let (bnd, arg, with) = self.parse_match_arg()?;
-            let rules = self.list_like(|p| p.parse_match_arm(), "{", "}", ";", false, 1)?;
-            Term::Mat { arg: Box::new(arg), bnd: Some(bnd), with, arms: rules }
+            let mut args = vec![self.parse_match_bind()?];
+            while self.try_consume(",") {
+              args.push(self.parse_match_bind()?);
+            }
+            let with = self.parse_match_with()?;
+            let arms = self.list_like(|p| p.parse_match_arm(), "{", "}", ";", false, 1)?;
+            make_match(args, with, arms)
           } else if self.try_consume("switch") {
             // switch
             self.parse_switch()?
           } else {
             // var
             let nam = self.labelled(|p| p.parse_hvml_name(), "term")?;
-            Term::Var { nam }
+            Term::var(nam)
           }
         }
       };
-      Ok(term)
+      // Like patterns, terms only get their location once they're fully parsed.
+      Ok(term.with_loc(self.span_from(ini_idx).into()))
     })
   }
 
+  /// Parses a number literal, either unsigned (`5`), signed (`-5`) or floating point (`1.5`).
+  fn parse_number(&mut self) -> Result<Term, String> {
+    self.skip_trivia();
+    let ini_idx = *self.index();
+    let neg = self.starts_with("-");
+    if neg {
+      self.advance_one();
+    }
+    let val = self.parse_u64()?;
+    let is_float = self.peek_many(2).is_some_and(|s| {
+      let mut chars = s.chars();
+      chars.next() == Some('.') && chars.next().is_some_and(|c| c.is_ascii_digit())
+    });
+    if is_float {
+      self.advance_one();
+      self.take_while(|c| c.is_ascii_digit() || c == '_');
+      let end_idx = *self.index();
+      let text = self.input()[ini_idx .. end_idx].replace('_', "");
+      match text.parse::<f32>() {
+        Ok(val) => Ok(Term::float_num(val)),
+        Err(_) => self.expected_spanned("float literal", ini_idx, end_idx),
+      }
+    } else if neg {
+      Ok(Term::signed_num((val as i64).wrapping_neg()))
+    } else {
+      Ok(Term::num(val))
+    }
+  }
+
+  /// Checks if the input starts with a numeric operator, optionally prefixed by its type (`i32.+`).
+  fn starts_with_oper(&mut self) -> bool {
+    self.skip_trivia();
+    let rest = self.input().get(*self.index() ..).unwrap_or_default();
+    let rest = OP_TYPES.iter().find_map(|(prefix, _)| rest.strip_prefix(prefix)).unwrap_or(rest);
+    rest.starts_with(|c: char| "+-*/%&|<>^=!".contains(c))
+  }
+
   fn parse_oper(&mut self) -> Result<Op, String> {
-    let opr = if self.try_consume("+") {
-      Op { ty: OpType::U60, op: IntOp::Add }
+    self.skip_trivia();
+    let ty = match OP_TYPES.iter().find(|(prefix, _)| self.starts_with(prefix)) {
+      Some((prefix, ty)) => {
+        self.advance_many(prefix.len());
+        *ty
+      }
+      None => OpType::U60,
+    };
+    let op = if self.try_consume("+") {
+      IntOp::Add
     } else if self.try_consume("-") {
-      Op { ty: OpType::U60, op: IntOp::Sub }
+      IntOp::Sub
     } else if self.try_consume("*") {
-      Op { ty: OpType::U60, op: IntOp::Mul }
+      IntOp::Mul
     } else if self.try_consume("/") {
-      Op { ty: OpType::U60, op: IntOp::Div }
+      IntOp::Div
     } else if self.try_consume("%") {
-      Op { ty: OpType::U60, op: IntOp::Rem }
+      IntOp::Rem
     } else if self.try_consume("<<") {
-      Op { ty: OpType::U60, op: IntOp::Shl }
+      IntOp::Shl
     } else if self.try_consume(">>") {
-      Op { ty: OpType::U60, op: IntOp::Shr }
+      IntOp::Shr
     } else if self.try_consume("<=") {
-      Op { ty: OpType::U60, op: IntOp::Le }
+      IntOp::Le
     } else if self.try_consume(">=") {
-      Op { ty: OpType::U60, op: IntOp::Ge }
+      IntOp::Ge
     } else if self.try_consume("<") {
-      Op { ty: OpType::U60, op: IntOp::Lt }
+      IntOp::Lt
     } else if self.try_consume(">") {
-      Op { ty: OpType::U60, op: IntOp::Gt }
+      IntOp::Gt
     } else if self.try_consume("==") {
-      Op { ty: OpType::U60, op: IntOp::Eq }
+      IntOp::Eq
     } else if self.try_consume("!=") {
-      Op { ty: OpType::U60, op: IntOp::Ne }
+      IntOp::Ne
     } else if self.try_consume("&") {
-      Op { ty: OpType::U60, op: IntOp::And }
+      IntOp::And
     } else if self.try_consume("|") {
-      Op { ty: OpType::U60, op: IntOp::Or }
+      IntOp::Or
     } else if self.try_consume("^") {
-      Op { ty: OpType::U60, op: IntOp::Xor }
+      IntOp::Xor
     } else {
       return self.expected("numeric operator");
     };
-    Ok(opr)
+    Ok(Op { ty, op })
+  }
+
+  fn parse_operator(&mut self) -> Result<Operator, String> {
+    // ("infixl" | "infixr") prec symbol = def
+    let assoc = if self.try_consume("infixl") {
+      Assoc::Left
+    } else {
+      self.consume("infixr")?;
+      Assoc::Right
+    };
+    self.skip_trivia();
+    let ini_idx = *self.index();
+    let prec = self.parse_u64()?;
+    if prec > MAX_PRECEDENCE {
+      let end_idx = *self.index();
+      return self.expected_spanned(&format!("precedence from 0 to {MAX_PRECEDENCE}"), ini_idx, end_idx);
+    }
+    self.skip_trivia();
+    let ini_idx = *self.index();
+    let symbol = self.take_while(|c| OPER_CHARS.contains(c)).to_string();
+    let end_idx = *self.index();
+    if symbol.is_empty() {
+      return self.expected("operator symbol");
+    }
+    if INFIX_OPERS.iter().chain(&BOOL_OPERS).any(|(builtin, ..)| *builtin == symbol) {
+      return self.expected_spanned("operator that is not builtin", ini_idx, end_idx);
+    }
+    if self.operators.contains_key(&symbol) {
+      return self.expected_spanned("operator that is not already declared", ini_idx, end_idx);
+    }
+    self.consume("=")?;
+    let def = self.labelled(|p| p.parse_hvml_name(), "definition name")?;
+    Ok(Operator { symbol, prec: prec as u8, assoc, def })
+  }
+
+  /// Checks if the input starts with an infix operator, optionally prefixed by its type (`i32.+`).
+  /// The operator must be followed by whitespace, to tell `(a * b)` and `(a - b)` apart from `(f *)` and `(f -1)`.
+  fn starts_with_infix_oper(&mut self) -> bool {
+    self.skip_trivia();
+    let rest = self.input().get(*self.index() ..).unwrap_or_default();
+    let rest = OP_TYPES.iter().find_map(|(prefix, _)| rest.strip_prefix(prefix)).unwrap_or(rest);
+    let symbol_len = rest.find(|c| !OPER_CHARS.contains(c)).unwrap_or(rest.len());
+    symbol_len > 0 && rest[symbol_len ..].starts_with(char::is_whitespace)
+  }
+
+  /// Checks if the input starts with a `*` on its own followed by another term.
+  /// Right after a variable, like in `(a * b)`, it could be a multiplication or an erased argument.
+  fn starts_with_ambiguous_star(&mut self) -> bool {
+    self.skip_trivia();
+    let rest = self.input().get(*self.index() ..).unwrap_or_default();
+    rest
+      .strip_prefix('*')
+      .is_some_and(|rest| rest.starts_with(char::is_whitespace) && !rest.trim_start().starts_with(')'))
+  }
+
+  /// Parses an infix expression like `(a + b * c)`, after its first operand and up to `end`.
+  /// The operands are grouped by the precedence of the operators when desugaring.
+  fn parse_infix_expr(&mut self, fst: Term, end: &str) -> Result<Term, String> {
+    let mut rest = vec![];
+    while !self.try_consume(end) {
+      let oper = self.parse_infix_oper()?;
+      rest.push((oper, self.parse_term()?));
+    }
+    let lst = rest.last().map_or(&fst, |(_, operand)| operand);
+    let loc = fst.loc().span().zip(lst.loc().span()).map(|(fst, lst)| Span { end: lst.end, ..fst });
+    Ok(Term::Infix { fst: Box::new(fst), rest, loc: loc.into() })
+  }
+
+  /// Parses the operator of an infix expression.
+  fn parse_infix_oper(&mut self) -> Result<InfixOper, String> {
+    self.skip_trivia();
+    let ini_idx = *self.index();
+    let ty = match OP_TYPES.iter().find(|(prefix, _)| self.starts_with(prefix)) {
+      Some((prefix, ty)) => {
+        self.advance_many(prefix.len());
+        Some(*ty)
+      }
+      None => None,
+    };
+    let symbol = self.take_while(|c| OPER_CHARS.contains(c));
+    let end_idx = *self.index();
+    let written = self.input()[ini_idx .. end_idx].to_string();
+    let oper = |kind, prec, assoc| Ok(InfixOper { symbol: written.clone(), kind, prec, assoc });
+    if let Some((_, prec, op)) = INFIX_OPERS.iter().find(|(builtin, ..)| *builtin == symbol) {
+      let opr = Op { ty: ty.unwrap_or(OpType::U60), op: *op };
+      return oper(InfixKind::Num(opr), *prec, Assoc::Left);
+    }
+    if ty.is_none() {
+      if let Some((_, prec, op)) = BOOL_OPERS.iter().find(|(builtin, ..)| *builtin == symbol) {
+        return oper(InfixKind::Bool(*op), *prec, Assoc::Left);
+      }
+      if let Some(decl) = self.operators.get(symbol) {
+        return oper(InfixKind::Def(decl.def.clone()), decl.prec, decl.assoc);
+      }
+    }
+    // Declared operators are not imported with the definitions of their file.
+    self.expected_spanned("builtin infix operator or one declared before in this file", ini_idx, end_idx)
   }
 
   fn parse_lambda(&mut self, tag: Tag) -> Result<Term, String> {
@@ -417,12 +842,12 @@ impl<'a> TermParser<'a> {
       // unscoped lambda
       let nam = self.parse_hvml_name()?;
       let bod = self.parse_term()?;
-      Term::Chn { tag, nam: Some(nam), bod: Box::new(bod) }
+      Term::Chn { tag, nam: Some(nam), bod: Box::new(bod), loc: Loc::default() }
     } else {
       // normal lambda
       let nam = self.parse_name_or_era()?;
       let bod = self.parse_term()?;
-      Term::Lam { tag, nam, bod: Box::new(bod) }
+      Term::Lam { tag, nam, bod: Box::new(bod), loc: Loc::default() }
     };
     Ok(term)
   }
@@ -432,6 +857,22 @@ impl<'a> TermParser<'a> {
     Ok(Name::new(nam))
   }
 
+  /// Parses a name, recording where it was written if names are being kept.
+  fn parse_recorded_name(&mut self, role: NameRole, label: &str) -> Result<Name, String> {
+    self.skip_trivia();
+    let ini_idx = *self.index();
+    let name = self.labelled(|p| p.parse_hvml_name(), label)?;
+    self.record_name(&name, role, ini_idx);
+    Ok(name)
+  }
+
+  fn record_name(&mut self, name: &Name, role: NameRole, ini_idx: usize) {
+    let end_idx = *self.index();
+    if let Some(names) = &mut self.names {
+      names.push(ParsedName { name: name.clone(), role, range: ini_idx .. end_idx });
+    }
+  }
+
   fn parse_name_or_era(&mut self) -> Result<Option<Name>, String> {
     self.labelled(
       |p| {
@@ -453,8 +894,21 @@ impl<'a> TermParser<'a> {
   }
 
   fn parse_match_arg(&mut self) -> Result<(Name, Term, Vec<Name>), String> {
+    let (bnd, arg) = self.parse_match_bind()?;
+    let with = self.parse_match_with()?;
+    Ok((bnd, arg, with))
+  }
+
+  fn parse_match_bind(&mut self) -> Result<(Name, Term), String> {
+    self.skip_trivia();
+    let ini_idx = *self.index();
     let bnd = self.parse_hvml_name()?;
-    let arg = if self.try_consume("=") { self.parse_term()? } else { Term::Var { nam: bnd.clone() } };
+    let loc = self.span_from(ini_idx).into();
+    let arg = if self.try_consume("=") { self.parse_term()? } else { Term::Var { nam: bnd.clone(), loc } };
+    Ok((bnd, arg))
+  }
+
+  fn parse_match_with(&mut self) -> Result<Vec<Name>, String> {
     let with = if self.try_consume("with") {
       let mut with = vec![self.parse_hvml_name()?];
       while !self.skip_starts_with("{") {
@@ -465,15 +919,30 @@ impl<'a> TermParser<'a> {
     } else {
       vec![]
     };
-    Ok((bnd, arg, with))
+    Ok(with)
   }
 
-  fn parse_match_arm(&mut self) -> Result<MatchRule, String> {
+  fn parse_match_arm(&mut self) -> Result<Rule, String> {
     self.try_consume("|");
-    let nam = self.parse_name_or_era()?;
+    self.skip_trivia();
+    let ini_idx = *self.index();
+    let mut pats = vec![self.parse_rule_pattern()?];
+    while self.try_consume(",") {
+      pats.push(self.parse_rule_pattern()?);
+    }
+    let guard = self.parse_guard()?;
     self.consume(":")?;
-    let bod = self.parse_term()?;
-    Ok((nam, vec![], bod))
+    let body = self.parse_term()?;
+    Ok(Rule { pats, guard, body, loc: self.span_from(ini_idx).into() })
+  }
+
+  fn parse_guard(&mut self) -> Result<Option<Term>, String> {
+    if self.starts_with_keyword("if") {
+      self.consume("if")?;
+      Ok(Some(self.parse_term()?))
+    } else {
+      Ok(None)
+    }
   }
 
   fn parse_switch(&mut self) -> Result<Term, String> {
@@ -509,7 +978,72 @@ impl<'a> TermParser<'a> {
     }
     let pred = Some(Name::new(format!("{}-{}", bnd, arms.len() - 1)));
     self.consume("}")?;
-    Ok(Term::Swt { arg: Box::new(arg), bnd: Some(bnd), with, pred, arms })
+    Ok(Term::Swt { arg: Box::new(arg), bnd: Some(bnd), with, pred, arms, loc: Loc::default() })
+  }
+
+  fn parse_if(&mut self) -> Result<Term, String> {
+    // The condition can be an infix expression without parentheses, up to the `{`.
+    let cond = self.parse_term()?;
+    let cond = if self.starts_with_infix_oper() {
+      self.parse_infix_expr(cond, "{")?
+    } else {
+      self.consume("{")?;
+      cond
+    };
+    let then = self.parse_term()?;
+    self.consume("}")?;
+    self.consume("else")?;
+    let els = if self.starts_with_keyword("if") {
+      // else if
+      let ini_idx = *self.index();
+      self.consume("if")?;
+      self.parse_if()?.with_loc(self.span_from(ini_idx).into())
+    } else {
+      self.consume("{")?;
+      let els = self.parse_term()?;
+      self.consume("}")?;
+      els
+    };
+    Ok(Term::If { cond: Box::new(cond), then: Box::new(then), els: Box::new(els), loc: Loc::default() })
+  }
+
+  fn parse_do_block(&mut self) -> Result<Term, String> {
+    self.consume("{")?;
+    let mut stmts = vec![];
+    loop {
+      self.skip_trivia();
+      let ini_idx = *self.index();
+      let bnd = self.parse_do_bind();
+      let val = self.parse_term()?;
+      self.try_consume(";");
+      if self.skip_starts_with("}") {
+        if bnd.is_some() {
+          return self.expected("statement after the bind");
+        }
+        self.consume("}")?;
+        // Each statement spans from where it starts to the end of the block.
+        let term = stmts.into_iter().rfold(val, |nxt, (nam, val, ini_idx)| Term::Do {
+          nam,
+          val: Box::new(val),
+          nxt: Box::new(nxt),
+          loc: self.span_from(ini_idx).into(),
+        });
+        return Ok(term);
+      }
+      stmts.push((bnd.unwrap_or(None), val, ini_idx));
+    }
+  }
+
+  /// Parses the `<name> <-` that starts a bind statement of a `do` block, if there's one.
+  fn parse_do_bind(&mut self) -> Option<Option<Name>> {
+    let ini_idx = *self.index();
+    match self.parse_name_or_era() {
+      Ok(nam) if self.try_consume("<-") => Some(nam),
+      _ => {
+        *self.index() = ini_idx;
+        None
+      }
+    }
   }
 
   /* Utils */
@@ -521,6 +1055,14 @@ impl<'a> TermParser<'a> {
     self.starts_with(text)
   }
 
+  /// Checks if the next token is the given keyword, and not just a name that starts with it.
+  fn starts_with_keyword(&mut self, keyword: &str) -> bool {
+    self.skip_trivia();
+    let rest = self.input().get(*self.index() ..).unwrap_or_default();
+    rest.starts_with(keyword)
+      && rest[keyword.len() ..].starts_with(|c: char| c.is_ascii_whitespace() || c == '{')
+  }
+
   fn skip_peek_one(&mut self) -> Option<char> {
     self.skip_trivia();
     self.peek_one()
@@ -576,6 +1118,22 @@ impl<'a> TermParser<'a> {
     }
   }
 
+  /// The span from `ini_idx` to the current position, if a source file is being parsed.
+  fn span_from(&self, ini_idx: usize) -> Option<Span> {
+    self.span_between(ini_idx, self.index)
+  }
+
+  fn span_between(&self, ini_idx: usize, end_idx: usize) -> Option<Span> {
+    self.file.as_ref().map(|file| Span { file: file.clone(), ini: ini_idx, end: end_idx })
+  }
+
+  /// Applies `fun` to `arg`, spanning from `ini_idx` to the end of the argument.
+  fn make_app(&self, tag: Tag, fun: Term, arg: Term, ini_idx: usize) -> Term {
+    let end_idx = arg.loc().span().map_or(self.index, |arg| arg.end);
+    let loc = self.span_between(ini_idx, end_idx).into();
+    Term::App { tag, fun: Box::new(fun), arg: Box::new(arg), loc }
+  }
+
   /* Overrides */
 
   /// Generates an error message for parsing failures, including the highlighted context.
@@ -716,6 +1274,7 @@ impl<'a> TermParser<'a> {
         continue;
       }
       if c == '/' && self.input().get(*self.index() ..).unwrap_or_default().starts_with("//") {
+        let ini_idx = *self.index();
         while let Some(c) = self.peek_one() {
           if c != '\n' {
             self.advance_one();
@@ -723,6 +1282,10 @@ impl<'a> TermParser<'a> {
             break;
           }
         }
+        let end_idx = *self.index();
+        if let Some(comments) = &mut self.comments {
+          comments.push(ini_idx .. end_idx);
+        }
         self.advance_one(); // Skip the newline character as well
         continue;
       }
@@ -770,3 +1333,25 @@ fn add_ctx(msg: &str, ini_idx: usize, end_idx: usize, file: &str) -> String {
   let ctx = highlight_error(ini_idx, end_idx, file);
   format!("{msg}\n{ctx}")
 }
+
+/// Builds a match term.
+/// A match on one value where each arm is a constructor name or a variable is kept as a [`Term::Mat`],
+/// while nested patterns, guards and matches on several values are compiled later from a [`Term::Cas`].
+fn make_match(args: Vec<(Name, Term)>, with: Vec<Name>, arms: Vec<Rule>) -> Term {
+  let is_simple =
+    arms.iter().all(|arm| matches!(arm.pats.as_slice(), [Pattern::Var(..)]) && arm.guard.is_none());
+  if args.len() == 1 && is_simple {
+    let (bnd, arg) = args.into_iter().next().unwrap();
+    let arms = arms
+      .into_iter()
+      .map(|mut arm| {
+        let Some(Pattern::Var(nam, _)) = arm.pats.pop() else { unreachable!() };
+        (nam, vec![], arm.body)
+      })
+      .collect();
+    Term::Mat { arg: Box::new(arg), bnd: Some(bnd), with, arms, loc: Loc::default() }
+  } else {
+    let (bnds, args) = args.into_iter().map(|(bnd, arg)| (Some(bnd), arg)).unzip();
+    Term::Cas { args, bnds, with, arms, loc: Loc::default() }
+  }
+}
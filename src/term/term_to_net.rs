@@ -10,14 +10,25 @@ use crate::{
 use std::collections::{hash_map::Entry, HashMap};
 
 pub fn book_to_nets(book: &Book) -> (HashMap<String, INet>, Labels) {
-  let mut nets = HashMap::new();
   let mut labels = Labels::default();
+  let nets = book_to_nets_with_labels(book, &mut labels);
+
+  labels.con.finish();
+  labels.dup.finish();
+
+  (nets, labels)
+}
+
+/// Like [`book_to_nets`], but keeps generating labels after the ones in `labels`.
+/// Used to compile new definitions that run together with already compiled ones.
+pub fn book_to_nets_with_labels(book: &Book, labels: &mut Labels) -> HashMap<String, INet> {
+  let mut nets = HashMap::new();
 
   let main = book.entrypoint.as_ref().unwrap();
 
   for def in book.defs.values() {
     for rule in def.rules.iter() {
-      let net = term_to_compat_net(&rule.body, &mut labels);
+      let net = term_to_compat_net(&rule.body, labels);
 
       let name = if def.name == *main { book.hvmc_entrypoint().to_string() } else { def.name.0.to_string() };
 
@@ -25,10 +36,7 @@ pub fn book_to_nets(book: &Book) -> (HashMap<String, INet>, Labels) {
     }
   }
 
-  labels.con.finish();
-  labels.dup.finish();
-
-  (nets, labels)
+  nets
 }
 
 /// Converts an IC term into an IC net.
@@ -76,7 +84,7 @@ impl EncodeTermState<'_> {
         // - 1: points to the lambda variable.
         // - 2: points to the lambda body.
         // core: (var_use bod)
-        Term::Lam { tag, nam, bod } => {
+        Term::Lam { tag, nam, bod, .. } => {
           let fun = self.inet.new_node(Con { lab: self.labels.con.generate(tag) });
 
           self.push_scope(nam, Port(fun, 1));
@@ -87,7 +95,7 @@ impl EncodeTermState<'_> {
           Some(Port(fun, 0))
         }
         // core: (var_use bod)
-        Term::Chn { tag, nam, bod } => {
+        Term::Chn { tag, nam, bod, .. } => {
           let fun = self.inet.new_node(Con { lab: self.labels.con.generate(tag) });
           if let Some(nam) = nam {
             self.global_vars.entry(nam.clone()).or_default().0 = Port(fun, 1);
@@ -101,7 +109,7 @@ impl EncodeTermState<'_> {
         // - 1: points to the function's argument.
         // - 2: points to where the application occurs.
         // core: & fun ~ (arg ret) (fun not necessarily main port)
-        Term::App { tag, fun, arg } => {
+        Term::App { tag, fun, arg, .. } => {
           let app = self.inet.new_node(Con { lab: self.labels.con.generate(tag) });
 
           let fun = self.encode_term(fun, Port(app, 0));
@@ -112,9 +120,9 @@ impl EncodeTermState<'_> {
 
           Some(Port(app, 2))
         }
-        Term::Mat { .. } => unreachable!("Should've been desugared already"),
+        Term::Mat { .. } | Term::Cas { .. } => unreachable!("Should've been desugared already"),
         // core: & arg ~ ?<(zero succ) ret>
-        Term::Swt { arg, bnd: _, with, pred: _, arms: rules } => {
+        Term::Swt { arg, bnd: _, with, pred: _, arms: rules, loc: _ } => {
           // At this point should be only num matches of 0 and succ.
           assert!(with.is_empty());
           assert!(rules.len() == 2);
@@ -145,7 +153,7 @@ impl EncodeTermState<'_> {
         // Dups with more than 2 variables become a list-like node tree of n-1 nodes.
         // All the nodes of a dup tree have the same label.
         // `@x dup #i {x0 x1 x2 x3}; A` => `({i x0 {i x1 {i x2 x3}}} A)`
-        Term::Dup { tag, bnd, val, nxt } => {
+        Term::Dup { tag, bnd, val, nxt, .. } => {
           let lab = self.labels.dup.generate(tag).unwrap();
           let (main, aux) = self.make_node_list(Dup { lab }, bnd.len());
 
@@ -164,7 +172,7 @@ impl EncodeTermState<'_> {
 
           nxt
         }
-        Term::Var { nam } => {
+        Term::Var { nam, .. } => {
           // We assume this variable to be valid, bound and correctly scoped.
           // This pass must be done before.
           debug_assert!(
@@ -179,18 +187,18 @@ impl EncodeTermState<'_> {
           *use_port = Some(up);
           Some(*declare_port)
         }
-        Term::Lnk { nam } => {
+        Term::Lnk { nam, .. } => {
           self.global_vars.entry(nam.clone()).or_default().1 = up;
           None
         }
         // core: @def_id
-        Term::Ref { nam: def_name } => {
+        Term::Ref { nam: def_name, .. } => {
           let node = self.inet.new_node(Ref { def_name: def_name.clone() });
           self.inet.link(Port(node, 1), Port(node, 2));
           self.inet.link(up, Port(node, 0));
           Some(Port(node, 0))
         }
-        Term::Ltp { bnd, val, nxt } => {
+        Term::Ltp { bnd, val, nxt, .. } => {
           let (main, aux) = self.make_node_list(Tup, bnd.len());
 
           let val = self.encode_term(val, main);
@@ -206,7 +214,7 @@ impl EncodeTermState<'_> {
 
           nxt
         }
-        Term::Let { nam: None, val, nxt } => {
+        Term::Let { nam: None, val, nxt, .. } => {
           let nod = self.inet.new_node(Era);
           let val = self.encode_term(val, Port(nod, 0));
 
@@ -214,9 +222,12 @@ impl EncodeTermState<'_> {
 
           self.encode_term(nxt, up)
         }
-        Term::Let { .. } => unreachable!(), // Removed in earlier pass
-        Term::Use { .. } => unreachable!(), // Removed in earlier pass
-        Term::Sup { tag, els } => {
+        Term::Let { .. } => unreachable!(),   // Removed in earlier pass
+        Term::Use { .. } => unreachable!(),   // Removed in earlier pass
+        Term::Do { .. } => unreachable!(),    // Removed in earlier pass
+        Term::Infix { .. } => unreachable!(), // Removed in desugar_infix
+        Term::If { .. } => unreachable!(),    // Removed in desugar_infix
+        Term::Sup { tag, els, .. } => {
           let lab = self.labels.dup.generate(tag).unwrap();
           let (main, aux) = self.make_node_list(Dup { lab }, els.len());
 
@@ -227,13 +238,13 @@ impl EncodeTermState<'_> {
 
           Some(main)
         }
-        Term::Era => {
+        Term::Era { .. } => {
           let era = self.inet.new_node(Era);
           self.inet.link(Port(era, 1), Port(era, 2));
           Some(Port(era, 0))
         }
         // core: #val
-        Term::Num { val } => {
+        Term::Num { val, .. } => {
           let node = self.inet.new_node(Num { val: *val });
           // This representation only has nodes of arity 2, so we connect the two aux ports that are not used.
           self.inet.link(Port(node, 1), Port(node, 2));
@@ -243,7 +254,7 @@ impl EncodeTermState<'_> {
         Term::Str { .. } => unreachable!(), // Removed in encode_str
         Term::Lst { .. } => unreachable!(), // Removed in encode_list
         // core: & fst ~ <op snd ret>
-        Term::Opx { opr, fst, snd } => {
+        Term::Opx { opr, fst, snd, .. } => {
           let opx = self.inet.new_node(Op2 { opr: *opr });
 
           let fst_port = self.encode_term(fst, Port(opx, 0));
@@ -254,7 +265,7 @@ impl EncodeTermState<'_> {
 
           Some(Port(opx, 2))
         }
-        Term::Tup { els } => {
+        Term::Tup { els, .. } => {
           let (main, aux) = self.make_node_list(Tup, els.len());
           for (el, aux) in els.iter().zip(aux.iter()) {
             let el = self.encode_term(el, *aux);
@@ -263,7 +274,7 @@ impl EncodeTermState<'_> {
 
           Some(main)
         }
-        Term::Err => unreachable!(),
+        Term::Err { .. } => unreachable!(),
       }
     })
   }
@@ -26,18 +26,21 @@ impl Ctx<'_> {
         self.info.add_rule_error(
           format!("Expected the entrypoint function to have only one rule, found {n_rules}."),
           entrypoint.clone(),
+          main_def.rules[1].loc.span(),
         );
       }
 
+      let span = main_def.span();
       let mut main_body = std::mem::take(&mut main_def.rules[0].body);
 
       for pat in main_def.rules[0].pats.iter().rev() {
-        if let Pattern::Var(var) = pat {
+        if let Pattern::Var(var, _) = pat {
           main_body = Term::lam(var.clone(), main_body);
         } else {
           self.info.add_rule_error(
             format!("Expected the entrypoint function to only have variable patterns, found '{pat}'."),
             entrypoint.clone(),
+            span.clone(),
           );
         }
       }
@@ -46,7 +49,7 @@ impl Ctx<'_> {
         main_body = Term::call(main_body, args);
       }
 
-      main_def.rules = vec![Rule { pats: vec![], body: main_body }];
+      main_def.rules = vec![Rule { pats: vec![], guard: None, body: main_body, loc: span.into() }];
     }
 
     self.info.fatal(())
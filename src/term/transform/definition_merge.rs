@@ -1,4 +1,5 @@
 use crate::{
+  diagnostics::Loc,
   maybe_grow,
   term::{Book, Definition, Name, Rule, Term},
 };
@@ -40,8 +41,8 @@ impl Book {
       if equal_defs.len() > 1 {
         // Merging some defs
         // Add the merged def
-        let new_def =
-          Definition { name: new_name.clone(), rules: vec![Rule { pats: vec![], body: term }], builtin };
+        let rules = vec![Rule { pats: vec![], guard: None, body: term, loc: Loc::default() }];
+        let new_def = Definition { name: new_name.clone(), rules, builtin };
         self.defs.insert(new_name.clone(), new_def);
         // Remove the old ones and write the map of old names to new ones.
         for name in equal_defs {
@@ -90,7 +91,7 @@ impl Term {
   /// `ref_map` with their corresponding targets.
   pub fn subst_ref_to_ref(term: &mut Term, ref_map: &BTreeMap<Name, Name>) -> bool {
     maybe_grow(|| match term {
-      Term::Ref { nam: def_name } => {
+      Term::Ref { nam: def_name, .. } => {
         if let Some(target_name) = ref_map.get(def_name) {
           *def_name = target_name.clone();
           true
@@ -81,7 +81,8 @@ impl Ctx<'_> {
       if prune_all || def.builtin {
         self.book.defs.shift_remove(&def_name);
       } else if !def_name.is_generated() {
-        self.info.add_rule_warning(UnusedDefinitionWarning, WarningType::UnusedDefinition, def_name);
+        let span = def.span();
+        self.info.add_rule_warning(UnusedDefinitionWarning, WarningType::UnusedDefinition, def_name, span);
       }
     }
   }
@@ -102,7 +103,7 @@ impl Book {
       self.find_manual_adt_encoding(term, uses, adt_encoding);
 
       match term {
-        Term::Ref { nam: def_name } => match self.ctrs.get(def_name) {
+        Term::Ref { nam: def_name, .. } => match self.ctrs.get(def_name) {
           Some(name) => self.insert_ctrs_used(name, uses, adt_encoding),
           None => self.insert_used(def_name, used, uses, adt_encoding),
         },
@@ -136,6 +137,13 @@ impl Book {
         }
         _ => (),
       },
+      // Likewise for the superpositions and dups tagged with the name of an adt.
+      AdtEncoding::NumTagged => match term {
+        Term::Sup { tag: Tag::Named(name), .. } | Term::Dup { tag: Tag::Named(name), .. } => {
+          self.insert_ctrs_used(name, uses, adt_encoding);
+        }
+        _ => (),
+      },
     }
   }
 
@@ -148,6 +156,12 @@ impl Book {
         }
         return;
       }
+      if let Some(host_uses) = self.host_builtins.get(def_name) {
+        for def_name in host_uses {
+          self.insert_used(def_name, used, uses, adt_encoding);
+        }
+        return;
+      }
 
       // This needs to be done for each rule in case the pass it's ran from has not encoded the pattern match
       // E.g.: the `flatten_rules` golden test
@@ -0,0 +1,42 @@
+use crate::{
+  maybe_grow,
+  term::{Book, Term, IO_BIND},
+};
+
+impl Book {
+  /// Turns the statements of `do` blocks into calls to `IO.bind`.
+  ///
+  /// Example:
+  /// ```hvm
+  /// do { name <- IO.query; (IO.print name) }
+  ///
+  /// // Transforms to:
+  /// (IO.bind IO.query λname (IO.print name))
+  /// ```
+  ///
+  /// The effects are then performed in the written order by `IO.run`,
+  /// both in strict and lazy mode.
+  pub fn desugar_do_blocks(&mut self) {
+    for def in self.defs.values_mut() {
+      for rule in def.rules.iter_mut() {
+        rule.terms_mut().for_each(Term::desugar_do_blocks);
+      }
+    }
+  }
+}
+
+impl Term {
+  pub fn desugar_do_blocks(&mut self) {
+    maybe_grow(|| {
+      for child in self.children_mut() {
+        child.desugar_do_blocks();
+      }
+
+      if let Term::Do { nam, val, nxt, loc } = self {
+        let nxt = Term::lam(nam.take(), std::mem::take(nxt.as_mut()));
+        let loc = std::mem::take(loc);
+        *self = Term::call(Term::r#ref(IO_BIND), [std::mem::take(val.as_mut()), nxt]).with_loc(loc);
+      }
+    })
+  }
+}
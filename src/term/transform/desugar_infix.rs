@@ -0,0 +1,112 @@
+use crate::{
+  diagnostics::{Loc, Span},
+  maybe_grow,
+  term::{Assoc, Book, InfixKind, InfixOper, IntOp, Name, Op, OpType, Term},
+};
+
+/// The name bound to the condition of the switches that `if`, `&&` and `||` turn into.
+const COND_BND: &str = "%cond";
+
+impl Book {
+  /// Turns infix expressions into numeric operations and calls to the definitions of declared operators,
+  /// grouping their operands by the precedence of the operators, and `if`s into switches.
+  ///
+  /// Example:
+  /// ```hvm
+  /// if a + b * c == 0 { x } else { y }
+  ///
+  /// // Transforms to:
+  /// switch %cond = (== (+ a (* b c)) 0) { 0: y; _: x }
+  /// ```
+  ///
+  /// `&&` and `||` also turn into switches, so that they only evaluate their second operand
+  /// when the first one doesn't decide the result.
+  pub fn desugar_infix(&mut self) {
+    for def in self.defs.values_mut() {
+      for rule in def.rules.iter_mut() {
+        rule.terms_mut().for_each(Term::desugar_infix);
+      }
+    }
+  }
+}
+
+impl Term {
+  pub fn desugar_infix(&mut self) {
+    maybe_grow(|| {
+      for child in self.children_mut() {
+        child.desugar_infix();
+      }
+
+      match self {
+        Term::Infix { fst, rest, loc } => {
+          let loc = std::mem::take(loc);
+          *self = group_infix(std::mem::take(fst.as_mut()), std::mem::take(rest)).with_loc(loc);
+        }
+        Term::If { cond, then, els, loc } => {
+          let cond = std::mem::take(cond.as_mut());
+          let loc = std::mem::take(loc);
+          *self = make_if(cond, std::mem::take(then.as_mut()), std::mem::take(els.as_mut())).with_loc(loc);
+        }
+        _ => {}
+      }
+    })
+  }
+}
+
+/// Groups the operands of an infix expression, applying its operators.
+/// Operators with higher precedence bind first, and the ones with the same precedence
+/// are grouped according to their associativity.
+fn group_infix(fst: Term, rest: Vec<(InfixOper, Term)>) -> Term {
+  fn reduce(operands: &mut Vec<Term>, oper: InfixOper) {
+    let snd = operands.pop().unwrap();
+    let fst = operands.pop().unwrap();
+    let loc = fst.loc().span().zip(snd.loc().span()).map(|(fst, snd)| Span { end: snd.end, ..fst });
+    operands.push(oper.apply(fst, snd).with_loc(loc.into()));
+  }
+
+  let mut operands = vec![fst];
+  let mut opers: Vec<InfixOper> = vec![];
+  for (oper, operand) in rest {
+    while opers
+      .last()
+      .is_some_and(|top| top.prec > oper.prec || (top.prec == oper.prec && oper.assoc == Assoc::Left))
+    {
+      reduce(&mut operands, opers.pop().unwrap());
+    }
+    opers.push(oper);
+    operands.push(operand);
+  }
+  while let Some(oper) = opers.pop() {
+    reduce(&mut operands, oper);
+  }
+  operands.pop().unwrap()
+}
+
+/// Builds the switch that an `if` turns into, which takes the `else` branch when the condition is 0.
+fn make_if(cond: Term, then: Term, els: Term) -> Term {
+  Term::switch(cond, Name::new(COND_BND), els, then)
+}
+
+impl InfixOper {
+  fn apply(self, fst: Term, snd: Term) -> Term {
+    match self.kind {
+      InfixKind::Num(opr) => Term::Opx { opr, fst: Box::new(fst), snd: Box::new(snd), loc: Loc::default() },
+      InfixKind::Bool(op) => {
+        // The result is 1 or 0, like the result of a comparison.
+        let snd = Term::Opx {
+          opr: Op { ty: OpType::U60, op: IntOp::Ne },
+          fst: Box::new(snd),
+          snd: Box::new(Term::num(0)),
+          loc: Loc::default(),
+        };
+        match op {
+          IntOp::And => make_if(fst, snd, Term::num(0)),
+          IntOp::Or => make_if(fst, Term::num(1), snd),
+          _ => unreachable!(),
+        }
+      }
+      // Refs are still encoded as vars at this point.
+      InfixKind::Def(nam) => Term::call(Term::var(nam), [fst, snd]),
+    }
+  }
+}
@@ -1,58 +1,442 @@
 use crate::{
-  diagnostics::{Diagnostics, ToStringVerbose, WarningType},
-  term::{builtins, Adts, Constructors, Ctx, Definition, Name, Pattern, Rule, Term},
+  diagnostics::{Diagnostics, Loc, Span, ToStringVerbose, WarningType},
+  maybe_grow,
+  term::{builtins, display::DisplayFn, Adts, Constructors, Ctx, Definition, Name, Pattern, Rule, Term},
 };
+use itertools::Itertools;
 use std::collections::{BTreeSet, HashSet};
 
+/// How many of the cases not covered by a pattern matching are shown in the error message.
+const MAX_MISSING_SHOWN: usize = 3;
+
 pub enum DesugarMatchDefErr {
-  AdtNotExhaustive { adt: Name, ctr: Name },
-  NumMissingDefault,
+  NotExhaustive { missing: Vec<Vec<Pattern>>, has_guards: bool },
   TypeMismatch { expected: Type, found: Type, pat: Pattern },
   RepeatedBind { bind: Name },
+  RedundantRule { idx: usize, rule: Vec<Pattern>, shadowed_by: Option<(usize, Vec<Pattern>)> },
+  UnreachableAlternatives { pat: Pattern },
+  RedundantAlternative { pat: Pattern },
 }
 
 impl Ctx<'_> {
   pub fn desugar_match_defs(&mut self) -> Result<(), Diagnostics> {
     self.info.start_pass();
 
+    let mut shared_defs = vec![];
     for (def_name, def) in self.book.defs.iter_mut() {
-      let errs = def.desugar_match_def(&self.book.ctrs, &self.book.adts);
-      for err in errs {
+      let def_span = def.span();
+      let errs = def.desugar_match_def(&self.book.ctrs, &self.book.adts, &mut shared_defs);
+      for (err, span) in errs {
+        let span = span.or_else(|| def_span.clone());
         match err {
-          DesugarMatchDefErr::AdtNotExhaustive { .. }
-          | DesugarMatchDefErr::NumMissingDefault
-          | DesugarMatchDefErr::TypeMismatch { .. } => self.info.add_rule_error(err, def_name.clone()),
+          DesugarMatchDefErr::NotExhaustive { .. } | DesugarMatchDefErr::TypeMismatch { .. } => {
+            self.info.add_rule_error(err, def_name.clone(), span.clone())
+          }
           DesugarMatchDefErr::RepeatedBind { .. } => {
-            self.info.add_rule_warning(err, WarningType::RepeatedBind, def_name.clone())
+            self.info.add_rule_warning(err, WarningType::RepeatedBind, def_name.clone(), span.clone())
+          }
+          DesugarMatchDefErr::RedundantRule { .. } => {
+            self.info.add_rule_warning(err, WarningType::RedundantMatch, def_name.clone(), span.clone())
+          }
+          DesugarMatchDefErr::UnreachableAlternatives { .. } => {
+            self.info.add_rule_warning(err, WarningType::UnreachableMatch, def_name.clone(), span.clone())
+          }
+          DesugarMatchDefErr::RedundantAlternative { .. } => {
+            self.info.add_rule_warning(err, WarningType::RedundantMatch, def_name.clone(), span.clone())
           }
         }
       }
     }
+    self.book.defs.extend(shared_defs.into_iter().map(|def| (def.name.clone(), def)));
 
     self.info.fatal(())
   }
 }
 
 impl Definition {
-  pub fn desugar_match_def(&mut self, ctrs: &Constructors, adts: &Adts) -> Vec<DesugarMatchDefErr> {
+  /// Returns the errors found, with the location of the `match` term they come from, if any.
+  ///
+  /// The definitions generated for the bodies of rules with or-patterns are pushed to `shared_defs`.
+  pub fn desugar_match_def(
+    &mut self,
+    ctrs: &Constructors,
+    adts: &Adts,
+    shared_defs: &mut Vec<Definition>,
+  ) -> Vec<(DesugarMatchDefErr, Option<Span>)> {
+    let mut shared =
+      SharedBodies { def_name: self.name.clone(), builtin: self.builtin, name_gen: 0, defs: shared_defs };
     let mut errs = vec![];
+    for rule in self.rules.iter_mut() {
+      rule.terms_mut().for_each(|term| term.desugar_match_terms(ctrs, adts, &mut shared, &mut errs));
+    }
 
-    let repeated_bind_errs = fix_repeated_binds(&mut self.rules);
-    errs.extend(repeated_bind_errs);
+    errs.extend(self.desugar_match_rules(ctrs, adts, &mut shared));
+    errs
+  }
+
+  fn desugar_match_rules(
+    &mut self,
+    ctrs: &Constructors,
+    adts: &Adts,
+    shared: &mut SharedBodies,
+  ) -> Vec<(DesugarMatchDefErr, Option<Span>)> {
+    let mut errs = vec![];
+    errs.extend(simplify_or_pats(&mut self.rules));
+    if let Err(err) = check_rules(&self.rules, ctrs, adts, &mut errs) {
+      errs.push((err, None));
+      return errs;
+    }
+
+    expand_or_pats(&mut self.rules, shared);
+    errs.extend(fix_repeated_binds(&mut self.rules));
 
     let args = (0 .. self.arity()).map(|i| Name::new(format!("%arg{i}"))).collect::<Vec<_>>();
+    let span = self.span();
     let rules = std::mem::take(&mut self.rules);
     match simplify_rule_match(args.clone(), rules, vec![], ctrs, adts) {
       Ok(body) => {
         let body = args.into_iter().rfold(body, |body, arg| Term::lam(Some(arg), body));
-        self.rules = vec![Rule { pats: vec![], body }];
+        self.rules = vec![Rule { pats: vec![], guard: None, body, loc: span.into() }];
       }
-      Err(e) => errs.push(e),
+      Err(e) => errs.push((e, None)),
     }
     errs
   }
 }
 
+impl Term {
+  /// Compiles the `match` terms with nested patterns or several matched values
+  /// the same way as the rules of a definition, with the matched values as the arguments.
+  ///
+  /// ```hvm
+  /// data List = (Cons h t) | Nil
+  /// match x, y = (Foo z) { (Cons h t), Nil: (A h t); *, *: B }
+  /// // Becomes:
+  /// let y = (Foo z); match x with y { Cons: match y with x.h x.t { Cons: B; Nil: (A x.h x.t) }; Nil: B }
+  /// ```
+  fn desugar_match_terms(
+    &mut self,
+    ctrs: &Constructors,
+    adts: &Adts,
+    shared: &mut SharedBodies,
+    errs: &mut Vec<(DesugarMatchDefErr, Option<Span>)>,
+  ) {
+    maybe_grow(|| {
+      for child in self.children_mut() {
+        child.desugar_match_terms(ctrs, adts, shared, errs);
+      }
+
+      if let Term::Cas { args, bnds, with, arms, loc } = self {
+        let span = loc.span();
+        let mut cas_errs = simplify_or_pats(arms);
+        let checked = check_rules(arms, ctrs, adts, &mut cas_errs);
+        errs.extend(cas_errs.into_iter().map(|(err, err_span)| (err, err_span.or_else(|| span.clone()))));
+        if let Err(err) = checked {
+          errs.push((err, span));
+          return;
+        }
+
+        expand_or_pats(arms, shared);
+        errs.extend(fix_repeated_binds(arms));
+
+        let names = bnds.iter().map(|bnd| bnd.clone().unwrap()).collect::<Vec<_>>();
+        let arms = std::mem::take(arms);
+        match simplify_rule_match(names.clone(), arms, std::mem::take(with), ctrs, adts) {
+          Ok(body) => {
+            let args = std::mem::take(args);
+            *self = names.into_iter().zip(args).rfold(body, |nxt, (nam, arg)| {
+              if matches!(&arg, Term::Var { nam: var, .. } if *var == nam) {
+                // Matching directly on a variable, no need to bind it.
+                nxt
+              } else {
+                Term::Let { nam: Some(nam), val: Box::new(arg), nxt: Box::new(nxt), loc: Loc::default() }
+              }
+            });
+          }
+          Err(err) => errs.push((err, span)),
+        }
+      }
+    })
+  }
+}
+
+/// Removes the alternatives of the or-patterns that can never be matched.
+fn simplify_or_pats(rules: &mut [Rule]) -> Vec<(DesugarMatchDefErr, Option<Span>)> {
+  let mut errs = vec![];
+  for pat in rules.iter_mut().flat_map(|r| &mut r.pats) {
+    pat.simplify_or(&mut errs);
+  }
+  errs
+}
+
+/// Splits the rules with or-patterns into one rule for each combination of alternatives,
+/// keeping the order in which they were written.
+///
+/// The guard and the body of the rule are moved into new definitions,
+/// so that every combination calls them instead of having its own copy.
+/// ```hvm
+/// (Foo (A | B) (C | D) x) = (Bar x)
+/// // Becomes:
+/// (Foo A C x) = (Foo$or0 x)
+/// (Foo A D x) = (Foo$or0 x)
+/// (Foo B C x) = (Foo$or0 x)
+/// (Foo B D x) = (Foo$or0 x)
+/// Foo$or0 = λx (Bar x)
+/// ```
+fn expand_or_pats(rules: &mut Vec<Rule>, shared: &mut SharedBodies) {
+  if !rules.iter().flat_map(|r| &r.pats).any(Pattern::has_or) {
+    return;
+  }
+  for mut rule in std::mem::take(rules) {
+    let expanded = Pattern::expand_or_seq(&rule.pats);
+    if expanded.len() > 1 {
+      rule.guard = rule.guard.map(|guard| shared.share(guard));
+      rule.body = shared.share(rule.body);
+    }
+    for pats in expanded {
+      rules.push(Rule { pats, guard: rule.guard.clone(), body: rule.body.clone(), loc: rule.loc.clone() });
+    }
+  }
+}
+
+/// The definitions generated for the guards and bodies shared by the alternatives of or-patterns.
+struct SharedBodies<'a> {
+  def_name: Name,
+  builtin: bool,
+  name_gen: usize,
+  defs: &'a mut Vec<Definition>,
+}
+
+impl SharedBodies<'_> {
+  /// Moves a term into a new definition that takes its free variables, returning a call to it.
+  ///
+  /// Terms with unscoped variables are kept as they are, since they can't be moved to another definition.
+  fn share(&mut self, term: Term) -> Term {
+    let (unscoped_decls, unscoped_uses) = term.unscoped_vars();
+    if !unscoped_decls.is_empty() || !unscoped_uses.is_empty() {
+      return term;
+    }
+    let free_vars = term.free_vars().into_keys().collect::<BTreeSet<_>>();
+    let name = Name::new(format!("{}$or{}", self.def_name, self.name_gen));
+    self.name_gen += 1;
+    let body = free_vars.iter().rfold(term, |bod, var| Term::lam(Some(var.clone()), bod));
+    let rules = vec![Rule { pats: vec![], guard: None, body, loc: Loc::default() }];
+    self.defs.push(Definition { name: name.clone(), rules, builtin: self.builtin });
+    Term::call(Term::Ref { nam: name, loc: Loc::default() }, free_vars.into_iter().map(Term::var))
+  }
+}
+
+/// Checks that the rules match every possible value and that each of them
+/// matches some value that isn't already matched by the rules before it.
+///
+/// Rules with guards are not counted as matching anything, since their guard can be false.
+///
+/// Returns an error with the patterns of the values that no rule matches, if there are any,
+/// and pushes a warning located at each rule that can never be reached.
+fn check_rules(
+  rules: &[Rule],
+  ctrs: &Constructors,
+  adts: &Adts,
+  errs: &mut Vec<(DesugarMatchDefErr, Option<Span>)>,
+) -> Result<(), DesugarMatchDefErr> {
+  let unguarded =
+    |rules: &[Rule]| rules.iter().filter(|r| r.guard.is_none()).map(|r| r.pats.clone()).collect::<Vec<_>>();
+
+  for (i, rule) in rules.iter().enumerate() {
+    if !is_useful(unguarded(&rules[.. i]), rule.pats.clone(), ctrs, adts) {
+      // Look for a single rule that already matches everything this one does.
+      let shadowed_by = rules[.. i]
+        .iter()
+        .enumerate()
+        .find(|(_, prev)| {
+          prev.guard.is_none() && !is_useful(vec![prev.pats.clone()], rule.pats.clone(), ctrs, adts)
+        })
+        .map(|(j, prev)| (j, prev.pats.clone()));
+      let err = DesugarMatchDefErr::RedundantRule { idx: i, rule: rule.pats.clone(), shadowed_by };
+      errs.push((err, rule.loc.span()));
+    }
+  }
+
+  let arity = rules.first().map_or(0, Rule::arity);
+  let missing = missing_pats(unguarded(rules), arity, ctrs, adts)?;
+  if missing.is_empty() {
+    Ok(())
+  } else {
+    let has_guards = rules.iter().any(|r| r.guard.is_some());
+    Err(DesugarMatchDefErr::NotExhaustive { missing, has_guards })
+  }
+}
+
+/// Finds the values that none of the rows of patterns match,
+/// specializing the rows for each case of the first column like [`simplify_rule_match`] does.
+///
+/// The values are returned as rows of patterns, using wildcards where any value is missing.
+/// ```hvm
+/// (Foo List.nil)
+/// (Foo (List.cons * List.nil))
+/// // Doesn't match:
+/// (Foo (List.cons * (List.cons * *)))
+/// ```
+///
+/// When the first column doesn't have every case of its type, like a number or only some constructors,
+/// the values are only searched for in the rows that start with a wildcard and are shown with a `*`,
+/// so that the number of rows returned doesn't grow with each column.
+fn missing_pats(
+  rows: Vec<Vec<Pattern>>,
+  arity: usize,
+  ctrs: &Constructors,
+  adts: &Adts,
+) -> Result<Vec<Vec<Pattern>>, DesugarMatchDefErr> {
+  if rows.is_empty() {
+    return Ok(vec![vec![Pattern::Var(None, Loc::default()); arity]]);
+  }
+  if arity == 0 {
+    return Ok(vec![]);
+  }
+
+  let rows = expand_fst_or(rows);
+  let typ = Type::infer_from_pats(rows.iter().map(|row| &row[0]), ctrs)?;
+  // The cases of the first column, if all of them are matched.
+  let heads = match typ {
+    Type::Tup(len) => Some(vec![Head::Tup(len)]),
+    Type::Adt(adt) => {
+      let heads = Head::ctrs_of(&adt, adts);
+      let complete = heads.iter().all(|head| rows.iter().any(|row| Head::of(&row[0]).as_ref() == Some(head)));
+      complete.then_some(heads)
+    }
+    Type::Any | Type::Num => None,
+  };
+
+  let mut missing = vec![];
+  if let Some(heads) = heads {
+    for head in heads {
+      let sub_missing = missing_pats(specialize(&rows, &head), head.arity() + arity - 1, ctrs, adts)?;
+      missing.extend(sub_missing.into_iter().map(|row| head.rebuild(row)));
+    }
+  } else {
+    let sub_missing = missing_pats(default_rows(&rows), arity - 1, ctrs, adts)?;
+    missing.extend(
+      sub_missing
+        .into_iter()
+        .map(|row| [Pattern::Var(None, Loc::default())].into_iter().chain(row).collect()),
+    );
+  }
+  Ok(missing)
+}
+
+/// Checks if a row of patterns matches some value that none of the other rows match.
+fn is_useful(rows: Vec<Vec<Pattern>>, row: Vec<Pattern>, ctrs: &Constructors, adts: &Adts) -> bool {
+  let Some(fst) = row.first() else { return rows.is_empty() };
+  if let Pattern::Or(alts, _) = fst {
+    return alts.iter().any(|alt| {
+      let row = [alt.clone()].into_iter().chain(row[1 ..].iter().cloned()).collect();
+      is_useful(rows.clone(), row, ctrs, adts)
+    });
+  }
+
+  let rows = expand_fst_or(rows);
+  let Ok(typ) = Type::infer_from_pats(rows.iter().map(|row| &row[0]).chain([fst]), ctrs) else {
+    // Type errors are reported when looking for the missing cases.
+    return true;
+  };
+  let heads = match (Head::of(fst), typ) {
+    (Some(head), _) => vec![head],
+    (None, Type::Tup(len)) => vec![Head::Tup(len)],
+    (None, Type::Adt(adt)) => {
+      // A variable only needs to be checked against each constructor if all of them are matched,
+      // otherwise it's useful for the missing ones if the rest of the row is useful for the default rows.
+      let heads = Head::ctrs_of(&adt, adts);
+      if !heads.iter().all(|head| rows.iter().any(|row| Head::of(&row[0]).as_ref() == Some(head))) {
+        return is_useful(default_rows(&rows), row[1 ..].to_vec(), ctrs, adts);
+      }
+      heads
+    }
+    (None, Type::Any | Type::Num) => return is_useful(default_rows(&rows), row[1 ..].to_vec(), ctrs, adts),
+  };
+  heads.iter().any(|head| {
+    let row = specialize(&[row.clone()], head).pop().unwrap();
+    is_useful(specialize(&rows, head), row, ctrs, adts)
+  })
+}
+
+/// Splits the rows that start with an or-pattern into one row for each alternative.
+fn expand_fst_or(rows: Vec<Vec<Pattern>>) -> Vec<Vec<Pattern>> {
+  rows
+    .into_iter()
+    .flat_map(|row| match &row[0] {
+      Pattern::Or(alts, _) => {
+        let rows =
+          alts.iter().map(|alt| [alt.clone()].into_iter().chain(row[1 ..].iter().cloned()).collect());
+        expand_fst_or(rows.collect())
+      }
+      _ => vec![row],
+    })
+    .collect()
+}
+
+/// Keeps the rows that can match the given case, replacing their first pattern with its subpatterns.
+fn specialize(rows: &[Vec<Pattern>], head: &Head) -> Vec<Vec<Pattern>> {
+  let mut new_rows = vec![];
+  for row in rows {
+    let sub_pats = match (&row[0], head) {
+      (Pattern::Var(..), _) => vec![Pattern::Var(None, Loc::default()); head.arity()],
+      (Pattern::Ctr(nam, pats, _), Head::Ctr(ctr, _)) if nam == ctr => pats.clone(),
+      (Pattern::Num(n, _), Head::Num(m)) if n == m => vec![],
+      (Pattern::Tup(pats, _), Head::Tup(_)) => pats.clone(),
+      _ => continue,
+    };
+    new_rows.push(sub_pats.into_iter().chain(row[1 ..].iter().cloned()).collect());
+  }
+  new_rows
+}
+
+/// Keeps the rows that start with a variable, without it.
+fn default_rows(rows: &[Vec<Pattern>]) -> Vec<Vec<Pattern>> {
+  rows.iter().filter(|row| row[0].is_wildcard()).map(|row| row[1 ..].to_vec()).collect()
+}
+
+/// The top level of a pattern, without its subpatterns.
+#[derive(PartialEq, Eq)]
+enum Head {
+  Ctr(Name, usize),
+  Num(u64),
+  Tup(usize),
+}
+
+impl Head {
+  fn of(pat: &Pattern) -> Option<Head> {
+    match pat {
+      Pattern::Var(..) => None,
+      Pattern::Ctr(nam, pats, _) => Some(Head::Ctr(nam.clone(), pats.len())),
+      Pattern::Num(n, _) => Some(Head::Num(*n)),
+      Pattern::Tup(pats, _) => Some(Head::Tup(pats.len())),
+      Pattern::Lst(..) | Pattern::Str(..) | Pattern::Or(..) => unreachable!(),
+    }
+  }
+
+  fn ctrs_of(adt: &Name, adts: &Adts) -> Vec<Head> {
+    adts[adt].ctrs.iter().map(|(ctr, fields)| Head::Ctr(ctr.clone(), fields.len())).collect()
+  }
+
+  fn arity(&self) -> usize {
+    match self {
+      Head::Ctr(_, arity) | Head::Tup(arity) => *arity,
+      Head::Num(_) => 0,
+    }
+  }
+
+  /// Turns the first patterns of a row back into a pattern with this head.
+  fn rebuild(&self, mut row: Vec<Pattern>) -> Vec<Pattern> {
+    let rest = row.split_off(self.arity());
+    let pat = match self {
+      Head::Ctr(nam, _) => Pattern::Ctr(nam.clone(), row, Loc::default()),
+      Head::Num(n) => Pattern::Num(*n, Loc::default()),
+      Head::Tup(_) => Pattern::Tup(row, Loc::default()),
+    };
+    [pat].into_iter().chain(rest).collect()
+  }
+}
+
 /// When a rule has repeated bind, the only one that is actually useful is the last one.
 ///
 /// Example: In `(Foo x x x x) = x`, the function should return the fourth argument.
@@ -63,7 +447,7 @@ impl Definition {
 /// // After this transformation, becomes:
 /// (Foo * (Succ *) (Cons a)) = (a a)
 /// ```
-fn fix_repeated_binds(rules: &mut [Rule]) -> Vec<DesugarMatchDefErr> {
+fn fix_repeated_binds(rules: &mut [Rule]) -> Vec<(DesugarMatchDefErr, Option<Span>)> {
   let mut errs = vec![];
   for rule in rules {
     let mut binds = HashSet::new();
@@ -71,7 +455,7 @@ fn fix_repeated_binds(rules: &mut [Rule]) -> Vec<DesugarMatchDefErr> {
       if binds.contains(nam) {
         // Repeated bind, not reachable and can be erased.
         if let Some(nam) = nam {
-          errs.push(DesugarMatchDefErr::RepeatedBind { bind: nam.clone() });
+          errs.push((DesugarMatchDefErr::RepeatedBind { bind: nam.clone() }, rule.loc.span()));
         }
         *nam = None;
         // TODO: Send a repeated bind warning
@@ -91,7 +475,7 @@ fn fix_repeated_binds(rules: &mut [Rule]) -> Vec<DesugarMatchDefErr> {
 ///
 /// If there are constructors of different types for the same arg, returns a type error.
 ///
-/// If no patterns match one of the constructors, returns a non-exhaustive match error.
+/// The rules must have already been checked to be exhaustive with [`check_rules`].
 ///
 /// Any nested subpatterns are extracted and moved into a nested match
 /// expression, together with the remaining match arguments.
@@ -104,10 +488,13 @@ fn simplify_rule_match(
   ctrs: &Constructors,
   adts: &Adts,
 ) -> Result<Term, DesugarMatchDefErr> {
-  if args.is_empty() {
-    Ok(rules.into_iter().next().unwrap().body)
-  } else if rules[0].pats.iter().all(|p| p.is_wildcard()) {
-    Ok(irrefutable_fst_row_rule(args, rules.into_iter().next().unwrap()))
+  let Some(fst_rule) = rules.first() else {
+    // No rule matches any value.
+    let missing = vec![vec![Pattern::Var(None, Loc::default()); args.len()]];
+    return Err(DesugarMatchDefErr::NotExhaustive { missing, has_guards: false });
+  };
+  if fst_rule.pats.iter().all(|p| p.is_wildcard()) {
+    irrefutable_fst_row_rule(args, rules, with, ctrs, adts)
   } else {
     let typ = Type::infer_from_def_arg(&rules, 0, ctrs)?;
     match typ {
@@ -122,15 +509,40 @@ fn simplify_rule_match(
 /// Irrefutable first row rule.
 /// Short-circuits the encoding in case the first rule always matches.
 /// This is useful to avoid unnecessary pattern matching.
-fn irrefutable_fst_row_rule(args: Vec<Name>, rule: Rule) -> Term {
-  let mut term = rule.body;
-  for (arg, pat) in args.into_iter().zip(rule.pats.into_iter()) {
-    let Pattern::Var(var) = pat else { unreachable!() };
+///
+/// If the rule has a guard, it's checked with a switch,
+/// falling back to the remaining rules when it's false.
+/// ```hvm
+/// case x0 ... xN { p0 ... pN if cond: body; rest }
+/// // Becomes:
+/// switch %guard = cond { 0: case x0 ... xN { rest }; _: body }
+/// ```
+fn irrefutable_fst_row_rule(
+  args: Vec<Name>,
+  mut rules: Vec<Rule>,
+  with: Vec<Name>,
+  ctrs: &Constructors,
+  adts: &Adts,
+) -> Result<Term, DesugarMatchDefErr> {
+  let mut rule = rules.remove(0);
+  for (arg, pat) in args.iter().zip(std::mem::take(&mut rule.pats)) {
+    let Pattern::Var(var, _) = pat else { unreachable!() };
     if let Some(var) = var {
-      term.subst(&var, &Term::Var { nam: arg });
+      rule.subst(&var, &Term::var(arg.clone()));
     }
   }
-  term
+  let Some(guard) = rule.guard else { return Ok(rule.body) };
+
+  let fallback = simplify_rule_match(args.clone(), rules, with.clone(), ctrs, adts)?;
+  let term = Term::Swt {
+    arg: Box::new(guard),
+    bnd: Some(Name::new("%guard")),
+    with: with.into_iter().chain(args).collect(),
+    pred: Some(Name::new("%guard-1")),
+    arms: vec![fallback, rule.body],
+    loc: Loc::default(),
+  };
+  Ok(term)
 }
 
 /// Var rule.
@@ -152,11 +564,11 @@ fn var_rule(
     let new_pats = rule.pats.split_off(1);
     let pat = rule.pats.pop().unwrap();
 
-    if let Pattern::Var(Some(nam)) = &pat {
-      rule.body.subst(nam, &Term::Var { nam: arg.clone() });
+    if let Pattern::Var(Some(nam), _) = &pat {
+      rule.subst(nam, &Term::var(arg.clone()));
     }
 
-    let new_rule = Rule { pats: new_pats, body: rule.body };
+    let new_rule = Rule { pats: new_pats, guard: rule.guard, body: rule.body, loc: rule.loc };
     new_rules.push(new_rule);
   }
 
@@ -199,27 +611,27 @@ fn tup_rule(
 
     // Extract subpatterns from the tuple pattern
     let mut new_pats = match pat {
-      Pattern::Tup(sub_pats) => sub_pats,
-      Pattern::Var(var) => {
+      Pattern::Tup(sub_pats, _) => sub_pats,
+      Pattern::Var(var, _) => {
         if let Some(var) = var {
           // Rebuild the tuple if it was a var pattern
-          let tup = Term::Tup { els: new_args.clone().map(|nam| Term::Var { nam }).collect() };
-          rule.body.subst(&var, &tup);
+          let tup = Term::Tup { els: new_args.clone().map(Term::var).collect(), loc: Loc::default() };
+          rule.subst(&var, &tup);
         }
-        new_args.clone().map(|nam| Pattern::Var(Some(nam))).collect()
+        new_args.clone().map(|nam| Pattern::Var(Some(nam), Loc::default())).collect()
       }
       _ => unreachable!(),
     };
     new_pats.extend(old_pats);
 
-    let new_rule = Rule { pats: new_pats, body: rule.body };
+    let new_rule = Rule { pats: new_pats, guard: rule.guard, body: rule.body, loc: rule.loc };
     new_rules.push(new_rule);
   }
 
   let bnd = new_args.clone().map(Some).collect();
   let args = new_args.chain(old_args).collect();
   let nxt = simplify_rule_match(args, new_rules, with, ctrs, adts)?;
-  let term = Term::Ltp { bnd, val: Box::new(Term::Var { nam: arg }), nxt: Box::new(nxt) };
+  let term = Term::Ltp { bnd, val: Box::new(Term::var(arg)), nxt: Box::new(nxt), loc: Loc::default() };
 
   Ok(term)
 }
@@ -231,11 +643,6 @@ fn num_rule(
   ctrs: &Constructors,
   adts: &Adts,
 ) -> Result<Term, DesugarMatchDefErr> {
-  // Number match must always have a default case
-  if !rules.iter().any(|r| r.pats[0].is_wildcard()) {
-    return Err(DesugarMatchDefErr::NumMissingDefault);
-  }
-
   let arg = args[0].clone();
   let args = args.split_off(1);
 
@@ -245,7 +652,7 @@ fn num_rule(
   // We first iterate over each present number then get the default.
   let nums = rules
     .iter()
-    .filter_map(|r| if let Pattern::Num(n) = r.pats[0] { Some(n) } else { None })
+    .filter_map(|r| if let Pattern::Num(n, _) = r.pats[0] { Some(n) } else { None })
     .collect::<BTreeSet<_>>()
     .into_iter()
     .collect::<Vec<_>>();
@@ -256,17 +663,17 @@ fn num_rule(
     let mut new_rules = vec![];
     for rule in rules.iter() {
       match &rule.pats[0] {
-        Pattern::Num(n) if n == num => {
-          let body = rule.body.clone();
-          let rule = Rule { pats: rule.pats[1 ..].to_vec(), body };
+        Pattern::Num(n, _) if n == num => {
+          let mut rule = rule.clone();
+          rule.pats.remove(0);
           new_rules.push(rule);
         }
-        Pattern::Var(var) => {
-          let mut body = rule.body.clone();
+        Pattern::Var(var, _) => {
+          let mut rule = rule.clone();
+          rule.pats.remove(0);
           if let Some(var) = var {
-            body.subst(var, &Term::Num { val: *num });
+            rule.subst(var, &Term::num(*num));
           }
-          let rule = Rule { pats: rule.pats[1 ..].to_vec(), body };
           new_rules.push(rule);
         }
         _ => (),
@@ -278,15 +685,13 @@ fn num_rule(
 
   // Default case
   let mut new_rules = vec![];
-  for rule in rules {
-    if let Pattern::Var(var) = &rule.pats[0] {
-      let mut body = rule.body.clone();
+  for mut rule in rules {
+    if let Pattern::Var(var, _) = rule.pats.remove(0) {
       if let Some(var) = var {
         let last_num = *nums.last().unwrap();
-        let var_recovered = Term::add_num(Term::Var { nam: pred_var.clone() }, 1 + last_num);
-        body.subst(var, &var_recovered);
+        let var_recovered = Term::add_num(Term::var(pred_var.clone()), 1 + last_num);
+        rule.subst(&var, &var_recovered);
       }
-      let rule = Rule { pats: rule.pats[1 ..].to_vec(), body };
       new_rules.push(rule);
     }
   }
@@ -301,10 +706,10 @@ fn num_rule(
     let val = if i > 0 {
       //  switch arg = (pred +1 +num_i-1 - num_i) { 0: body_i; _: acc }
       // nums[i] >= nums[i-1]+1, so we do a sub here.
-      Term::sub_num(Term::Var { nam: pred_var.clone() }, nums[i] - 1 - nums[i - 1])
+      Term::sub_num(Term::var(pred_var.clone()), nums[i] - 1 - nums[i - 1])
     } else {
       //  switch arg = (arg -num_0) { 0: body_0; _: acc}
-      Term::sub_num(Term::Var { nam: arg.clone() }, nums[i])
+      Term::sub_num(Term::var(arg.clone()), nums[i])
     };
 
     Term::Swt {
@@ -313,6 +718,7 @@ fn num_rule(
       with: swt_with.clone(),
       pred: Some(pred_var.clone()),
       arms: vec![body, term],
+      loc: Loc::default(),
     }
   });
 
@@ -393,37 +799,30 @@ fn switch_rule(
         // (Ctr pat0_0 ... pat0_m) pat1 ... patN: body
         // becomes
         // pat0_0 ... pat0_m pat1 ... patN: body
-        Pattern::Ctr(found_ctr, new_pats) if ctr == found_ctr => {
+        Pattern::Ctr(found_ctr, new_pats, _) if ctr == found_ctr => {
           let pats = new_pats.iter().cloned().chain(old_pats).collect();
-          let body = rule.body.clone();
-          let rule = Rule { pats, body };
-          new_rules.push(rule);
+          new_rules.push(Rule { pats, ..rule.clone() });
         }
         // Var, match and rebuild the constructor.
         // var pat1 ... patN: body
         // becomes
         // arg0.field0 ... arg0.fieldM pat1 ... patN:
         //   use var = (Ctr arg0.field0 ... arg0.fieldM); body
-        Pattern::Var(var) => {
-          let new_pats = new_args.clone().map(|n| Pattern::Var(Some(n)));
+        Pattern::Var(var, _) => {
+          let new_pats = new_args.clone().map(|n| Pattern::Var(Some(n), Loc::default()));
           let pats = new_pats.chain(old_pats.clone()).collect();
-          let mut body = rule.body.clone();
+          let mut rule = Rule { pats, ..rule.clone() };
           let reconstructed_var =
-            Term::call(Term::Ref { nam: ctr.clone() }, new_args.clone().map(|nam| Term::Var { nam }));
+            Term::call(Term::Ref { nam: ctr.clone(), loc: Loc::default() }, new_args.clone().map(Term::var));
           if let Some(var) = var {
-            body.subst(var, &reconstructed_var);
+            rule.subst(var, &reconstructed_var);
           }
-          let rule = Rule { pats, body };
           new_rules.push(rule);
         }
         _ => (),
       }
     }
 
-    if new_rules.is_empty() {
-      return Err(DesugarMatchDefErr::AdtNotExhaustive { adt: adt_name, ctr: ctr.clone() });
-    }
-
     let body = simplify_rule_match(args, new_rules, with.clone(), ctrs, adts)?;
     new_arms.push((Some(ctr.clone()), new_args.map(Some).collect(), body));
   }
@@ -432,10 +831,11 @@ fn switch_rule(
   let mat_with = with.into_iter().chain(old_args).collect::<Vec<_>>();
 
   let term = Term::Mat {
-    arg: Box::new(Term::Var { nam: arg.clone() }),
+    arg: Box::new(Term::var(arg.clone())),
     bnd: Some(arg.clone()),
     with: mat_with,
     arms: new_arms,
+    loc: Loc::default(),
   };
   Ok(term)
 }
@@ -460,7 +860,13 @@ impl Type {
     arg_idx: usize,
     ctrs: &Constructors,
   ) -> Result<Type, DesugarMatchDefErr> {
-    let pats = rules.iter().map(|r| &r.pats[arg_idx]);
+    Type::infer_from_pats(rules.iter().map(|r| &r.pats[arg_idx]), ctrs)
+  }
+
+  fn infer_from_pats<'a>(
+    pats: impl IntoIterator<Item = &'a Pattern>,
+    ctrs: &Constructors,
+  ) -> Result<Type, DesugarMatchDefErr> {
     let mut arg_type = Type::Any;
     for pat in pats {
       arg_type = match (arg_type, pat.to_type(ctrs)) {
@@ -485,17 +891,90 @@ impl Type {
 impl Pattern {
   fn to_type(&self, ctrs: &Constructors) -> Type {
     match self {
-      Pattern::Var(_) => Type::Any,
-      Pattern::Ctr(ctr_nam, _) => {
+      Pattern::Var(..) => Type::Any,
+      Pattern::Ctr(ctr_nam, _, _) => {
         let adt_nam = ctrs.get(ctr_nam).expect("Unknown constructor '{ctr_nam}'");
         Type::Adt(adt_nam.clone())
       }
-      Pattern::Tup(args) => Type::Tup(args.len()),
-      Pattern::Num(_) => Type::Num,
+      Pattern::Tup(args, _) => Type::Tup(args.len()),
+      Pattern::Num(..) => Type::Num,
       Pattern::Lst(..) => Type::Adt(Name::new(builtins::LIST)),
       Pattern::Str(..) => Type::Adt(Name::new(builtins::STRING)),
+      Pattern::Or(..) => unreachable!("Or-patterns should've been expanded already"),
+    }
+  }
+
+  fn has_or(&self) -> bool {
+    matches!(self, Pattern::Or(..)) || self.children().any(Pattern::has_or)
+  }
+
+  /// Removes the alternatives of the or-patterns that can never be matched.
+  fn simplify_or(&mut self, errs: &mut Vec<(DesugarMatchDefErr, Option<Span>)>) {
+    for child in self.children_mut() {
+      child.simplify_or(errs);
+    }
+    if let Pattern::Or(alts, _) = self {
+      if let Some(idx) = alts.iter().position(Pattern::is_wildcard)
+        && idx + 1 < alts.len()
+      {
+        let pat = alts[idx].clone();
+        errs.push((DesugarMatchDefErr::UnreachableAlternatives { pat: pat.clone() }, pat.loc().span()));
+        alts.truncate(idx + 1);
+      }
+      let mut seen = vec![];
+      alts.retain(|alt| {
+        if seen.contains(alt) {
+          errs.push((DesugarMatchDefErr::RedundantAlternative { pat: alt.clone() }, alt.loc().span()));
+          false
+        } else {
+          seen.push(alt.clone());
+          true
+        }
+      });
+    }
+  }
+
+  /// Returns the patterns without or-patterns that together match the same values as this one.
+  fn expand_or(&self) -> Vec<Pattern> {
+    match self {
+      Pattern::Or(alts, _) => alts.iter().flat_map(Pattern::expand_or).collect(),
+      Pattern::Ctr(nam, args, loc) => Pattern::expand_or_seq(args)
+        .into_iter()
+        .map(|args| Pattern::Ctr(nam.clone(), args, loc.clone()))
+        .collect(),
+      Pattern::Tup(els, loc) => {
+        Pattern::expand_or_seq(els).into_iter().map(|els| Pattern::Tup(els, loc.clone())).collect()
+      }
+      Pattern::Lst(els, loc) => {
+        Pattern::expand_or_seq(els).into_iter().map(|els| Pattern::Lst(els, loc.clone())).collect()
+      }
+      Pattern::Var(..) | Pattern::Num(..) | Pattern::Str(..) => vec![self.clone()],
     }
   }
+
+  /// Every combination of the expanded alternatives of a sequence of patterns, in order.
+  fn expand_or_seq(pats: &[Pattern]) -> Vec<Vec<Pattern>> {
+    pats.iter().fold(vec![vec![]], |seqs, pat| {
+      let alts = pat.expand_or();
+      seqs
+        .into_iter()
+        .flat_map(|seq| {
+          alts.iter().map(move |alt| {
+            let mut seq = seq.clone();
+            seq.push(alt.clone());
+            seq
+          })
+        })
+        .collect()
+    })
+  }
+}
+
+impl Rule {
+  /// Substitutes the variable in both the guard and the body of the rule.
+  fn subst(&mut self, from: &Name, to: &Term) {
+    self.terms_mut().for_each(|term| term.subst(from, to));
+  }
 }
 
 impl std::fmt::Display for Type {
@@ -512,8 +991,19 @@ impl std::fmt::Display for Type {
 impl ToStringVerbose for DesugarMatchDefErr {
   fn to_string_verbose(&self, _verbose: bool) -> String {
     match self {
-      DesugarMatchDefErr::AdtNotExhaustive { adt, ctr } => {
-        format!("Non-exhaustive pattern matching rule. Constructor '{ctr}' of type '{adt}' not covered")
+      DesugarMatchDefErr::NotExhaustive { missing, has_guards } => {
+        let mut msg = format!(
+          "Non-exhaustive pattern matching rule. Cases not covered: {}",
+          missing.iter().take(MAX_MISSING_SHOWN).map(|pats| format!("'{}'", display_pats(pats))).join(", ")
+        );
+        if missing.len() > MAX_MISSING_SHOWN {
+          msg.push_str(&format!(" and {} more", missing.len() - MAX_MISSING_SHOWN));
+        }
+        msg.push('.');
+        if *has_guards {
+          msg.push_str(" Rules with guards are not counted, since their guard can be false.");
+        }
+        msg
       }
       DesugarMatchDefErr::TypeMismatch { expected, found, pat } => {
         format!(
@@ -521,12 +1011,45 @@ impl ToStringVerbose for DesugarMatchDefErr {
           expected, pat, found
         )
       }
-      DesugarMatchDefErr::NumMissingDefault => {
-        "Non-exhaustive pattern matching rule. Default case of number type not covered.".to_string()
-      }
       DesugarMatchDefErr::RepeatedBind { bind } => {
         format!("Repeated bind in pattern matching rule: '{bind}'.")
       }
+      DesugarMatchDefErr::RedundantRule { idx, rule, shadowed_by } => {
+        let rule = display_pats(rule);
+        match shadowed_by {
+          Some((prev_idx, prev)) => format!(
+            "Redundant pattern matching rule. Rule {} '{rule}' is already covered by rule {} '{}'.",
+            idx + 1,
+            prev_idx + 1,
+            display_pats(prev)
+          ),
+          None => format!(
+            "Redundant pattern matching rule. Rule {} '{rule}' is already covered by the rules before it.",
+            idx + 1
+          ),
+        }
+      }
+      DesugarMatchDefErr::UnreachableAlternatives { pat } => {
+        format!("Unreachable alternatives in or-pattern. All alternatives after '{pat}' will be ignored.")
+      }
+      DesugarMatchDefErr::RedundantAlternative { pat } => {
+        format!("Redundant alternative in or-pattern. '{pat}' appears more than once.")
+      }
     }
   }
 }
+
+/// Displays the patterns of a rule in an error message,
+/// without parentheses around the constructors that have no fields.
+fn display_pats(pats: &[Pattern]) -> impl std::fmt::Display + '_ {
+  fn display_pat(pat: &Pattern) -> impl std::fmt::Display + '_ {
+    DisplayFn(move |f| match pat {
+      Pattern::Ctr(nam, pats, _) if pats.is_empty() => write!(f, "{nam}"),
+      Pattern::Ctr(nam, pats, _) => write!(f, "({nam} {})", pats.iter().map(display_pat).join(" ")),
+      Pattern::Tup(pats, _) => write!(f, "({})", pats.iter().map(display_pat).join(", ")),
+      Pattern::Or(pats, _) => write!(f, "({})", pats.iter().map(display_pat).join(" | ")),
+      _ => write!(f, "{pat}"),
+    })
+  }
+  DisplayFn(move |f| write!(f, "{}", pats.iter().map(display_pat).join(" ")))
+}
@@ -1,4 +1,7 @@
-use crate::term::{AdtEncoding, Book, Definition, Name, Rule, Tag, Term};
+use crate::{
+  diagnostics::Loc,
+  term::{AdtEncoding, Book, Definition, Name, Rule, Tag, Term},
+};
 
 impl Book {
   /// Defines a function for each constructor in each ADT in the book.
@@ -10,7 +13,7 @@ impl Book {
 
         let body = encode_ctr(adt_name, args.clone(), ctrs, ctr_name, adt_encoding);
 
-        let rules = vec![Rule { pats: vec![], body }];
+        let rules = vec![Rule { pats: vec![], guard: None, body, loc: Loc::default() }];
         let def = Definition { name: ctr_name.clone(), rules, builtin: adt.builtin };
         defs.push((ctr_name.clone(), def));
       }
@@ -29,21 +32,34 @@ fn encode_ctr(
   match adt_encoding {
     // λarg1 λarg2 λarg3 λctr1 λctr2 (ctr2 arg1 arg2 arg3)
     AdtEncoding::Scott => {
-      let ctr = Term::Var { nam: ctr_name.clone() };
+      let ctr = Term::var(ctr_name.clone());
       let app = ctr_args.iter().cloned().fold(ctr, Term::arg_call);
       let lam = ctrs.into_iter().rfold(app, |acc, arg| Term::named_lam(arg, acc));
       ctr_args.into_iter().rfold(lam, |acc, arg| Term::named_lam(arg, acc))
     }
     // λarg1 λarg2 #type λctr1 #type λctr2 #type.ctr2.arg2(#type.ctr2.arg1(ctr2 arg1) arg2)
     AdtEncoding::TaggedScott => {
-      let ctr = Term::Var { nam: ctr_name.clone() };
+      let ctr = Term::var(ctr_name.clone());
       let app = ctr_args
         .iter()
         .cloned()
-        .fold(ctr, |acc, nam| Term::tagged_app(Tag::adt_name(adt_name), acc, Term::Var { nam }));
+        .fold(ctr, |acc, nam| Term::tagged_app(Tag::adt_name(adt_name), acc, Term::var(nam)));
       let lam =
         ctrs.into_iter().rfold(app, |acc, arg| Term::tagged_lam(Tag::adt_name(adt_name), Some(arg), acc));
       ctr_args.into_iter().rfold(lam, |acc, arg| Term::named_lam(arg, acc))
     }
+    // λarg1 λarg2 #type{ctr_index #type{arg1 arg2}}
+    AdtEncoding::NumTagged => {
+      let tag = Tag::adt_name(adt_name);
+      let idx = ctrs.iter().position(|ctr| ctr == ctr_name).unwrap();
+      let fields = ctr_args.iter().map(|nam| Term::var(nam.clone())).collect::<Vec<_>>();
+      let payload = match fields.len() {
+        0 => Term::era(),
+        1 => fields.into_iter().next().unwrap(),
+        _ => Term::Sup { tag: tag.clone(), els: fields, loc: Loc::default() },
+      };
+      let sup = Term::Sup { tag, els: vec![Term::num(idx as u64), payload], loc: Loc::default() };
+      ctr_args.into_iter().rfold(sup, |acc, arg| Term::named_lam(arg, acc))
+    }
   }
 }
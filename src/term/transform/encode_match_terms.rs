@@ -1,4 +1,5 @@
 use crate::{
+  diagnostics::Loc,
   maybe_grow,
   term::{AdtEncoding, Adts, Book, Constructors, MatchRule, Name, Tag, Term},
 };
@@ -28,13 +29,13 @@ impl Term {
         child.encode_matches(ctrs, adts, adt_encoding)
       }
 
-      if let Term::Mat { arg, bnd, with, arms: rules } = self {
+      if let Term::Mat { arg, bnd, with, arms: rules, loc: _ } = self {
         assert!(with.is_empty());
         let bnd = std::mem::take(bnd).unwrap();
         let arg = std::mem::take(arg.as_mut());
         let rules = std::mem::take(rules);
         *self = encode_match(bnd, arg, rules, ctrs, adts, adt_encoding);
-      } else if let Term::Swt { arg, bnd, with, pred, arms: rules } = self {
+      } else if let Term::Swt { arg, bnd, with, pred, arms: rules, .. } = self {
         assert!(with.is_empty());
         let bnd = std::mem::take(bnd).unwrap();
         let arg = std::mem::take(arg.as_mut());
@@ -59,10 +60,14 @@ fn encode_match(
   // Add a `use` term reconstructing the matched variable to each arm.
   for rule in rules.iter_mut() {
     let ctr = rule.0.clone().unwrap();
-    let fields = adts[adt_nam].ctrs[&ctr].iter().map(|f| Term::Var { nam: Name::new(format!("{bnd}.{f}")) });
-    let orig = Term::call(Term::Ref { nam: ctr }, fields);
-    rule.2 =
-      Term::Use { nam: Some(bnd.clone()), val: Box::new(orig), nxt: Box::new(std::mem::take(&mut rule.2)) };
+    let fields = adts[adt_nam].ctrs[&ctr].iter().map(|f| Term::var(Name::new(format!("{bnd}.{f}"))));
+    let orig = Term::call(Term::Ref { nam: ctr, loc: Loc::default() }, fields);
+    rule.2 = Term::Use {
+      nam: Some(bnd.clone()),
+      val: Box::new(orig),
+      nxt: Box::new(std::mem::take(&mut rule.2)),
+      loc: Loc::default(),
+    };
   }
 
   // ADT Encoding depends on compiler option
@@ -86,6 +91,44 @@ fn encode_match(
       }
       Term::tagged_call(Tag::adt_name(adt_nam), arg, arms)
     }
+    // let #adt_name{%tag %fields} = x; (switch %tag { 0: arm[0]; 1: arm[1]; _: arm[2] } %fields)
+    AdtEncoding::NumTagged => {
+      let tag = Tag::adt_name(adt_nam);
+      let tag_var = Name::new("%tag");
+      let fields_var = Name::new("%fields");
+
+      // Each arm receives the fields of its constructor in the same way they are stored.
+      let mut arms = vec![];
+      for rule in rules {
+        let mut fields = rule.1;
+        let arm = match fields.len() {
+          0 => Term::lam(None, rule.2),
+          1 => Term::lam(fields.pop().unwrap(), rule.2),
+          _ => {
+            let val = Box::new(Term::var(fields_var.clone()));
+            let dup =
+              Term::Dup { tag: tag.clone(), bnd: fields, val, nxt: Box::new(rule.2), loc: Loc::default() };
+            Term::named_lam(fields_var.clone(), dup)
+          }
+        };
+        arms.push(arm);
+      }
+
+      let (tag_bnd, fun) = if arms.len() == 1 {
+        (None, arms.pop().unwrap())
+      } else {
+        let pred = Some(Name::new(format!("{tag_var}-{}", arms.len() - 1)));
+        (Some(tag_var.clone()), encode_switch(tag_var.clone(), Term::var(tag_var), pred, arms))
+      };
+      let nxt = Term::app(fun, Term::var(fields_var.clone()));
+      Term::Dup {
+        tag,
+        bnd: vec![tag_bnd, Some(fields_var)],
+        val: Box::new(arg),
+        nxt: Box::new(nxt),
+        loc: Loc::default(),
+      }
+    }
   }
 }
 
@@ -97,11 +140,16 @@ fn encode_switch(bnd: Name, arg: Term, pred: Option<Name>, mut rules: Vec<Term>)
   let n_nums = rules.len() - 1;
   for (i, rule) in rules.iter_mut().enumerate() {
     let orig = if i != n_nums {
-      Term::Num { val: i as u64 }
+      Term::num(i as u64)
     } else {
-      Term::add_num(Term::Var { nam: pred.clone().unwrap() }, n_nums as u64)
+      Term::add_num(Term::var(pred.clone().unwrap()), n_nums as u64)
+    };
+    *rule = Term::Use {
+      nam: Some(bnd.clone()),
+      val: Box::new(orig),
+      nxt: Box::new(std::mem::take(rule)),
+      loc: Loc::default(),
     };
-    *rule = Term::Use { nam: Some(bnd.clone()), val: Box::new(orig), nxt: Box::new(std::mem::take(rule)) };
   }
 
   // Create the cascade of switches
@@ -111,14 +159,22 @@ fn encode_switch(bnd: Name, arg: Term, pred: Option<Name>, mut rules: Vec<Term>)
   nums.iter_mut().enumerate().rfold(last_arm, |term, (i, rule)| {
     let arms = vec![std::mem::take(rule), term];
     if i == 0 {
-      Term::Swt { arg: Box::new(arg.clone()), bnd: Some(bnd.clone()), with: vec![], pred: None, arms }
+      Term::Swt {
+        arg: Box::new(arg.clone()),
+        bnd: Some(bnd.clone()),
+        with: vec![],
+        pred: None,
+        arms,
+        loc: Loc::default(),
+      }
     } else {
       let swt = Term::Swt {
-        arg: Box::new(Term::Var { nam: match_var.clone() }),
+        arg: Box::new(Term::var(match_var.clone())),
         bnd: Some(match_var.clone()),
         with: vec![],
         pred: None,
         arms,
+        loc: Loc::default(),
       };
       Term::named_lam(match_var.clone(), swt)
     }
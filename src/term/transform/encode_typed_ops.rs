@@ -0,0 +1,69 @@
+use crate::{
+  diagnostics::Loc,
+  maybe_grow,
+  term::{Book, IntOp, Op, OpType, Term, NUM_FLOAT_TAG},
+};
+
+impl Book {
+  /// Makes the results of signed and float operations follow the encoding of native numbers.
+  ///
+  /// The typed operations of the runtime only use the low bits of their arguments and results,
+  /// so signed results are sign-extended to 60 bits and float results are tagged as floats.
+  ///
+  /// Example:
+  /// ```hvm
+  /// (i32.+ a b)
+  /// ```
+  /// becomes
+  /// ```hvm
+  /// (- (^ (& (i32.+ a b) 0xFFFFFFFF) 0x80000000) 0x80000000)
+  /// ```
+  pub fn encode_typed_ops(&mut self) {
+    for def in self.defs.values_mut() {
+      for rule in def.rules.iter_mut() {
+        rule.terms_mut().for_each(Term::encode_typed_ops);
+      }
+    }
+  }
+}
+
+impl Term {
+  pub(crate) fn encode_typed_ops(&mut self) {
+    maybe_grow(|| {
+      for child in self.children_mut() {
+        child.encode_typed_ops();
+      }
+
+      // Comparisons always return 0 or 1, which don't need any encoding.
+      let Term::Opx { opr, .. } = self else { return };
+      let opr = *opr;
+      if matches!(opr.op, IntOp::Eq | IntOp::Ne | IntOp::Lt | IntOp::Gt | IntOp::Le | IntOp::Ge) {
+        return;
+      }
+
+      let term = std::mem::take(self);
+      *self = match opr.ty {
+        OpType::I8 => sign_extend(term, 8),
+        OpType::I16 => sign_extend(term, 16),
+        OpType::I32 => sign_extend(term, 32),
+        OpType::F32 => u60_op(IntOp::Or, u60_op(IntOp::And, term, u32::MAX as u64), NUM_FLOAT_TAG),
+        _ => term,
+      };
+    })
+  }
+}
+
+fn sign_extend(term: Term, bits: u64) -> Term {
+  let sign = 1 << (bits - 1);
+  let low_bits = u60_op(IntOp::And, term, (1 << bits) - 1);
+  u60_op(IntOp::Sub, u60_op(IntOp::Xor, low_bits, sign), sign)
+}
+
+fn u60_op(op: IntOp, fst: Term, val: u64) -> Term {
+  Term::Opx {
+    opr: Op { ty: OpType::U60, op },
+    fst: Box::new(fst),
+    snd: Box::new(Term::num(val)),
+    loc: Loc::default(),
+  }
+}
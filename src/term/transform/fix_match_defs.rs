@@ -1,7 +1,9 @@
 use crate::{
-  diagnostics::Diagnostics,
-  term::{Adts, Constructors, Ctx, Pattern},
+  diagnostics::{Diagnostics, Span},
+  maybe_grow,
+  term::{Adts, Constructors, Ctx, Pattern, Term},
 };
+use std::collections::BTreeSet;
 
 impl Ctx<'_> {
   /// Makes every pattern matching definition have correct a left-hand side.
@@ -11,26 +13,32 @@ impl Ctx<'_> {
     self.info.start_pass();
 
     for def in self.book.defs.values_mut() {
-      let mut errs = vec![];
-
       let def_arity = def.arity();
       for rule in &mut def.rules {
+        let mut errs = vec![];
+
         if rule.arity() != def_arity {
-          errs.push(format!(
+          let err = format!(
             "Incorrect pattern matching rule arity. Expected {} args, found {}.",
             def_arity,
             rule.arity()
-          ));
+          );
+          errs.push((err, None));
         }
 
         for pat in &mut rule.pats {
           pat.resolve_pat(&self.book.ctrs);
           pat.check_good_ctr(&self.book.ctrs, &self.book.adts, &mut errs);
+          pat.check_or_binds(&mut errs);
+        }
+
+        for term in rule.terms_mut() {
+          term.fix_match_pats(&self.book.ctrs, &self.book.adts, &mut errs);
         }
-      }
 
-      for err in errs {
-        self.info.add_rule_error(err, def.name.clone());
+        for (err, span) in errs {
+          self.info.add_rule_error(err, def.name.clone(), span.or_else(|| rule.loc.span()));
+        }
       }
     }
 
@@ -38,12 +46,45 @@ impl Ctx<'_> {
   }
 }
 
+impl Term {
+  /// Does the same as [`Ctx::fix_match_defs`] for the arms of `match` terms with nested patterns.
+  /// The errors are returned with the location of the pattern or arm they were found in.
+  fn fix_match_pats(&mut self, ctrs: &Constructors, adts: &Adts, errs: &mut Vec<(String, Option<Span>)>) {
+    maybe_grow(|| {
+      if let Term::Cas { args, arms, .. } = self {
+        for arm in arms.iter_mut() {
+          let mut arm_errs = vec![];
+          if arm.arity() != args.len() {
+            let err = format!(
+              "Incorrect pattern matching arity in 'match' term. Expected {} patterns, found {}.",
+              args.len(),
+              arm.arity()
+            );
+            arm_errs.push((err, None));
+          }
+
+          for pat in &mut arm.pats {
+            pat.resolve_pat(ctrs);
+            pat.check_good_ctr(ctrs, adts, &mut arm_errs);
+            pat.check_or_binds(&mut arm_errs);
+          }
+          errs.extend(arm_errs.into_iter().map(|(err, span)| (err, span.or_else(|| arm.loc.span()))));
+        }
+      }
+
+      for child in self.children_mut() {
+        child.fix_match_pats(ctrs, adts, errs);
+      }
+    })
+  }
+}
+
 impl Pattern {
   /// If a var pattern actually refers to an ADT constructor, convert it into a constructor pattern.
   fn resolve_pat(&mut self, ctrs: &Constructors) {
-    if let Pattern::Var(Some(nam)) = self {
+    if let Pattern::Var(Some(nam), loc) = self {
       if ctrs.contains_key(nam) {
-        *self = Pattern::Ctr(std::mem::take(nam), vec![]);
+        *self = Pattern::Ctr(std::mem::take(nam), vec![], std::mem::take(loc));
       }
     }
     for child in self.children_mut() {
@@ -52,23 +93,38 @@ impl Pattern {
   }
 
   /// Check that ADT constructor pats are correct, meaning defined in a `data` and with correct arity.
-  fn check_good_ctr(&self, ctrs: &Constructors, adts: &Adts, errs: &mut Vec<String>) {
-    if let Pattern::Ctr(nam, args) = self {
+  fn check_good_ctr(&self, ctrs: &Constructors, adts: &Adts, errs: &mut Vec<(String, Option<Span>)>) {
+    if let Pattern::Ctr(nam, args, loc) = self {
       if let Some(adt) = ctrs.get(nam) {
         let expected_arity = adts[adt].ctrs[nam].len();
         let found_arity = args.len();
         if expected_arity != found_arity {
-          errs.push(format!(
+          let err = format!(
             "Incorrect arity for constructor '{}' of type '{}' in pattern matching rule. Expected {} fields, found {}",
             nam, adt, expected_arity, found_arity
-          ));
+          );
+          errs.push((err, loc.span()));
         }
       } else {
-        errs.push(format!("Unbound constructor '{nam}' in pattern matching rule."));
+        errs.push((format!("Unbound constructor '{nam}' in pattern matching rule."), loc.span()));
       }
     }
     for child in self.children() {
       child.check_good_ctr(ctrs, adts, errs);
     }
   }
+  /// Check that all the alternatives of an or-pattern bind the same variables,
+  /// since the body of the rule can't know which of them matched.
+  fn check_or_binds(&self, errs: &mut Vec<(String, Option<Span>)>) {
+    if let Pattern::Or(alts, loc) = self {
+      let binds = |alt: &Pattern| alt.binds().flatten().cloned().collect::<BTreeSet<_>>();
+      if alts.iter().any(|alt| binds(alt) != binds(&alts[0])) {
+        let err = format!("All alternatives of the or-pattern '{self}' must bind the same variables.");
+        errs.push((err, loc.span()));
+      }
+    }
+    for child in self.children() {
+      child.check_or_binds(errs);
+    }
+  }
 }
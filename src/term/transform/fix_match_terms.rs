@@ -1,5 +1,5 @@
 use crate::{
-  diagnostics::{Diagnostics, ToStringVerbose, WarningType},
+  diagnostics::{Diagnostics, Loc, Span, ToStringVerbose, WarningType},
   maybe_grow,
   term::{Adts, Constructors, Ctx, MatchRule, Name, Term},
 };
@@ -46,21 +46,25 @@ impl Ctx<'_> {
 
     for def in self.book.defs.values_mut() {
       for rule in def.rules.iter_mut() {
-        let errs = rule.body.fix_match_terms(&self.book.ctrs, &self.book.adts);
+        let mut errs = vec![];
+        for term in rule.terms_mut() {
+          errs.extend(term.fix_match_terms(&self.book.ctrs, &self.book.adts));
+        }
 
-        for err in errs {
+        for (err, span) in errs {
+          let span = span.or_else(|| rule.loc.span());
           match err {
             FixMatchErr::AdtMismatch { .. } | FixMatchErr::NonExhaustiveMatch { .. } => {
-              self.info.add_rule_error(err, def.name.clone())
+              self.info.add_rule_error(err, def.name.clone(), span)
             }
             FixMatchErr::IrrefutableMatch { .. } => {
-              self.info.add_rule_warning(err, WarningType::IrrefutableMatch, def.name.clone())
+              self.info.add_rule_warning(err, WarningType::IrrefutableMatch, def.name.clone(), span)
             }
             FixMatchErr::UnreachableMatchArms { .. } => {
-              self.info.add_rule_warning(err, WarningType::UnreachableMatch, def.name.clone())
+              self.info.add_rule_warning(err, WarningType::UnreachableMatch, def.name.clone(), span)
             }
             FixMatchErr::RedundantArm { .. } => {
-              self.info.add_rule_warning(err, WarningType::RedundantMatch, def.name.clone())
+              self.info.add_rule_warning(err, WarningType::RedundantMatch, def.name.clone(), span)
             }
           }
         }
@@ -72,7 +76,8 @@ impl Ctx<'_> {
 }
 
 impl Term {
-  fn fix_match_terms(&mut self, ctrs: &Constructors, adts: &Adts) -> Vec<FixMatchErr> {
+  /// Returns the errors found, with the location of the `match` term they come from.
+  fn fix_match_terms(&mut self, ctrs: &Constructors, adts: &Adts) -> Vec<(FixMatchErr, Option<Span>)> {
     maybe_grow(|| {
       let mut errs = Vec::new();
 
@@ -81,8 +86,11 @@ impl Term {
         errs.append(&mut e);
       }
 
-      if let Term::Mat { .. } = self {
-        self.fix_match(&mut errs, ctrs, adts);
+      if let Term::Mat { loc, .. } = self {
+        let span = loc.span();
+        let mut mat_errs = Vec::new();
+        self.fix_match(&mut mat_errs, ctrs, adts);
+        errs.extend(mat_errs.into_iter().map(|err| (err, span.clone())));
       }
 
       errs
@@ -90,7 +98,7 @@ impl Term {
   }
 
   fn fix_match(&mut self, errs: &mut Vec<FixMatchErr>, ctrs: &Constructors, adts: &Adts) {
-    let Term::Mat { arg: _, bnd, with: _, arms: rules } = self else { unreachable!() };
+    let Term::Mat { arg: _, bnd, with: _, arms: rules, loc: _ } = self else { unreachable!() };
     let bnd = bnd.clone().unwrap();
 
     // Normalize arms, making one arm for each constructor of the matched adt.
@@ -110,7 +118,7 @@ impl Term {
           body
         } else {
           errs.push(FixMatchErr::NonExhaustiveMatch { typ: adt_nam.clone(), missing: ctr.clone() });
-          Term::Err
+          Term::default()
         };
         new_rules.push((Some(ctr.clone()), fields, body));
       }
@@ -121,7 +129,7 @@ impl Term {
       let match_var = rules[0].0.take();
       *self = std::mem::take(&mut rules[0].2);
       if let Some(var) = match_var {
-        self.subst(&var, &Term::Var { nam: bnd.clone() });
+        self.subst(&var, &Term::var(bnd.clone()));
       }
     }
   }
@@ -189,8 +197,8 @@ fn match_field(arg: &Name, field: &Name) -> Name {
 }
 
 fn rebuild_ctr(arg: &Name, ctr: &Name, fields: &[Name]) -> Term {
-  let ctr = Term::Ref { nam: ctr.clone() };
-  let fields = fields.iter().map(|f| Term::Var { nam: match_field(arg, f) });
+  let ctr = Term::Ref { nam: ctr.clone(), loc: Loc::default() };
+  let fields = fields.iter().map(|f| Term::var(match_field(arg, f)));
   Term::call(ctr, fields)
 }
 
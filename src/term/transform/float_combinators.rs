@@ -1,6 +1,7 @@
 use indexmap::IndexSet;
 
 use crate::{
+  diagnostics::Loc,
   maybe_grow, multi_iterator,
   term::{Book, Definition, Name, Rule, Term},
 };
@@ -70,10 +71,10 @@ fn float_combinator(
   let comb_name = Name::new(format!("{}$C{}", def_name, *name_gen));
   *name_gen += 1;
 
-  let comb_ref = Term::Ref { nam: comb_name.clone() };
+  let comb_ref = Term::Ref { nam: comb_name.clone(), loc: Loc::default() };
   let extracted_term = std::mem::replace(term, comb_ref);
 
-  let rules = vec![Rule { body: extracted_term, pats: Vec::new() }];
+  let rules = vec![Rule { pats: Vec::new(), guard: None, body: extracted_term, loc: Loc::default() }];
   let rule = Definition { name: comb_name.clone(), rules, builtin };
   combinators.insert(comb_name, rule);
 }
@@ -86,13 +87,13 @@ impl Term {
   /// - A Reference with safe body.
   pub fn is_safe(&self, book: &Book, seen: &mut IndexSet<Name>) -> bool {
     maybe_grow(|| match self {
-      Term::Num { .. } | Term::Era => true,
+      Term::Num { .. } | Term::Era { .. } => true,
 
-      Term::Tup { els } | Term::Sup { els, .. } => els.iter().all(|e| Term::is_safe(e, book, seen)),
+      Term::Tup { els, .. } | Term::Sup { els, .. } => els.iter().all(|e| Term::is_safe(e, book, seen)),
 
       Term::Lam { .. } => self.is_safe_lambda(book, seen),
 
-      Term::Ref { nam } => {
+      Term::Ref { nam, .. } => {
         if seen.contains(nam) {
           return false;
         }
@@ -122,7 +123,7 @@ impl Term {
     }
 
     match current {
-      Term::Var { nam } if scope.contains(&nam) => true,
+      Term::Var { nam, .. } if scope.contains(&nam) => true,
       Term::Ref { .. } => true,
       term => term.is_safe(book, seen),
     }
@@ -141,7 +142,7 @@ impl Term {
 
 impl Term {
   pub fn float_children_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut Term> {
-    multi_iterator!(FloatIter { Zero, Two, Vec, Mat, App, Swt });
+    multi_iterator!(FloatIter { Zero, Two, Three, Vec, Infix, Mat, Cas, App, Swt });
     match self {
       Term::App { fun, arg, .. } => {
         let mut args = vec![arg.as_mut()];
@@ -153,16 +154,24 @@ impl Term {
         args.push(app);
         FloatIter::App(args)
       }
-      Term::Mat { arg, bnd: _, with: _, arms } => {
+      Term::Mat { arg, bnd: _, with: _, arms, loc: _ } => {
         FloatIter::Mat([arg.as_mut()].into_iter().chain(arms.iter_mut().map(|r| &mut r.2)))
       }
-      Term::Swt { arg, bnd: _, with: _, pred: _, arms } => {
+      Term::Cas { args, bnds: _, with: _, arms, loc: _ } => {
+        FloatIter::Cas(args.iter_mut().chain(arms.iter_mut().flat_map(Rule::terms_mut)))
+      }
+      Term::Swt { arg, bnd: _, with: _, pred: _, arms, loc: _ } => {
         FloatIter::Swt([arg.as_mut()].into_iter().chain(arms.iter_mut()))
       }
-      Term::Tup { els } | Term::Sup { els, .. } | Term::Lst { els } => FloatIter::Vec(els),
+      Term::If { cond, then, els, .. } => FloatIter::Three([cond.as_mut(), then.as_mut(), els.as_mut()]),
+      Term::Tup { els, .. } | Term::Sup { els, .. } | Term::Lst { els, .. } => FloatIter::Vec(els),
+      Term::Infix { fst, rest, .. } => {
+        FloatIter::Infix([fst.as_mut()].into_iter().chain(rest.iter_mut().map(|(_, el)| el)))
+      }
       Term::Ltp { val: fst, nxt: snd, .. }
       | Term::Let { val: fst, nxt: snd, .. }
       | Term::Use { val: fst, nxt: snd, .. }
+      | Term::Do { val: fst, nxt: snd, .. }
       | Term::Dup { val: fst, nxt: snd, .. }
       | Term::Opx { fst, snd, .. } => FloatIter::Two([fst.as_mut(), snd.as_mut()]),
       Term::Lam { bod, .. } | Term::Chn { bod, .. } => bod.float_children_mut(),
@@ -172,8 +181,8 @@ impl Term {
       | Term::Nat { .. }
       | Term::Str { .. }
       | Term::Ref { .. }
-      | Term::Era
-      | Term::Err => FloatIter::Zero([]),
+      | Term::Era { .. }
+      | Term::Err { .. } => FloatIter::Zero([]),
     }
   }
 }
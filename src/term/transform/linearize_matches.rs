@@ -81,7 +81,7 @@ impl Term {
     // The used vars are any free vars in the argument,
     // the match bind and the ctr field binds.
     let (vars, with, mut arms) = match self {
-      Term::Mat { arg, bnd, with, arms } => {
+      Term::Mat { arg, bnd, with, arms, loc: _ } => {
         let mut vars = arg.free_vars().into_keys().collect::<HashSet<_>>();
         if let Some(bnd) = bnd {
           vars.insert(bnd.clone());
@@ -94,7 +94,7 @@ impl Term {
 
         (vars, with, arms)
       }
-      Term::Swt { arg, bnd, with, pred, arms } => {
+      Term::Swt { arg, bnd, with, pred, arms, .. } => {
         let mut vars = arg.free_vars().into_keys().collect::<HashSet<_>>();
         if let Some(bnd) = bnd {
           vars.insert(bnd.clone());
@@ -219,10 +219,10 @@ impl Term {
 pub fn lift_match_vars(match_term: &mut Term) -> &mut Term {
   // Collect match arms with binds
   let arms: Vec<_> = match match_term {
-    Term::Mat { arg: _, bnd: _, with: _, arms: rules } => {
+    Term::Mat { arg: _, bnd: _, with: _, arms: rules, loc: _ } => {
       rules.iter().map(|(_, binds, body)| (binds.iter().flatten().cloned().collect(), body)).collect()
     }
-    Term::Swt { arg: _, bnd: _, with: _, pred, arms } => {
+    Term::Swt { arg: _, bnd: _, with: _, pred, arms, loc: _ } => {
       let (succ, nums) = arms.split_last_mut().unwrap();
       let mut arms = nums.iter().map(|body| (vec![], body)).collect::<Vec<_>>();
       arms.push((vec![pred.clone().unwrap()], succ));
@@ -247,14 +247,14 @@ pub fn lift_match_vars(match_term: &mut Term) -> &mut Term {
 
   // Add lambdas to the arms
   match match_term {
-    Term::Mat { arg: _, bnd: _, with, arms } => {
+    Term::Mat { arg: _, bnd: _, with, arms, loc: _ } => {
       with.retain(|with| !vars_to_lift.contains(with));
       for arm in arms {
         let old_body = std::mem::take(&mut arm.2);
         arm.2 = vars_to_lift.iter().cloned().rfold(old_body, |body, nam| Term::named_lam(nam, body));
       }
     }
-    Term::Swt { arg: _, bnd: _, with, pred: _, arms } => {
+    Term::Swt { arg: _, bnd: _, with, pred: _, arms, loc: _ } => {
       with.retain(|with| !vars_to_lift.contains(with));
       for arm in arms {
         let old_body = std::mem::take(arm);
@@ -276,7 +276,7 @@ pub fn lift_match_vars(match_term: &mut Term) -> &mut Term {
 fn get_match_reference(mut match_term: &mut Term) -> &mut Term {
   loop {
     match match_term {
-      Term::App { tag: _, fun, arg: _ } => match_term = fun.as_mut(),
+      Term::App { tag: _, fun, arg: _, loc: _ } => match_term = fun.as_mut(),
       Term::Swt { .. } | Term::Mat { .. } => return match_term,
       _ => unreachable!(),
     }
@@ -304,21 +304,21 @@ impl Term {
       }
     });
     match self {
-      Term::Mat { arg: _, bnd: _, with, arms: rules } => {
+      Term::Mat { arg: _, bnd: _, with, arms: rules, loc: _ } => {
         // Linearize the vars in the `with` clause, but only the used ones.
         let with = retain_used_names(std::mem::take(with), rules.iter().map(|r| &r.2));
         for rule in rules {
           rule.2 =
             with.iter().rfold(std::mem::take(&mut rule.2), |bod, nam| Term::lam(Some(nam.clone()), bod));
         }
-        *self = Term::call(std::mem::take(self), with.into_iter().map(|nam| Term::Var { nam }));
+        *self = Term::call(std::mem::take(self), with.into_iter().map(Term::var));
       }
-      Term::Swt { arg: _, bnd: _, with, pred: _, arms } => {
+      Term::Swt { arg: _, bnd: _, with, pred: _, arms, loc: _ } => {
         let with = retain_used_names(std::mem::take(with), arms.iter());
         for arm in arms {
           *arm = with.iter().rfold(std::mem::take(arm), |bod, nam| Term::lam(Some(nam.clone()), bod));
         }
-        *self = Term::call(std::mem::take(self), with.into_iter().map(|nam| Term::Var { nam }));
+        *self = Term::call(std::mem::take(self), with.into_iter().map(Term::var));
       }
       _ => {}
     }
@@ -1,4 +1,5 @@
 use crate::{
+  diagnostics::Loc,
   maybe_grow,
   term::{Book, Name, Tag, Term},
 };
@@ -39,7 +40,7 @@ impl Term {
 
 fn term_to_affine(term: &mut Term, var_uses: &mut HashMap<Name, u64>) {
   maybe_grow(|| match term {
-    Term::Let { nam: Some(nam), val, nxt } => {
+    Term::Let { nam: Some(nam), val, nxt, .. } => {
       // TODO: This is swapping the order of how the bindings are
       // used, since it's not following the usual AST order (first
       // val, then nxt). Doesn't change behaviour, but looks strange.
@@ -54,7 +55,7 @@ fn term_to_affine(term: &mut Term, var_uses: &mut HashMap<Name, u64>) {
             let val = std::mem::take(val);
             let nxt = std::mem::take(nxt);
 
-            *term = Term::Let { nam: None, val, nxt };
+            *term = Term::Let { nam: None, val, nxt, loc: Loc::default() };
           } else {
             *term = std::mem::take(nxt.as_mut());
           }
@@ -72,7 +73,7 @@ fn term_to_affine(term: &mut Term, var_uses: &mut HashMap<Name, u64>) {
       }
     }
 
-    Term::Let { nam: None, val, nxt } => {
+    Term::Let { nam: None, val, nxt, .. } => {
       term_to_affine(nxt, var_uses);
 
       if val.has_unscoped() {
@@ -84,7 +85,7 @@ fn term_to_affine(term: &mut Term, var_uses: &mut HashMap<Name, u64>) {
       }
     }
 
-    Term::Var { nam } => {
+    Term::Var { nam, .. } => {
       let instantiated_count = var_uses.entry(nam.clone()).or_default();
       *instantiated_count += 1;
       *nam = dup_name(nam, *instantiated_count);
@@ -127,8 +128,9 @@ fn duplicate_term(nam: &Name, nxt: &mut Term, uses: u64, dup_body: Option<&mut T
   *nxt = Term::Dup {
     tag: Tag::Auto,
     bnd: (1 .. uses + 1).map(|i| Some(dup_name(nam, i))).collect(),
-    val: Box::new(dup_body.map_or_else(|| Term::Var { nam: nam.clone() }, std::mem::take)),
+    val: Box::new(dup_body.map_or_else(|| Term::var(nam.clone()), std::mem::take)),
     nxt: Box::new(std::mem::take(nxt)),
+    loc: Loc::default(),
   }
 }
 
@@ -2,9 +2,12 @@ pub mod apply_args;
 pub mod apply_use;
 pub mod definition_merge;
 pub mod definition_pruning;
+pub mod desugar_do_blocks;
+pub mod desugar_infix;
 pub mod desugar_match_defs;
 pub mod encode_adts;
 pub mod encode_match_terms;
+pub mod encode_typed_ops;
 pub mod fix_match_defs;
 pub mod fix_match_terms;
 pub mod float_combinators;
@@ -13,4 +16,5 @@ pub mod linearize_vars;
 pub mod resolve_refs;
 pub mod resugar_adts;
 pub mod resugar_builtins;
+pub mod resugar_typed_nums;
 pub mod unique_names;
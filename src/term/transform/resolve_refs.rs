@@ -1,6 +1,6 @@
 use crate::{
   builtins::CORE_BUILTINS,
-  diagnostics::{Diagnostics, ToStringVerbose},
+  diagnostics::{Diagnostics, Span, ToStringVerbose},
   maybe_grow,
   term::{Ctx, Name, Pattern, Term},
 };
@@ -21,7 +21,8 @@ impl Ctx<'_> {
   pub fn resolve_refs(&mut self) -> Result<(), Diagnostics> {
     self.info.start_pass();
 
-    let def_names = self.book.defs.keys().cloned().collect::<HashSet<_>>();
+    let def_names =
+      self.book.defs.keys().chain(self.book.host_builtins.keys()).cloned().collect::<HashSet<_>>();
     for (def_name, def) in &mut self.book.defs {
       for rule in def.rules.iter_mut() {
         let mut scope = HashMap::new();
@@ -30,8 +31,12 @@ impl Ctx<'_> {
           push_scope(name.as_ref(), &mut scope);
         }
 
-        let res = rule.body.resolve_refs(&def_names, self.book.entrypoint.as_ref(), &mut scope);
-        self.info.take_rule_err(res, def_name.clone());
+        for term in rule.guard.iter_mut().chain([&mut rule.body]) {
+          let res = term.resolve_refs(&def_names, self.book.entrypoint.as_ref(), &mut scope);
+          if let Err((err, span)) = res {
+            self.info.add_rule_error(err, def_name.clone(), span.or_else(|| rule.loc.span()));
+          }
+        }
       }
     }
 
@@ -40,26 +45,27 @@ impl Ctx<'_> {
 }
 
 impl Term {
+  /// Returns the error with the location of the reference to main, if known.
   pub fn resolve_refs<'a>(
     &'a mut self,
     def_names: &HashSet<Name>,
     main: Option<&Name>,
     scope: &mut HashMap<&'a Name, usize>,
-  ) -> Result<(), ReferencedMainErr> {
+  ) -> Result<(), (ReferencedMainErr, Option<Span>)> {
     maybe_grow(move || {
-      if let Term::Var { nam } = self
+      if let Term::Var { nam, loc } = self
         && is_var_in_scope(nam, scope)
       {
         // If the variable is actually a reference to main, don't swap and return an error.
         if let Some(main) = main
           && nam == main
         {
-          return Err(ReferencedMainErr);
+          return Err((ReferencedMainErr, loc.span()));
         }
 
         // If the variable is actually a reference to a function, swap the term.
         if def_names.contains(nam) || CORE_BUILTINS.contains(&nam.0.as_ref()) {
-          *self = Term::r#ref(nam);
+          *self = Term::r#ref(nam).with_loc(loc.clone());
         }
       }
 
@@ -1,28 +1,200 @@
 use std::collections::VecDeque;
 
 use crate::{
-  diagnostics::ToStringVerbose,
+  diagnostics::{Loc, ToStringVerbose},
   maybe_grow,
   term::{Adt, AdtEncoding, Book, Name, Tag, Term},
 };
 
+#[derive(PartialEq)]
 pub enum AdtReadbackError {
   MalformedCtr(Name),
   MalformedMatch(Name),
   UnexpectedTag(Tag, Tag),
+  /// A scott encoded term has the shape of constructors of more than one datatype.
+  AmbiguousCtr(Vec<Name>),
 }
 
 impl Term {
   pub fn resugar_adts(&mut self, book: &Book, adt_encoding: AdtEncoding) -> Vec<AdtReadbackError> {
     let mut errs = Default::default();
     match adt_encoding {
-      // No way of resugaring simple scott encoded terms.
-      AdtEncoding::Scott => (),
+      AdtEncoding::Scott => self.resugar_scott(book, None, &mut errs),
       AdtEncoding::TaggedScott => self.resugar_tagged_scott(book, &mut errs),
+      AdtEncoding::NumTagged => self.resugar_num_tagged(book, &mut errs),
     };
     errs
   }
 
+  /// Reconstructs scott encoded constructors, guessing their datatype from their shape.
+  ///
+  /// A constructor is a chain of one lambda per constructor of its datatype, where only one of them is used,
+  /// applied to the fields in the body. Only the datatypes with constructors in the program are considered,
+  /// and terms that could be constructors of more than one of them are kept as they are.
+  ///
+  /// The empty constructors of builtin datatypes, like `List.nil`, have the same shape as many other terms,
+  /// like Church encoded `false`. They are only resugared at the end of a value of their datatype,
+  /// the `spine` that is being read back, so `λt λf f` doesn't become `[]`.
+  ///
+  /// # Example
+  ///
+  /// ```hvm
+  /// data Option = (Some val) | None
+  ///
+  /// // This value:
+  /// (Some None)
+  ///
+  /// // Gives the following readback:
+  /// λa λ* (a λ* λb b)
+  ///
+  /// // Which gets resugared as:
+  /// (Some None)
+  /// ```
+  fn resugar_scott(&mut self, book: &Book, spine: Option<&Name>, errs: &mut Vec<AdtReadbackError>) {
+    maybe_grow(|| {
+      if let Some((adt_name, ctr)) = self.scott_ctr(book, spine, errs) {
+        let mut body = &mut *self;
+        while let Term::Lam { bod, .. } = body {
+          body = bod.as_mut();
+        }
+        let mut fields = vec![];
+        while let Term::App { fun, arg, .. } = body {
+          fields.push(std::mem::take(arg.as_mut()));
+          body = fun.as_mut();
+        }
+        fields.reverse();
+        // The last field of a builtin constructor, like the tail of a list, continues its value.
+        let builtin = book.adts[&adt_name].builtin;
+        let n_fields = fields.len();
+        for (i, field) in fields.iter_mut().enumerate() {
+          let spine = (builtin && i + 1 == n_fields).then_some(&adt_name);
+          field.resugar_scott(book, spine, errs);
+        }
+        *self = Term::call(Term::Ref { nam: ctr, loc: Loc::default() }, fields);
+        return;
+      }
+
+      for child in self.children_mut() {
+        child.resugar_scott(book, None, errs);
+      }
+    })
+  }
+
+  /// If this term has the shape of a scott encoded constructor of exactly one datatype,
+  /// returns the datatype and the constructor.
+  fn scott_ctr(
+    &self,
+    book: &Book,
+    spine: Option<&Name>,
+    errs: &mut Vec<AdtReadbackError>,
+  ) -> Option<(Name, Name)> {
+    let mut binds = vec![];
+    let mut body = self;
+    while let Term::Lam { tag: Tag::Static, nam, bod, .. } = body {
+      binds.push(nam);
+      body = bod;
+    }
+    let mut fields = vec![];
+    while let Term::App { tag: Tag::Static, fun, arg, .. } = body {
+      fields.push(arg);
+      body = fun;
+    }
+    let Term::Var { nam: head, .. } = body else { return None };
+    let idx = binds.iter().position(|bind| bind.as_ref() == Some(head))?;
+
+    // The other lambdas are erased, and the fields can't use the one that is applied.
+    let used_in_fields = fields.iter().any(|field| field.free_vars().contains_key(head));
+    if binds.iter().filter(|bind| bind.is_some()).count() != 1 || used_in_fields {
+      return None;
+    }
+
+    let fitting_ctr = |adt: &Adt| {
+      let (ctr, ctr_fields) = adt.ctrs.get_index(idx)?;
+      let fits =
+        adt.ctrs.len() == binds.len() && ctr_fields.len() == fields.len() && book.defs.contains_key(ctr);
+      fits.then(|| ctr.clone())
+    };
+
+    if let Some(spine) = spine
+      && let Some(ctr) = fitting_ctr(&book.adts[spine])
+    {
+      return Some((spine.clone(), ctr));
+    }
+
+    let candidates = book
+      .adts
+      .iter()
+      .filter_map(|(adt_name, adt)| {
+        let ctr = fitting_ctr(adt)?;
+        let empty_builtin = adt.builtin && fields.is_empty();
+        (!empty_builtin).then_some((adt_name, ctr))
+      })
+      .collect::<Vec<_>>();
+    match candidates.as_slice() {
+      [] => None,
+      [(adt_name, ctr)] => Some(((*adt_name).clone(), ctr.clone())),
+      _ => {
+        let err = AdtReadbackError::AmbiguousCtr(candidates.iter().map(|(adt, _)| (*adt).clone()).collect());
+        if !errs.contains(&err) {
+          errs.push(err);
+        }
+        None
+      }
+    }
+  }
+
+  /// Reconstructs adt-tagged superpositions as their constructors.
+  /// Matches are native numeric switches, which are not resugared.
+  ///
+  /// # Example
+  ///
+  /// ```hvm
+  /// data Tree = (Node left right) | Leaf
+  ///
+  /// // This value:
+  /// (Node Leaf 5)
+  ///
+  /// // Gives the following readback:
+  /// #Tree{0 #Tree{#Tree{1 *} 5}}
+  ///
+  /// // Which gets resugared as:
+  /// (Node Leaf 5)
+  /// ```
+  fn resugar_num_tagged(&mut self, book: &Book, errs: &mut Vec<AdtReadbackError>) {
+    maybe_grow(|| {
+      if let Term::Sup { tag: Tag::Named(adt_name), els, .. } = self
+        && let Some((adt_name, adt)) = book.adts.get_key_value(adt_name)
+      {
+        let ctr = match els.as_slice() {
+          [Term::Num { val, .. }, _] => adt.ctrs.get_index(*val as usize),
+          _ => None,
+        };
+        let fields = ctr.and_then(|(_, ctr_args)| {
+          let payload = els.pop().unwrap();
+          match ctr_args.len() {
+            0 => Some(vec![]),
+            1 => Some(vec![payload]),
+            n => sup_fields(payload, adt_name, n),
+          }
+        });
+        match (ctr, fields) {
+          (Some((ctr, _)), Some(mut fields)) => {
+            for field in fields.iter_mut() {
+              field.resugar_num_tagged(book, errs);
+            }
+            *self = Term::call(Term::Ref { nam: ctr.clone(), loc: Loc::default() }, fields);
+          }
+          _ => errs.push(AdtReadbackError::MalformedCtr(adt_name.clone())),
+        }
+        return;
+      }
+
+      for child in self.children_mut() {
+        child.resugar_num_tagged(book, errs);
+      }
+    })
+  }
+
   fn resugar_tagged_scott(&mut self, book: &Book, errs: &mut Vec<AdtReadbackError>) {
     maybe_grow(|| match self {
       Term::Lam { tag: Tag::Named(adt_name), bod, .. } | Term::Chn { tag: Tag::Named(adt_name), bod, .. } => {
@@ -33,7 +205,7 @@ impl Term {
         }
       }
 
-      Term::App { tag: Tag::Named(adt_name), fun, arg } => {
+      Term::App { tag: Tag::Named(adt_name), fun, arg, .. } => {
         if let Some((adt_name, adt)) = book.adts.get_key_value(adt_name) {
           self.resugar_match_tagged_scott(book, adt_name, adt, errs);
         } else {
@@ -79,7 +251,7 @@ impl Term {
     // One lambda per ctr of this adt
     for ctr in &adt.ctrs {
       match app {
-        Term::Lam { tag: Tag::Named(tag), nam, bod } if tag == adt_name => {
+        Term::Lam { tag: Tag::Named(tag), nam, bod, .. } if tag == adt_name => {
           if let Some(nam) = nam {
             if current_arm.is_some() {
               errs.push(AdtReadbackError::MalformedCtr(adt_name.clone()));
@@ -125,7 +297,7 @@ impl Term {
     }
 
     match cur {
-      Term::Var { nam } if nam == &arm_name => {}
+      Term::Var { nam, .. } if nam == &arm_name => {}
       _ => {
         errs.push(AdtReadbackError::MalformedCtr(adt_name.clone()));
         return;
@@ -186,7 +358,7 @@ impl Term {
     // If matching a constructor with N fields, the body should start with N tagged lambdas.
     for (ctr, ctr_args) in adt.ctrs.iter().rev() {
       match cur {
-        Term::App { tag: Tag::Named(tag), fun, arg } if tag == adt_name => {
+        Term::App { tag: Tag::Named(tag), fun, arg, .. } if tag == adt_name => {
           // We expect a lambda for each field. If we got anything
           // else, we have to create an eta-reducible match to get
           // the same behaviour.
@@ -223,7 +395,7 @@ impl Term {
             let applied_arm = Term::tagged_call(
               expected_tag.clone(),
               std::mem::take(arg.as_mut()),
-              fields.clone().map(|f| Term::Var { nam: f }),
+              fields.clone().map(Term::var),
             );
             arms.push_front((Some(ctr.clone()), fields.map(Some).collect(), applied_arm));
           }
@@ -243,7 +415,7 @@ impl Term {
 
     // If the match is on a non-var we have to extract it to get usable ctr fields.
     // Here we get the arg name and separate the term if it's not a variable.
-    let (arg, bind) = if let Term::Var { nam } = cur {
+    let (arg, bind) = if let Term::Var { nam, .. } = cur {
       (nam.clone(), None)
     } else {
       (Name::new("%matched"), Some(std::mem::take(cur)))
@@ -259,7 +431,7 @@ impl Term {
           let field_name = &adt.ctrs[ctr.as_ref().unwrap()][field_idx];
           let new_field = Name::new(format!("{arg}.{field_name}"));
           new_fields.push(Some(new_field.clone()));
-          body.subst(old_field, &Term::Var { nam: new_field });
+          body.subst(old_field, &Term::var(new_field));
         }
       }
       *fields = new_fields;
@@ -268,13 +440,36 @@ impl Term {
     let arms = arms.into_iter().collect::<Vec<_>>();
 
     *self = if let Some(bind) = bind {
-      Term::Mat { arg: Box::new(bind), bnd: Some(arg.clone()), with: vec![], arms }
+      Term::Mat { arg: Box::new(bind), bnd: Some(arg.clone()), with: vec![], arms, loc: Loc::default() }
     } else {
-      Term::Mat { arg: Box::new(Term::Var { nam: arg.clone() }), bnd: Some(arg.clone()), with: vec![], arms }
+      Term::Mat {
+        arg: Box::new(Term::var(arg.clone())),
+        bnd: Some(arg.clone()),
+        with: vec![],
+        arms,
+        loc: Loc::default(),
+      }
     };
   }
 }
 
+/// Flattens the `n` fields of a constructor, stored in nested superpositions with the tag of its datatype.
+fn sup_fields(mut term: Term, adt_name: &Name, n: usize) -> Option<Vec<Term>> {
+  let mut fields = vec![];
+  while fields.len() < n - 1 {
+    match &mut term {
+      Term::Sup { tag: Tag::Named(tag), els, .. } if tag == adt_name && els.len() == 2 => {
+        let rest = els.pop().unwrap();
+        fields.push(els.pop().unwrap());
+        term = rest;
+      }
+      _ => return None,
+    }
+  }
+  fields.push(term);
+  Some(fields)
+}
+
 impl ToStringVerbose for AdtReadbackError {
   fn to_string_verbose(&self, _verbose: bool) -> String {
     match self {
@@ -286,6 +481,10 @@ impl ToStringVerbose for AdtReadbackError {
         let found = if let Tag::Static = found { "no tag".to_string() } else { format!("'{found}'") };
         format!("Unexpected tag found during Adt readback, expected '{}', but found {}.", expected, found)
       }
+      AdtReadbackError::AmbiguousCtr(adts) => {
+        let adts = adts.iter().map(|adt| format!("'{adt}'")).collect::<Vec<_>>().join(", ");
+        format!("Could not tell the datatype of a scott encoded constructor, it could be any of {adts}.")
+      }
     }
   }
 }
@@ -1,4 +1,5 @@
 use crate::{
+  diagnostics::Loc,
   maybe_grow,
   term::{Term, LCONS, LNIL, NAT_SUCC, NAT_ZERO, SCONS, SNIL},
 };
@@ -13,20 +14,22 @@ impl Term {
   pub fn resugar_nats(&mut self) {
     maybe_grow(|| match self {
       // (Nat.succ pred)
-      Term::App { fun: box Term::Ref { nam: ctr }, arg: box pred, .. } => {
+      Term::App { fun: box Term::Ref { nam: ctr, .. }, arg: box pred, .. } => {
         pred.resugar_nats();
 
         if ctr == NAT_SUCC {
-          if let Term::Nat { val } = pred {
-            *self = Term::Nat { val: *val + 1 };
+          if let Term::Nat { val, .. } = pred {
+            *self = Term::Nat { val: *val + 1, loc: Loc::default() };
           } else {
             let n = std::mem::take(pred);
-            *self = Term::call(Term::Ref { nam: ctr.clone() }, [n]);
+            *self = Term::call(Term::Ref { nam: ctr.clone(), loc: Loc::default() }, [n]);
           }
         }
       }
       // (Nat.zero)
-      Term::Ref { nam: def_name } if def_name == NAT_ZERO => *self = Term::Nat { val: 0 },
+      Term::Ref { nam: def_name, .. } if def_name == NAT_ZERO => {
+        *self = Term::Nat { val: 0, loc: Loc::default() }
+      }
 
       _ => {
         for child in self.children_mut() {
@@ -41,7 +44,7 @@ impl Term {
     maybe_grow(|| match self {
       // (String.cons Num tail)
       Term::App {
-        fun: box Term::App { fun: box Term::Ref { nam: ctr }, arg: box head, .. },
+        fun: box Term::App { fun: box Term::Ref { nam: ctr, .. }, arg: box head, .. },
         arg: box tail,
         ..
       } => {
@@ -49,8 +52,8 @@ impl Term {
         tail.resugar_strings();
 
         if ctr == SCONS
-          && let Term::Num { val } = head
-          && let Term::Str { val: tail } = tail
+          && let Term::Num { val, .. } = head
+          && let Term::Str { val: tail, .. } = tail
         {
           // If well formed string, add the next character to the string we're building
           let head = unsafe { char::from_u32_unchecked(*val as u32) }.to_string();
@@ -60,18 +63,18 @@ impl Term {
           // Otherwise rebuild the constructor with the new tail
 
           // Create `(Cons head Nil)` instead of `(Cons head "")`
-          if matches!(&tail, Term::Str { val } if val.is_empty()) {
+          if matches!(&tail, Term::Str { val, .. } if val.is_empty()) {
             *tail = Term::r#ref(SNIL);
           }
 
           let head = std::mem::take(head);
           let tail = std::mem::take(tail);
 
-          *self = Term::call(Term::Ref { nam: ctr.clone() }, [head, tail]);
+          *self = Term::call(Term::Ref { nam: ctr.clone(), loc: Loc::default() }, [head, tail]);
         }
       }
       // (String.nil)
-      Term::Ref { nam: def_name } if def_name == SNIL => *self = Term::str(""),
+      Term::Ref { nam: def_name, .. } if def_name == SNIL => *self = Term::str(""),
 
       _ => {
         for child in self.children_mut() {
@@ -86,7 +89,7 @@ impl Term {
     maybe_grow(|| match self {
       // (List.cons el tail)
       Term::App {
-        fun: box Term::App { fun: box Term::Ref { nam: ctr }, arg: box head, .. },
+        fun: box Term::App { fun: box Term::Ref { nam: ctr, .. }, arg: box head, .. },
         arg: box tail,
         ..
       } => {
@@ -95,19 +98,21 @@ impl Term {
         let head = std::mem::take(head);
 
         if ctr == LCONS
-          && let Term::Lst { els: tail } = tail
+          && let Term::Lst { els: tail, .. } = tail
         {
           // If well formed list, cons the next element to the list being formed
           let mut els = vec![head];
           els.extend(std::mem::take(tail));
-          *self = Term::Lst { els };
+          *self = Term::Lst { els, loc: Loc::default() };
         } else {
           let tail = std::mem::take(tail);
-          *self = Term::call(Term::Ref { nam: ctr.clone() }, [head, tail]);
+          *self = Term::call(Term::Ref { nam: ctr.clone(), loc: Loc::default() }, [head, tail]);
         }
       }
       // (List.nil)
-      Term::Ref { nam: def_name } if def_name == LNIL => *self = Term::Lst { els: vec![] },
+      Term::Ref { nam: def_name, .. } if def_name == LNIL => {
+        *self = Term::Lst { els: vec![], loc: Loc::default() }
+      }
 
       _ => {
         for child in self.children_mut() {
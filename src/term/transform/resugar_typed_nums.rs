@@ -0,0 +1,27 @@
+use crate::{
+  maybe_grow,
+  term::{NumType, Term, NUM_FLOAT_TAG, NUM_SIGN_BIT},
+};
+
+impl Term {
+  /// Marks the numbers whose bits encode a negative or a float number,
+  /// so that they are displayed as one instead of as a large unsigned number.
+  ///
+  /// The same bits could also be an unsigned number, so this is only done when asked for,
+  /// with the `typed_nums` run option.
+  pub fn resugar_typed_nums(&mut self) {
+    maybe_grow(|| {
+      if let Term::Num { val, typ, .. } = self {
+        // Negative numbers are sign-extended, so they also have the float tag set.
+        if *val & NUM_SIGN_BIT != 0 {
+          *typ = NumType::Signed;
+        } else if *val & NUM_FLOAT_TAG != 0 {
+          *typ = NumType::Float;
+        }
+      }
+      for child in self.children_mut() {
+        child.resugar_typed_nums();
+      }
+    })
+  }
+}
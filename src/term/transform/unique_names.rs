@@ -35,7 +35,7 @@ impl UniqueNameGenerator {
   // Recursively assign an id to each variable in the term, then convert each id into a unique name.
   pub fn unique_names_in_term(&mut self, term: &mut Term) {
     maybe_grow(|| match term {
-      Term::Var { nam } => *nam = self.use_var(nam),
+      Term::Var { nam, .. } => *nam = self.use_var(nam),
       _ => {
         for (child, binds) in term.children_mut_with_binds_mut() {
           let binds: Vec<_> = binds.collect();
@@ -0,0 +1,157 @@
+use crate::{
+  diagnostics::{Diagnostics, DiagnosticsConfig, Severity},
+  run_book,
+  term::{Book, Name, Term},
+  CompileOpts, RunOpts, RunStats, ENTRY_POINT, HVM1_ENTRY_POINT,
+};
+use std::fmt;
+
+/// Definitions whose name starts with this prefix are tests.
+/// Inside modules, the prefix is checked after the module path, like in `Data.List.test_map`.
+pub const TEST_PREFIX: &str = "test_";
+
+/// The suffix of the definition with the expected result of a test, like `test_sum.expected`.
+/// Tests without one are expected to return `1`.
+pub const EXPECTED_SUFFIX: &str = ".expected";
+
+/// The maximum amount of rewrites of each test, unless another limit is given.
+/// Stops tests that don't terminate from blocking the others.
+pub const TEST_MAX_REWRITES_DEFAULT: usize = 100_000_000;
+
+pub struct TestResult {
+  pub name: Name,
+  pub outcome: TestOutcome,
+  /// The stats of running the test, if it could be compiled.
+  pub stats: Option<RunStats>,
+}
+
+pub enum TestOutcome {
+  Passed,
+  Failed {
+    expected: Term,
+    found: Term,
+  },
+  /// The test reached the maximum amount of rewrites before finishing.
+  RewriteLimit(usize),
+  /// The test, or its expected result, has compilation or readback errors.
+  Error(Diagnostics),
+}
+
+impl TestResult {
+  pub fn passed(&self) -> bool {
+    matches!(self.outcome, TestOutcome::Passed)
+  }
+}
+
+/// The names of the tests of a book, in the order they were defined.
+/// Only tests that contain `filter` in their name are returned.
+pub fn find_tests(book: &Book, filter: Option<&str>) -> Vec<Name> {
+  book
+    .defs
+    .values()
+    .filter(|def| !def.builtin)
+    .map(|def| &def.name)
+    .filter(|name| {
+      name.rsplit('.').next().unwrap().starts_with(TEST_PREFIX) && !name.ends_with(EXPECTED_SUFFIX)
+    })
+    .filter(|name| match filter {
+      Some(filter) => name.contains(filter),
+      None => true,
+    })
+    .cloned()
+    .collect()
+}
+
+/// Runs each test of a book as the entrypoint, comparing its result with the expected one.
+pub fn run_tests(
+  book: &Book,
+  filter: Option<&str>,
+  run_opts: RunOpts,
+  compile_opts: CompileOpts,
+  diagnostics_cfg: DiagnosticsConfig,
+) -> Vec<TestResult> {
+  let tests = find_tests(book, filter);
+  tests
+    .into_iter()
+    .map(|name| run_test(book, name, run_opts.clone(), compile_opts.clone(), diagnostics_cfg))
+    .collect()
+}
+
+pub fn run_test(
+  book: &Book,
+  name: Name,
+  run_opts: RunOpts,
+  compile_opts: CompileOpts,
+  diagnostics_cfg: DiagnosticsConfig,
+) -> TestResult {
+  let (found, readback_diagnostics, stats) =
+    match eval_entrypoint(book, &name, run_opts.clone(), compile_opts.clone(), diagnostics_cfg) {
+      Ok(res) => res,
+      Err(diagnostics) => return TestResult { name, outcome: TestOutcome::Error(diagnostics), stats: None },
+    };
+  // The result of an interrupted test is not meaningful, so we don't look at it.
+  if let Some(max_rewrites) = run_opts.max_rewrites
+    && stats.reached_max_rewrites
+  {
+    return TestResult { name, outcome: TestOutcome::RewriteLimit(max_rewrites), stats: Some(stats) };
+  }
+  if readback_diagnostics.has_errors() {
+    return TestResult { name, outcome: TestOutcome::Error(readback_diagnostics), stats: Some(stats) };
+  }
+
+  let expected_name = Name::new(format!("{name}{EXPECTED_SUFFIX}"));
+  let expected = if book.defs.contains_key(&expected_name) {
+    match eval_entrypoint(book, &expected_name, run_opts, compile_opts, diagnostics_cfg) {
+      Ok((expected, diagnostics, _)) if !diagnostics.has_errors() => expected,
+      Ok((_, diagnostics, _)) | Err(diagnostics) => {
+        return TestResult { name, outcome: TestOutcome::Error(diagnostics), stats: Some(stats) };
+      }
+    }
+  } else {
+    Term::num(1)
+  };
+
+  let outcome = if found == expected { TestOutcome::Passed } else { TestOutcome::Failed { expected, found } };
+  TestResult { name, outcome, stats: Some(stats) }
+}
+
+/// Runs a definition of a book as its entrypoint, returning its result and the readback diagnostics.
+fn eval_entrypoint(
+  book: &Book,
+  entrypoint: &Name,
+  run_opts: RunOpts,
+  compile_opts: CompileOpts,
+  diagnostics_cfg: DiagnosticsConfig,
+) -> Result<(Term, Diagnostics, RunStats), Diagnostics> {
+  let mut book = book.clone();
+  // The main function would conflict with the test as the entrypoint.
+  for main in [ENTRY_POINT, HVM1_ENTRY_POINT] {
+    if *entrypoint != main {
+      book.defs.shift_remove(&Name::new(main));
+    }
+  }
+  book.entrypoint = Some(entrypoint.clone());
+
+  // Definitions that are used only by other tests are not an issue.
+  let diagnostics_cfg = DiagnosticsConfig { unused_definition: Severity::Allow, ..diagnostics_cfg };
+  let (term, info) = run_book(book, run_opts.max_memory, run_opts, compile_opts, diagnostics_cfg, None)?;
+  if let Some(code) = info.exit_code {
+    return Err(format!("'{entrypoint}' exited with status code {code}.").into());
+  }
+  Ok((term, info.diagnostics, info.stats))
+}
+
+impl fmt::Display for TestOutcome {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      TestOutcome::Passed => write!(f, "ok"),
+      TestOutcome::Failed { expected, found } => {
+        write!(f, "FAILED\n  expected: {expected}\n  found:    {found}")
+      }
+      TestOutcome::RewriteLimit(max_rewrites) => {
+        write!(f, "FAILED\n  reached the limit of {max_rewrites} rewrites")
+      }
+      TestOutcome::Error(diagnostics) => write!(f, "FAILED\n{diagnostics}"),
+    }
+  }
+}
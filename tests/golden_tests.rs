@@ -1,12 +1,16 @@
 use hvml::{
+  builtins::host_fns::HostFns,
   compile_book, desugar_book,
   diagnostics::{Diagnostics, DiagnosticsConfig, Severity, ToStringVerbose},
   net::{hvmc_to_net::hvmc_to_net, net_to_hvmc::net_to_hvmc},
+  program::Program,
+  repl::{read_entry, Repl},
   run_book,
   term::{
-    load_book::do_parse_book, net_to_term::net_to_term, parser::TermParser, term_to_compat_net,
-    term_to_net::Labels, AdtEncoding, Book, Ctx, Name,
+    format::format_code, load_book::do_parse_book, net_to_term::net_to_term, parser::TermParser,
+    term_to_compat_net, term_to_net::Labels, AdtEncoding, Book, Ctx, Name, Term,
   },
+  test_runner::run_tests,
   CompileOpts, RunOpts,
 };
 use insta::assert_snapshot;
@@ -63,6 +67,10 @@ fn run_golden_test_dir(test_name: &str, run: &RunFn) {
 }
 
 fn run_golden_test_dir_multiple(test_name: &str, run: &[&RunFn]) {
+  // Diagnostics are highlighted with colors otherwise, which makes the snapshots hard to read.
+  // Also inherited by the commands that the tests run.
+  std::env::set_var("NO_COLOR", "1");
+
   let root = PathBuf::from(format!(
     "{}{TESTS_PATH}{}",
     env!("CARGO_MANIFEST_DIR"),
@@ -100,7 +108,7 @@ fn compile_term() {
     term.check_unbound_vars(&mut HashMap::new(), &mut vec);
 
     if !vec.is_empty() {
-      return Err(vec.into_iter().map(|e| e.to_string_verbose(true)).join("\n").into());
+      return Err(vec.into_iter().map(|(e, _)| e.to_string_verbose(true)).join("\n").into());
     }
 
     term.make_var_names_unique();
@@ -180,6 +188,7 @@ fn run_file() {
           "-O=no-pre-reduce",
           "-L",
           "-1",
+          "--typed-nums",
         ])
         .output()
         .expect("Run process");
@@ -188,7 +197,7 @@ fn run_file() {
     }),
     (&|_code, path| {
       let output = std::process::Command::new(env!("CARGO_BIN_EXE_hvml"))
-        .args(["run", path.to_str().unwrap(), "-Dall", "-Oall"])
+        .args(["run", path.to_str().unwrap(), "-Dall", "-Oall", "--typed-nums"])
         .output()
         .expect("Run process");
 
@@ -230,7 +239,11 @@ fn readback_lnet() {
 #[test]
 fn simplify_matches() {
   run_golden_test_dir(function_name!(), &|code, path| {
-    let diagnostics_cfg = DiagnosticsConfig::new(Severity::Error, true);
+    // Some of the tests check how redundant rules are compiled, so they can't be errors.
+    let diagnostics_cfg = DiagnosticsConfig {
+      redundant_match: Severity::Warning,
+      ..DiagnosticsConfig::new(Severity::Error, true)
+    };
     let mut book = do_parse_book(code, path)?;
     let mut ctx = Ctx::new(&mut book, diagnostics_cfg);
 
@@ -371,8 +384,11 @@ fn cli() {
     args_file.read_to_string(&mut args_buf).expect("Read args");
     let args = args_buf.lines();
 
-    let output =
-      std::process::Command::new(env!("CARGO_BIN_EXE_hvml")).args(args).output().expect("Run command");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_hvml"))
+      .args(args)
+      .env("HVML_PATH", "tests/golden_tests/cli/lib")
+      .output()
+      .expect("Run command");
 
     Ok(format_output(output))
   })
@@ -391,18 +407,26 @@ fn mutual_recursion() {
   })
 }
 
+/// The io tests write their files in a temporary directory, written as `{tmp}` in their paths,
+/// so that the test files are never modified.
+fn with_tmp_dir(code: &str) -> String {
+  let tmp_dir = std::env::temp_dir().join("hvml_io_tests");
+  std::fs::create_dir_all(&tmp_dir).unwrap();
+  code.replace("{tmp}", &tmp_dir.display().to_string().replace('\\', "\\\\"))
+}
+
 #[test]
 fn io() {
   run_golden_test_dir_multiple(function_name!(), &[
     (&|code, path| {
-      let book = do_parse_book(code, path)?;
+      let book = do_parse_book(&with_tmp_dir(code), path)?;
       let compile_opts = CompileOpts::default_lazy();
       let diagnostics_cfg = DiagnosticsConfig::default_lazy();
       let (res, info) = run_book(book, None, RunOpts::lazy(), compile_opts, diagnostics_cfg, None)?;
       Ok(format!("Lazy mode:\n{}{}", info.diagnostics, res))
     }),
     (&|code, path| {
-      let book = do_parse_book(code, path)?;
+      let book = do_parse_book(&with_tmp_dir(code), path)?;
       let compile_opts = CompileOpts::default_strict();
       let diagnostics_cfg = DiagnosticsConfig::default_strict();
       let (res, info) = run_book(book, None, RunOpts::default(), compile_opts, diagnostics_cfg, None)?;
@@ -411,6 +435,157 @@ fn io() {
   ])
 }
 
+#[test]
+fn host_fns() {
+  fn host_fns() -> HostFns {
+    let mut host_fns = HostFns::default();
+    host_fns
+      .register("DB.lookup", 1, |args| match &args[0] {
+        Term::Str { val, .. } if &**val == "alice" => Term::encode_ok(Term::from(30u64)),
+        _ => Term::encode_err(Term::from("Not found")),
+      })
+      .unwrap();
+    host_fns
+      .register("Str.repeat", 2, |args| match (&args[0], &args[1]) {
+        (Term::Str { val, .. }, Term::Num { val: times, .. }) => Term::from(val.repeat(*times as usize)),
+        _ => Term::era(),
+      })
+      .unwrap();
+    host_fns
+  }
+
+  assert!(HostFns::default().register("Zero", 0, |_| Term::era()).is_err());
+
+  run_golden_test_dir_multiple(function_name!(), &[
+    (&|code, path| {
+      let book = do_parse_book(code, path)?;
+      let compile_opts = CompileOpts::default_lazy();
+      let diagnostics_cfg = DiagnosticsConfig::default_lazy();
+      let run_opts = RunOpts { host_fns: host_fns(), ..RunOpts::lazy() };
+      let (res, info) = run_book(book, None, run_opts, compile_opts, diagnostics_cfg, None)?;
+      Ok(format!("Lazy mode:\n{}{}", info.diagnostics, res))
+    }),
+    (&|code, path| {
+      let book = do_parse_book(code, path)?;
+      let compile_opts = CompileOpts::default_strict();
+      let diagnostics_cfg = DiagnosticsConfig::default_strict();
+      let run_opts = RunOpts { host_fns: host_fns(), ..RunOpts::default() };
+      let (res, info) = run_book(book, None, run_opts, compile_opts, diagnostics_cfg, None)?;
+      Ok(format!("Strict mode:\n{}{}", info.diagnostics, res))
+    }),
+  ])
+}
+
+#[test]
+fn repl() {
+  run_golden_test_dir(function_name!(), &|code, _| {
+    let compile_opts = CompileOpts::default_strict();
+    let diagnostics_cfg = DiagnosticsConfig::default_strict();
+    let mut repl = Repl::new(Book::builtins(), compile_opts, diagnostics_cfg, RunOpts::default());
+
+    let mut lines = code.lines().map(|line| Ok(line.to_string()));
+    let mut transcript = String::new();
+    while let Some(entry) = read_entry(&mut lines, &mut std::io::sink()).unwrap() {
+      for (i, line) in entry.lines().enumerate() {
+        writeln!(transcript, "{} {line}", if i == 0 { ">" } else { "|" }).unwrap();
+      }
+      transcript.push_str(&repl.eval(&entry).unwrap_or_else(|err| err.to_string()));
+    }
+    Ok(transcript)
+  })
+}
+
+#[test]
+fn format_file() {
+  run_golden_test_dir(function_name!(), &|code, _| {
+    let formatted = format_code(code)?;
+    let reformatted = format_code(&formatted)?;
+    if reformatted != formatted {
+      return Err(format!("Formatting is not idempotent, formatting again gives:\n{reformatted}").into());
+    }
+    Ok(formatted)
+  })
+}
+
+#[test]
+fn test_file() {
+  run_golden_test_dir(function_name!(), &|code, path| {
+    let book = do_parse_book(code, path)?;
+    let run_opts = RunOpts { single_core: true, max_rewrites: Some(10_000), ..RunOpts::default() };
+    let diagnostics_cfg =
+      DiagnosticsConfig { recursion_cycle: Severity::Allow, ..DiagnosticsConfig::default_strict() };
+    let results = run_tests(&book, None, run_opts, CompileOpts::default_strict(), diagnostics_cfg);
+    Ok(results.iter().map(|result| format!("test {} ... {}", result.name, result.outcome)).join("\n"))
+  })
+}
+
+#[test]
+fn program_call() {
+  run_golden_test_dir(function_name!(), &|code, path| {
+    let book = do_parse_book(code, path)?;
+    let diagnostics_cfg =
+      DiagnosticsConfig { recursion_cycle: Severity::Allow, ..DiagnosticsConfig::default_strict() };
+    let run_opts = RunOpts { single_core: true, ..RunOpts::default() };
+    let mut program = Program::new(book, CompileOpts::default_strict(), diagnostics_cfg, run_opts)?;
+
+    let mut result = String::new();
+    let sum: u64 = program.call_as("sum", vec![Term::from(vec![1u64, 2, 3])])?;
+    writeln!(result, "sum [1, 2, 3] = {sum}").unwrap();
+    let sum: u64 = program.call_as("sum", vec![Term::from(vec![10u64, 20])])?;
+    writeln!(result, "sum [10, 20] = {sum}").unwrap();
+    let doubled: Vec<u64> = program.call_as("double", vec![Term::from(vec![1u64, 2])])?;
+    writeln!(result, "double [1, 2] = {doubled:?}").unwrap();
+    let greeting: String = program.call_as("greet", vec![Term::from("world")])?;
+    writeln!(result, "greet \"world\" = {greeting:?}").unwrap();
+    let (wrapped, _) = program.call("wrap", vec![Term::from(5u64)])?;
+    writeln!(result, "wrap 5 = {wrapped}").unwrap();
+    let (_, info) = program.call("stop", vec![Term::from(3u64)])?;
+    writeln!(result, "stop 3 exited with {:?}", info.exit_code).unwrap();
+    let sum: u64 = program.call_as("sum", vec![Term::from(vec![1u64])])?;
+    writeln!(result, "sum [1] = {sum}").unwrap();
+    let first = TermParser::new_term("match l = [4, 5] { List.cons: [l.head]; List.nil: [] }")?;
+    let sum: u64 = program.call_as("sum", vec![first])?;
+    writeln!(result, "sum (first [4, 5]) = {sum}").unwrap();
+    let missing = program.call("missing", vec![]).err().unwrap();
+    write!(result, "missing:\n{missing}").unwrap();
+    let limited = RunOpts { single_core: false, max_rewrites: Some(1_000), ..RunOpts::default() };
+    program.set_run_opts(limited)?;
+    let sum: u64 = program.call_as("sum", vec![Term::from(vec![1u64, 2])])?;
+    writeln!(result, "sum [1, 2] with a rewrite limit = {sum}").unwrap();
+    let lazy_limited = RunOpts { max_rewrites: Some(1_000), ..RunOpts::lazy() };
+    let lazy_limited = program.set_run_opts(lazy_limited).err().unwrap();
+    write!(result, "lazy with a rewrite limit:\n{lazy_limited}").unwrap();
+    Ok(result)
+  })
+}
+
+#[test]
+#[cfg(feature = "lsp")]
+fn lsp() {
+  use hvml::lsp::analysis::Analysis;
+
+  run_golden_test_dir(function_name!(), &|code, path| {
+    let analysis = Analysis::new(code, path);
+    let line_col = |offset: usize| {
+      let line_start = code[.. offset].rfind('\n').map_or(0, |i| i + 1);
+      format!("{}:{}", code[.. offset].matches('\n').count() + 1, offset - line_start + 1)
+    };
+
+    let mut result = String::from("Occurrences:\n");
+    for occ in &analysis.occurrences {
+      let kind = format!("{:?}", occ.kind).to_lowercase();
+      let declaration = if occ.is_declaration { " (declaration)" } else { "" };
+      writeln!(result, "{} {kind} {}{declaration}", line_col(occ.ini), occ.name).unwrap();
+    }
+    result.push_str("Diagnostics:\n");
+    for diag in &analysis.diagnostics {
+      writeln!(result, "{}-{} {:?}: {}", line_col(diag.ini), line_col(diag.end), diag.severity, diag.message)
+        .unwrap();
+    }
+    Ok(result)
+  })
+}
+
 #[test]
 fn examples() -> Result<(), Diagnostics> {
   let examples_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples");